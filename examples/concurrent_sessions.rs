@@ -0,0 +1,174 @@
+//! 多线程 session 并发查询的性能测试
+//!
+//! 每个线程使用单独的 session 执行只读查询, 对比两种存储引擎:
+//! - serialized: 用 Serialized 包装磁盘存储引擎, 每次 get 和 scan 都要先拿到同一把 Mutex,
+//!   扫描时一直持有到迭代器释放, 和之前 Mvcc 使用 Mutex<E> 时对存储引擎的加锁方式一致
+//! - concurrent: 直接使用磁盘存储引擎, 读操作只需要 Mvcc 的读锁, 可以并行
+//!
+//! 两种方式执行的 SQL 和事务流程完全相同, 差别只在存储引擎的读操作是否互斥
+//!
+//! 运行: cargo run --release --example concurrent_sessions
+
+use std::{
+    ops::RangeBounds,
+    sync::{Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant},
+};
+
+use sqldb_rs_fzr::{
+    error::Result,
+    sql::{
+        engine::{kv::KVEngine, Engine},
+        executor::ResultSet,
+    },
+    storage::{
+        disk::DiskEngine,
+        engine::{Engine as StorageEngine, EngineIterator, Status, WriteBatch},
+    },
+};
+
+const ROWS: usize = 1000;
+const QUERIES_PER_THREAD: usize = 200;
+
+/**
+ * 模拟之前只有一个 Mutex 的存储引擎, 同一时刻只有一个操作可以访问存储引擎
+ * 写操作需要 &mut self, 本身已经是互斥的, 只需要给读操作加锁
+ */
+struct Serialized<E: StorageEngine> {
+    inner: E,
+    lock: Mutex<()>,
+}
+
+impl<E: StorageEngine> Serialized<E> {
+    fn new(inner: E) -> Self {
+        Self {
+            inner,
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl<E: StorageEngine> StorageEngine for Serialized<E> {
+    type EngineIterator<'a>
+        = SerializedIterator<'a, E::EngineIterator<'a>>
+    where
+        E: 'a;
+
+    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+        self.inner.set(key, value)
+    }
+
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let _guard = self.lock.lock()?;
+        self.inner.get(key)
+    }
+
+    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+        self.inner.delete(key)
+    }
+
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        self.inner.write_batch(batch)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.inner.sync()
+    }
+
+    fn status(&self) -> Result<Status> {
+        let _guard = self.lock.lock()?;
+        self.inner.status()
+    }
+
+    //扫描期间一直持有锁, 直到迭代器被释放
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        let guard = self.lock.lock().unwrap();
+        SerializedIterator {
+            inner: self.inner.scan(range),
+            _guard: guard,
+        }
+    }
+}
+
+struct SerializedIterator<'a, I> {
+    inner: I,
+    _guard: MutexGuard<'a, ()>,
+}
+
+impl<I: EngineIterator> Iterator for SerializedIterator<'_, I> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<I: EngineIterator> DoubleEndedIterator for SerializedIterator<'_, I> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<I: EngineIterator> EngineIterator for SerializedIterator<'_, I> {}
+
+fn load<E: StorageEngine + 'static>(engine: &KVEngine<E>) -> Result<()> {
+    let mut session = engine.session()?;
+    session.execute("create table bench (a int primary key, b int, c varchar);")?;
+    for i in 0..ROWS {
+        session.execute(&format!("insert into bench values ({}, {}, 'row-{}');", i, i, i))?;
+    }
+    Ok(())
+}
+
+fn run<E: StorageEngine + Send + Sync + 'static>(engine: &KVEngine<E>, threads: usize) -> Duration {
+    let start = Instant::now();
+    let handles = (0..threads)
+        .map(|t| {
+            let engine = engine.clone();
+            thread::spawn(move || -> Result<()> {
+                let mut session = engine.session()?;
+                for i in 0..QUERIES_PER_THREAD {
+                    let sql = format!(
+                        "select * from bench where b = {};",
+                        (t * QUERIES_PER_THREAD + i) % ROWS
+                    );
+                    //查询结果在读取时才从存储中拉取
+                    if let ResultSet::Scan { rows, .. } = session.execute(&sql)? {
+                        rows.collect::<Result<Vec<_>>>()?;
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.join().unwrap().unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let serialized_engine = KVEngine::new(Serialized::new(DiskEngine::new(
+        dir.path().join("serialized-log"),
+    )?));
+    let concurrent_engine = KVEngine::new(DiskEngine::new(dir.path().join("concurrent-log"))?);
+    load(&serialized_engine)?;
+    load(&concurrent_engine)?;
+
+    println!("{:>8} {:>16} {:>16} {:>8}", "threads", "serialized q/s", "concurrent q/s", "speedup");
+    for threads in [1, 2, 4, 8] {
+        let queries = (threads * QUERIES_PER_THREAD) as f64;
+        let serialized = run(&serialized_engine, threads);
+        let concurrent = run(&concurrent_engine, threads);
+        println!(
+            "{:>8} {:>16.0} {:>16.0} {:>7.2}x",
+            threads,
+            queries / serialized.as_secs_f64(),
+            queries / concurrent.as_secs_f64(),
+            serialized.as_secs_f64() / concurrent.as_secs_f64()
+        );
+    }
+    Ok(())
+}
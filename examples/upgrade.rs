@@ -0,0 +1,27 @@
+//! 离线升级旧格式的磁盘日志
+//!
+//! 升级之前需要停止使用这份数据的服务, 原来的数据保留为 <path>.old
+//!
+//! 运行: cargo run --example upgrade -- <path>
+
+use std::{env, path::PathBuf, process};
+
+use sqldb_rs_fzr::storage::disk::DiskEngine;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => PathBuf::from(path),
+        None => {
+            eprintln!("usage: upgrade <path>");
+            process::exit(2);
+        }
+    };
+    match DiskEngine::upgrade(path.clone()) {
+        Ok(true) => println!("{} upgraded", path.display()),
+        Ok(false) => println!("{} is already in the current format", path.display()),
+        Err(err) => {
+            eprintln!("upgrade failed: {}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -16,6 +16,16 @@ pub enum Error {
     Parse(String),
     Internal(String),
     WriteConflict,
+    //主键重复
+    DuplicateKey(String),
+    //唯一索引中的值重复
+    UniqueViolation(String),
+    //只读事务中执行写操作
+    ReadOnly,
+    //可串行化事务提交时, 读取过的数据被并发的事务修改
+    Serialization,
+    //磁盘日志损坏, 记录损坏的文件以及偏移量
+    Corruption(String, u64),
 }
 
 impl From<ParseFloatError> for Error {
@@ -74,6 +84,13 @@ impl Display for Error {
             Error::Parse(err) => write!(f, "parse error {}", err),
             Error::Internal(err) => write!(f, "internal error {}", err),
             Error::WriteConflict => write!(f, "write conflict,try transaction"),
+            Error::DuplicateKey(key) => write!(f, "duplicate primary key {}", key),
+            Error::UniqueViolation(value) => write!(f, "duplicate value {}", value),
+            Error::ReadOnly => write!(f, "can not write in a read-only transaction"),
+            Error::Serialization => write!(f, "serialization failure,try transaction"),
+            Error::Corruption(file, offset) => {
+                write!(f, "log file {} corrupted at offset {}", file, offset)
+            }
         }
     }
 }
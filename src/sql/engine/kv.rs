@@ -1,12 +1,21 @@
+use std::ops::Bound;
+
 use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{Error, Result},
-    sql::types::{Row, Value},
-    storage::{self, engine::Engine as StorageEngin},
+    sql::{
+        schema::{AlterTable, Index, Table},
+        types::{Row, Value},
+    },
+    storage::{
+        self,
+        engine::{prefix_end, Engine as StorageEngin},
+        keycode::{deserialize_key, serialize_key},
+    },
 };
 
-use super::{Engine, Transaction};
+use super::{Engine, ScanIterator, Transaction};
 
 pub struct KVEngine<E: StorageEngin> {
     pub kv: storage::mvcc::Mvcc<E>,
@@ -28,12 +37,32 @@ impl<E: StorageEngin> KVEngine<E> {
     }
 }
 
-impl<E: StorageEngin> Engine for KVEngine<E> {
+impl<E: StorageEngin + 'static> Engine for KVEngine<E> {
     type Transaction = KVTransaction<E>;
 
     fn begin(&self) -> Result<Self::Transaction> {
         Ok(Self::Transaction::new(self.kv.begin()?))
     }
+
+    fn begin_read_only(&self) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_read_only()?))
+    }
+
+    fn begin_serializable(&self) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_serializable()?))
+    }
+
+    fn begin_as_of(&self, version: u64) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_as_of(version)?))
+    }
+
+    fn current_version(&self) -> Result<u64> {
+        self.kv.current_version()
+    }
+
+    fn vacuum(&self) -> Result<usize> {
+        self.kv.vacuum()
+    }
 }
 
 //KVTransaction 定义,实际上对存储引擎MvccTransaction的封装
@@ -47,7 +76,85 @@ impl<E: StorageEngin> KVTransaction<E> {
     }
 }
 
-impl<E: StorageEngin> Transaction for KVTransaction<E> {
+impl<E: StorageEngin + 'static> KVTransaction<E> {
+    //保存表结构
+    fn save_table(&mut self, table: &Table) -> Result<()> {
+        let key = Key::Table(table.name.clone()).encode()?;
+        let value = bincode::serialize(table)?;
+        self.txn.set(key, value)
+    }
+
+    //删除前缀下所有的数据, 返回删除的数量
+    fn delete_prefix(&mut self, prefix: KeyPrefix) -> Result<usize> {
+        let results = self.txn.scan_prefix(prefix.encode()?)?;
+        let count = results.len();
+        for result in results {
+            self.txn.delete(result.key)?;
+        }
+        Ok(count)
+    }
+
+    //修改表结构时重写表中所有的行
+    fn rewrite_rows<F: Fn(&mut Row)>(&mut self, table_name: &str, f: F) -> Result<()> {
+        let prefix = KeyPrefix::Row(table_name.to_string()).encode()?;
+        for result in self.txn.scan_prefix(prefix)? {
+            let mut row: Row = bincode::deserialize(&result.value)?;
+            f(&mut row);
+            self.txn.set(result.key, bincode::serialize(&row)?)?;
+        }
+        Ok(())
+    }
+
+    //写入索引数据, 唯一索引需要检查值是否已经存在, NULL 值不参与唯一性检查
+    fn create_index_entry(
+        &mut self,
+        table: &Table,
+        index: &Index,
+        value: &Value,
+        pk: &Value,
+    ) -> Result<()> {
+        let key = Self::index_key(table, index, value, pk)?;
+        if !Self::is_unique_entry(index, value) {
+            return self.txn.set(key, vec![]);
+        }
+        if self.txn.get(key.clone())?.is_some() {
+            return Err(Error::UniqueViolation(format!(
+                "{} in unique index {}",
+                value, index.name
+            )));
+        }
+        self.txn.set(key, bincode::serialize(pk)?)
+    }
+
+    fn delete_index_entry(
+        &mut self,
+        table: &Table,
+        index: &Index,
+        value: &Value,
+        pk: &Value,
+    ) -> Result<()> {
+        self.txn.delete(Self::index_key(table, index, value, pk)?)
+    }
+
+    /**
+     * 索引数据的 key
+     * 唯一索引中非 NULL 的值只对应一行, key 中的主键固定为 NULL, 实际的主键保存在 value 中
+     * 并发的事务写入相同的值时写的是同一个 key, 提交之前就会发生写冲突
+     */
+    fn index_key(table: &Table, index: &Index, value: &Value, pk: &Value) -> Result<Vec<u8>> {
+        let pk = match Self::is_unique_entry(index, value) {
+            true => Value::Null,
+            false => pk.clone(),
+        };
+        Key::Index(table.name.clone(), index.column_name.clone(), value.clone(), pk).encode()
+    }
+
+    fn is_unique_entry(index: &Index, value: &Value) -> bool {
+        index.unique && *value != Value::Null
+    }
+}
+
+impl<E: StorageEngin + 'static> Transaction for KVTransaction<E> {
     fn commit(&self) -> Result<()> {
         self.txn.commit()
     }
@@ -56,49 +163,143 @@ impl<E: StorageEngin> Transaction for KVTransaction<E> {
         self.txn.rollback()
     }
 
+    fn version(&self) -> u64 {
+        self.txn.version()
+    }
+
+    fn savepoint(&mut self, name: String) -> Result<()> {
+        self.txn.savepoint(name)
+    }
+
+    fn rollback_to_savepoint(&mut self, name: &str) -> Result<()> {
+        self.txn.rollback_to_savepoint(name)
+    }
+
+    fn release_savepoint(&mut self, name: &str) -> Result<()> {
+        self.txn.release_savepoint(name)
+    }
+
     fn create_row(&mut self, table_name: String, row: Row) -> Result<()> {
         let table = self.must_get_table(table_name.clone())?;
 
         //校验行可靠性
-        for (i, col) in table.columns.iter().enumerate() {
-            match row[i].datatype() {
-                None if col.nullable => {}
-                None => {
-                    return Err(Error::Internal(format!(
-                        "column {} can not be null",
-                        col.name
-                    )))
-                }
-                Some(datatype) if datatype != col.datatype => {
-                    return Err(Error::Internal(format!(
-                        "column {} type mismatch",
-                        col.name
-                    )))
-                }
-                _ => {}
-            }
+        table.validate_row(&row)?;
+
+        //主键不能重复
+        let pk = table.get_primary_key(&row)?;
+        if self.read_by_id(table_name.clone(), &pk)?.is_some() {
+            return Err(Error::DuplicateKey(format!("{} in table {}", pk, table_name)));
         }
 
         //存放数据
-        //暂时以第一列作为主键, 一行的唯一标识
-        let id = Key::Row(table_name.clone(), row[0].clone());
+        let id = Key::Row(table_name.clone(), pk.clone()).encode()?;
         let value = bincode::serialize(&row)?;
-        self.txn.set(bincode::serialize(&id)?, value)?;
+        self.txn.set(id, value)?;
+
+        //维护索引数据
+        for index in &table.indexes {
+            let value = &row[table.get_column_index(&index.column_name)?];
+            self.create_index_entry(&table, index, value, &pk)?;
+        }
         Ok(())
     }
 
-    fn scan_table(&self, table_name: String) -> Result<Vec<Row>> {
-        let prefix = KeyPrefix::Row(table_name);
-        let results = self.txn.scan_prefix(bincode::serialize(&prefix)?)?;
-        let mut rows = Vec::new();
-        for result in results {
-            let row = bincode::deserialize(&result.value)?;
-            rows.push(row);
+    fn update_row(&mut self, table: &Table, id: &Value, row: Row) -> Result<()> {
+        //主键发生变化, 删除原来的数据再重新写入
+        let new_id = table.get_primary_key(&row)?;
+        if new_id != *id {
+            self.delete_row(table, id)?;
+            return self.create_row(table.name.clone(), row);
         }
-        Ok(rows)
+
+        table.validate_row(&row)?;
+        let old_row = self
+            .read_by_id(table.name.clone(), id)?
+            .ok_or(Error::Internal(format!(
+                "row {} does not exists in table {}",
+                id, table.name
+            )))?;
+
+        //只更新值发生变化的索引
+        for index in &table.indexes {
+            let i = table.get_column_index(&index.column_name)?;
+            if old_row[i] != row[i] {
+                self.delete_index_entry(table, index, &old_row[i], id)?;
+                self.create_index_entry(table, index, &row[i], id)?;
+            }
+        }
+
+        let key = Key::Row(table.name.clone(), new_id).encode()?;
+        let value = bincode::serialize(&row)?;
+        self.txn.set(key, value)
+    }
+
+    fn delete_row(&mut self, table: &Table, id: &Value) -> Result<()> {
+        //删除索引数据
+        if !table.indexes.is_empty() {
+            if let Some(row) = self.read_by_id(table.name.clone(), id)? {
+                for index in &table.indexes {
+                    let value = &row[table.get_column_index(&index.column_name)?];
+                    self.delete_index_entry(table, index, value, id)?;
+                }
+            }
+        }
+
+        let key = Key::Row(table.name.clone(), id.clone()).encode()?;
+        self.txn.delete(key)
     }
 
-    fn create_table(&mut self, table: crate::sql::schema::Table) -> Result<()> {
+    fn read_by_id(&self, table_name: String, id: &Value) -> Result<Option<Row>> {
+        let key = Key::Row(table_name, id.clone()).encode()?;
+        Ok(self
+            .txn
+            .get(key)?
+            .map(|it| bincode::deserialize(&it))
+            .transpose()?)
+    }
+
+    fn scan_table(&self, table_name: String) -> Result<ScanIterator> {
+        //行数据的 key 按照主键的大小顺序编码, 扫描结果按主键有序
+        let prefix = KeyPrefix::Row(table_name).encode()?;
+        let iter = self.txn.scan_prefix_iter(prefix)?;
+        Ok(Box::new(iter.map(|result| Ok(bincode::deserialize(&result?.value)?))))
+    }
+
+    fn scan_table_range(
+        &self,
+        table_name: String,
+        start: Bound<Value>,
+        end: Bound<Value>,
+    ) -> Result<ScanIterator> {
+        //主键的范围对应行数据 key 的范围, 没有边界时使用表的前缀
+        let prefix = KeyPrefix::Row(table_name.clone()).encode()?;
+        let key = |value: Value| Key::Row(table_name.clone(), value).encode();
+        let start = match start {
+            Bound::Included(value) => Bound::Included(key(value)?),
+            Bound::Excluded(value) => Bound::Excluded(key(value)?),
+            Bound::Unbounded => Bound::Included(prefix.clone()),
+        };
+        let end = match end {
+            Bound::Included(value) => Bound::Included(key(value)?),
+            Bound::Excluded(value) => Bound::Excluded(key(value)?),
+            Bound::Unbounded => prefix_end(&prefix),
+        };
+        //范围为空时不需要扫描
+        let empty = match (&start, &end) {
+            (Bound::Included(s), Bound::Included(e)) => s > e,
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => {
+                s >= e
+            }
+            _ => false,
+        };
+        if empty {
+            return Ok(Box::new(std::iter::empty()));
+        }
+        let iter = self.txn.scan((start, end))?;
+        Ok(Box::new(iter.map(|result| Ok(bincode::deserialize(&result?.value)?))))
+    }
+
+    fn create_table(&mut self, table: Table) -> Result<()> {
         //判断表是否已经存在
         if self.get_table(table.name.clone())?.is_some() {
             return Err(Error::Internal(format!(
@@ -108,47 +309,288 @@ impl<E: StorageEngin> Transaction for KVTransaction<E> {
         }
 
         //判断表的有效性
-        if table.columns.is_empty() {
+        table.validate()?;
+
+        self.save_table(&table)
+    }
+
+    fn drop_table(&mut self, table_name: String) -> Result<()> {
+        let table = self.must_get_table(table_name.clone())?;
+        self.truncate_table(table_name.clone())?;
+        self.txn.delete(Key::Table(table.name).encode()?)
+    }
+
+    fn truncate_table(&mut self, table_name: String) -> Result<usize> {
+        let table = self.must_get_table(table_name.clone())?;
+        for index in &table.indexes {
+            self.delete_prefix(KeyPrefix::Index(table_name.clone(), index.column_name.clone()))?;
+        }
+        self.delete_prefix(KeyPrefix::Row(table_name))
+    }
+
+    fn alter_table(&mut self, table_name: String, operation: AlterTable) -> Result<()> {
+        let mut table = self.must_get_table(table_name.clone())?;
+        match operation {
+            AlterTable::AddColumn(column) => {
+                if table.columns.iter().any(|it| it.name == column.name) {
+                    return Err(Error::Internal(format!(
+                        "column {} has already exists",
+                        column.name
+                    )));
+                }
+                if column.primary_key {
+                    return Err(Error::Internal(format!(
+                        "can not add primary key column {}",
+                        column.name
+                    )));
+                }
+                //已有的行使用默认值填充新的列
+                let value = match &column.default {
+                    Some(value) => value.clone(),
+                    None if column.nullable => Value::Null,
+                    None => {
+                        return Err(Error::Internal(format!(
+                            "column {} must have a default value",
+                            column.name
+                        )))
+                    }
+                };
+                if value.datatype().is_some_and(|it| it != column.datatype) {
+                    return Err(Error::Internal(format!(
+                        "default value of column {} type mismatch",
+                        column.name
+                    )));
+                }
+                self.rewrite_rows(&table_name, |row| row.push(value.clone()))?;
+                table.columns.push(column);
+            }
+            AlterTable::DropColumn(column_name) => {
+                let i = table.get_column_index(&column_name)?;
+                if table.columns[i].primary_key {
+                    return Err(Error::Internal(format!(
+                        "can not drop primary key column {}",
+                        column_name
+                    )));
+                }
+                //列上的索引一起删除
+                if table.get_index_by_column(&column_name).is_some() {
+                    self.delete_prefix(KeyPrefix::Index(table_name.clone(), column_name.clone()))?;
+                    table.indexes.retain(|it| it.column_name != column_name);
+                }
+                self.rewrite_rows(&table_name, |row| {
+                    row.remove(i);
+                })?;
+                table.columns.remove(i);
+            }
+            AlterTable::RenameColumn(old_name, new_name) => {
+                let i = table.get_column_index(&old_name)?;
+                if table.columns.iter().any(|it| it.name == new_name) {
+                    return Err(Error::Internal(format!(
+                        "column {} has already exists",
+                        new_name
+                    )));
+                }
+                //索引数据的 key 中包含列名, 需要迁移到新的列名下
+                if let Some(index) = table.indexes.iter_mut().find(|it| it.column_name == old_name) {
+                    let prefix = KeyPrefix::Index(table_name.clone(), old_name).encode()?;
+                    for result in self.txn.scan_prefix(prefix)? {
+                        if let Key::Index(t, _, value, pk) = Key::decode(&result.key)? {
+                            let key = Key::Index(t, new_name.clone(), value, pk).encode()?;
+                            self.txn.set(key, result.value)?;
+                        }
+                        self.txn.delete(result.key)?;
+                    }
+                    index.column_name = new_name.clone();
+                }
+                table.columns[i].name = new_name;
+            }
+            AlterTable::RenameTable(new_name) => {
+                if self.get_table(new_name.clone())?.is_some() {
+                    return Err(Error::Internal(format!(
+                        "table {} has already exists",
+                        new_name
+                    )));
+                }
+                //行数据和索引数据的 key 中都包含表名, 全部迁移到新的表名下
+                let mut prefixes = vec![KeyPrefix::Row(table_name.clone())];
+                for index in &table.indexes {
+                    prefixes.push(KeyPrefix::Index(table_name.clone(), index.column_name.clone()));
+                }
+                for prefix in prefixes {
+                    for result in self.txn.scan_prefix(prefix.encode()?)? {
+                        let key = match Key::decode(&result.key)? {
+                            Key::Row(_, pk) => Key::Row(new_name.clone(), pk),
+                            Key::Index(_, column, value, pk) => {
+                                Key::Index(new_name.clone(), column, value, pk)
+                            }
+                            key => return Err(Error::Internal(format!("unexpected key {:?}", key))),
+                        };
+                        self.txn.set(key.encode()?, result.value)?;
+                        self.txn.delete(result.key)?;
+                    }
+                }
+                self.txn.delete(Key::Table(table_name).encode()?)?;
+                table.name = new_name;
+            }
+        }
+        table.validate()?;
+        self.save_table(&table)
+    }
+
+    fn scan_tables(&self) -> Result<Vec<Table>> {
+        let prefix = KeyPrefix::Table.encode()?;
+        let results = self.txn.scan_prefix(prefix)?;
+        let mut tables = Vec::new();
+        for result in results {
+            tables.push(bincode::deserialize(&result.value)?);
+        }
+        Ok(tables)
+    }
+
+    fn create_index(&mut self, table_name: String, index: Index) -> Result<()> {
+        let mut table = self.must_get_table(table_name.clone())?;
+        table.get_column_index(&index.column_name)?;
+
+        //索引名在整个库中唯一
+        for t in self.scan_tables()? {
+            if t.indexes.iter().any(|it| it.name == index.name) {
+                return Err(Error::Internal(format!(
+                    "index {} has already exists",
+                    index.name
+                )));
+            }
+        }
+        //同一列上只能有一个索引
+        if let Some(existing) = table.get_index_by_column(&index.column_name) {
             return Err(Error::Internal(format!(
-                "table {} has no columns",
-                table.name
+                "column {} has already been indexed by {}",
+                index.column_name, existing.name
             )));
         }
 
-        let key = Key::Table(table.name.clone());
-        let value = bincode::serialize(&table)?;
+        //为已有的数据建立索引
+        let i = table.get_column_index(&index.column_name)?;
+        let rows = self.scan_table(table_name)?.collect::<Result<Vec<_>>>()?;
+        for row in rows {
+            let pk = table.get_primary_key(&row)?;
+            self.create_index_entry(&table, &index, &row[i], &pk)?;
+        }
+
+        table.indexes.push(index);
+        self.save_table(&table)
+    }
+
+    fn drop_index(&mut self, index_name: String) -> Result<()> {
+        let mut table = self
+            .scan_tables()?
+            .into_iter()
+            .find(|it| it.indexes.iter().any(|index| index.name == index_name))
+            .ok_or(Error::Internal(format!(
+                "index {} does not exists",
+                index_name
+            )))?;
 
-        self.txn.set(bincode::serialize(&key)?, value)
+        let position = table
+            .indexes
+            .iter()
+            .position(|it| it.name == index_name)
+            .unwrap_or_default();
+        let index = table.indexes.remove(position);
+
+        //删除所有的索引数据
+        let prefix = KeyPrefix::Index(table.name.clone(), index.column_name).encode()?;
+        for result in self.txn.scan_prefix(prefix)? {
+            self.txn.delete(result.key)?;
+        }
+
+        self.save_table(&table)
     }
 
-    fn get_table(&self, table_name: String) -> Result<Option<crate::sql::schema::Table>> {
-        let key = Key::Table(table_name);
+    fn read_by_index(
+        &self,
+        table_name: String,
+        column_name: String,
+        value: &Value,
+    ) -> Result<Vec<Value>> {
+        let mut prefix = KeyPrefix::Index(table_name, column_name).encode()?;
+        prefix.extend(serialize_key(value)?);
+
+        let mut pks = Vec::new();
+        for result in self.txn.scan_prefix(prefix)? {
+            match Key::decode(&result.key)? {
+                //唯一索引的主键保存在 value 中
+                Key::Index(_, _, _, Value::Null) => pks.push(bincode::deserialize(&result.value)?),
+                Key::Index(_, _, _, pk) => pks.push(pk),
+                key => return Err(Error::Internal(format!("unexpected key {:?}", key))),
+            }
+        }
+        Ok(pks)
+    }
+
+    fn get_table(&self, table_name: String) -> Result<Option<Table>> {
+        let key = Key::Table(table_name).encode()?;
         let v = self
             .txn
-            .get(bincode::serialize(&key)?)?
+            .get(key)?
             .map(|it| bincode::deserialize(&it))
             .transpose()?;
         Ok(v)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+//使用 keycode 保序编码, 行数据按照 表名 + 主键 的顺序排列
+//索引数据按照 表名 + 列名 + 列值 + 主键 的顺序排列, 唯一索引中非 NULL 值的主键为 NULL
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 enum Key {
     Table(String),
     Row(String, Value),
+    Index(String, String, Value, Value),
+}
+
+impl Key {
+    fn encode(&self) -> Result<Vec<u8>> {
+        serialize_key(self)
+    }
+
+    fn decode(data: &[u8]) -> Result<Key> {
+        deserialize_key(data)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 enum KeyPrefix {
     Table,
     Row(String),
+    Index(String, String),
+}
+
+impl KeyPrefix {
+    fn encode(&self) -> Result<Vec<u8>> {
+        serialize_key(self)
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{error::Result, sql::engine::Engine, storage::memory::MemoryEngine};
+    use crate::{
+        error::{Error, Result},
+        sql::{
+            engine::{Engine, Session},
+            executor::ResultSet,
+            types::{Row, Value},
+        },
+        storage::memory::MemoryEngine,
+    };
+
+    use super::{KVEngine, Key, KeyPrefix};
 
-    use super::KVEngine;
+    //执行查询并读取所有的行, 表达式计算的错误在读取到对应的行时才返回
+    fn query<E: Engine>(s: &mut Session<E>, sql: &str) -> Result<Vec<Row>> {
+        match s.execute(sql)? {
+            ResultSet::Scan { rows, .. } => rows.collect(),
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
 
     #[test]
     fn test_create_table() -> Result<()> {
@@ -162,4 +604,1062 @@ mod tests {
         println!("{:?}", v1);
         Ok(())
     }
+
+    #[test]
+    fn test_select_where() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b text, c float);")?;
+        s.execute("insert into t1 values(1, 'a', 1.5), (2, 'b', null), (3, null, 3.5);")?;
+
+        let ids = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows.map(|it| it.unwrap()[0].clone()).collect(),
+            _ => vec![],
+        };
+
+        let v = s.execute("select * from t1 where a >= 2;")?;
+        assert_eq!(ids(v), vec![Value::Integer(2), Value::Integer(3)]);
+
+        let v = s.execute("select * from t1 where c > 1 and a * 2 <= 4;")?;
+        assert_eq!(ids(v), vec![Value::Integer(1)]);
+
+        //NULL 参与比较结果为 NULL, 不会被选中
+        let v = s.execute("select * from t1 where b != 'a';")?;
+        assert_eq!(ids(v), vec![Value::Integer(2)]);
+
+        let v = s.execute("select * from t1 where not (b = 'a') or c is null;")?;
+        assert_eq!(ids(v), vec![Value::Integer(2)]);
+
+        let v = s.execute("select * from t1 where b is not null and (c > 3 or a = 1);")?;
+        assert_eq!(ids(v), vec![Value::Integer(1)]);
+
+        assert!(query(&mut s, "select * from t1 where a + 'x' > 1;").is_err());
+        assert!(query(&mut s, "select * from t1 where d = 1;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_projection() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b int, c text);")?;
+        s.execute("insert into t1 values(1, 10, 'x'), (2, 20, null);")?;
+
+        match s.execute("select a, b + 1 as c, 'x', c is null from t1 where a > 1;")? {
+            ResultSet::Scan { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                assert_eq!(columns, vec!["a", "c", "'x'", "c IS NULL"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(2),
+                        Value::Integer(21),
+                        Value::String("x".to_string()),
+                        Value::Boolean(true)
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        match s.execute("select 1 + 2, 7 / 2 as d;")? {
+            ResultSet::Scan { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                assert_eq!(columns, vec!["1 + 2", "d"]);
+                assert_eq!(rows, vec![vec![Value::Integer(3), Value::Integer(3)]]);
+            }
+            _ => unreachable!(),
+        }
+
+        //整数除以 0 报错, 浮点数除以 0 得到无穷大或者 NaN
+        assert!(query(&mut s, "select 1 / 0;").is_err());
+        assert!(query(&mut s, "select 1 % 0;").is_err());
+        assert_eq!(
+            query(&mut s, "select 1.0 / 0, 1 / 0.0, -1.0 / 0;")?,
+            vec![vec![
+                Value::Float(f64::INFINITY),
+                Value::Float(f64::INFINITY),
+                Value::Float(f64::NEG_INFINITY)
+            ]]
+        );
+        match &query(&mut s, "select 0.0 / 0, 1.0 % 0;")?[0][..] {
+            [Value::Float(a), Value::Float(b)] => assert!(a.is_nan() && b.is_nan()),
+            row => panic!("unexpected row {:?}", row),
+        }
+
+        assert_eq!(
+            query(&mut s, "select -9223372036854775808, -9223372036854775807 - 1;")?,
+            vec![vec![Value::Integer(i64::MIN), Value::Integer(i64::MIN)]]
+        );
+        assert!(query(&mut s, "select 9223372036854775808;").is_err());
+
+        //列名中的浮点数常量保留小数点
+        match s.execute("select 5 / 0.0, 2.0 * 3;")? {
+            ResultSet::Scan { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                assert_eq!(columns, vec!["5 / 0.0", "2.0 * 3"]);
+                assert_eq!(rows, vec![vec![Value::Float(f64::INFINITY), Value::Float(6.0)]]);
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(query(&mut s, "select d from t1;").is_err());
+        assert!(query(&mut s, "select *;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_limit() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b int, c float);")?;
+        s.execute("insert into t1 values(1, 2, 1.5), (2, null, 3.0), (3, 1, null), (4, 2, 0.5);")?;
+
+        let ids = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows.map(|it| it.unwrap()[0].clone()).collect(),
+            _ => vec![],
+        };
+        let ints = |v: Vec<i64>| v.into_iter().map(Value::Integer).collect::<Vec<_>>();
+
+        //ASC 默认 NULL 在最后, DESC 默认 NULL 在最前
+        let v = s.execute("select * from t1 order by b, c desc;")?;
+        assert_eq!(ids(v), ints(vec![3, 1, 4, 2]));
+        let v = s.execute("select * from t1 order by b desc, a;")?;
+        assert_eq!(ids(v), ints(vec![2, 1, 4, 3]));
+        let v = s.execute("select * from t1 order by c nulls first;")?;
+        assert_eq!(ids(v), ints(vec![3, 4, 1, 2]));
+        let v = s.execute("select * from t1 order by c desc nulls last;")?;
+        assert_eq!(ids(v), ints(vec![2, 1, 4, 3]));
+
+        //可以按照别名和没有出现在 select 列表中的表达式排序
+        let v = s.execute("select a * -1 as x from t1 order by x limit 2;")?;
+        assert_eq!(ids(v), ints(vec![-4, -3]));
+        let v = s.execute("select a from t1 order by c * 2 desc limit 2 offset 1;")?;
+        assert_eq!(ids(v), ints(vec![2, 1]));
+
+        let v = s.execute("select * from t1 limit 10 offset 3;")?;
+        assert_eq!(ids(v), ints(vec![4]));
+        let v = s.execute("select * from t1 limit 0;")?;
+        assert_eq!(ids(v), ints(vec![]));
+
+        //LIMIT 拿够行数之后不再拉取, a = 3 的行不会被计算
+        let v = s.execute("select a / (a - 3) from t1 limit 2;")?;
+        assert_eq!(ids(v), ints(vec![0, -2]));
+        assert!(query(&mut s, "select a / (a - 3) from t1;").is_err());
+
+        assert!(query(&mut s, "select * from t1 limit -1;").is_err());
+        assert!(query(&mut s, "select * from t1 limit 'a';").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_aggregate() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b text, c int, d float);")?;
+
+        //没有数据时也会返回一行
+        match s.execute("select count(*), count(c), sum(c), avg(c), min(b) from t1;")? {
+            ResultSet::Scan { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                assert_eq!(columns, vec!["COUNT(*)", "COUNT(c)", "SUM(c)", "AVG(c)", "MIN(b)"]);
+                assert_eq!(
+                    rows,
+                    vec![vec![
+                        Value::Integer(0),
+                        Value::Integer(0),
+                        Value::Null,
+                        Value::Null,
+                        Value::Null
+                    ]]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        s.execute(
+            "insert into t1 values
+                (1, 'x', 1, 1.5), (2, 'y', 2, null), (3, 'x', null, 2.5),
+                (4, 'y', 4, 0.5), (5, 'z', null, null);",
+        )?;
+
+        match s.execute("select count(*), count(c), sum(c), avg(c), sum(d), min(b), max(d) from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows.collect::<Result<Vec<_>>>()?,
+                vec![vec![
+                    Value::Integer(5),
+                    Value::Integer(3),
+                    Value::Integer(7),
+                    Value::Float(7.0 / 3.0),
+                    Value::Float(4.5),
+                    Value::String("x".to_string()),
+                    Value::Float(2.5)
+                ]]
+            ),
+            _ => unreachable!(),
+        }
+
+        match s.execute(
+            "select b, count(*) as n, sum(c) + 1 from t1 where a > 1 group by b having count(*) > 1 or max(c) is null order by n desc, b;",
+        )? {
+            ResultSet::Scan { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                assert_eq!(columns, vec!["b", "n", "SUM(c) + 1"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::String("y".to_string()), Value::Integer(2), Value::Integer(7)],
+                        vec![Value::String("x".to_string()), Value::Integer(1), Value::Null],
+                        vec![Value::String("z".to_string()), Value::Integer(1), Value::Null],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        //按照表达式分组
+        match s.execute("select a % 2, count(*) from t1 group by a % 2 order by a % 2;")? {
+            ResultSet::Scan { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                assert_eq!(columns, vec!["a % 2", "COUNT(*)"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(0), Value::Integer(2)],
+                        vec![Value::Integer(1), Value::Integer(3)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(query(&mut s, "select a, count(*) from t1;").is_err());
+        assert!(query(&mut s, "select * from t1 group by b;").is_err());
+        assert!(query(&mut s, "select sum(b) from t1;").is_err());
+        assert!(query(&mut s, "select foo(a) from t1;").is_err());
+        assert!(query(&mut s, "select sum(count(a)) from t1;").is_err());
+        assert!(query(&mut s, "select a from t1 where count(*) > 1;").is_err());
+
+        //0.0 和 -0.0, 以及所有的 NaN 分在同一组
+        s.execute("create table t2 (a int primary key, b float);")?;
+        s.execute("insert into t2 values(1, 0.0), (2, -0.0), (3, 0.0 / 0.0), (4, -(0.0 / 0.0)), (5, 1.0);")?;
+        let counts = query(&mut s, "select count(*) from t2 group by b order by b;")?;
+        assert_eq!(
+            counts,
+            vec![vec![Value::Integer(2)], vec![Value::Integer(1)], vec![Value::Integer(2)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, name text);")?;
+        s.execute("create table orders (id int primary key, user_id int, amount float);")?;
+        s.execute("insert into users values(1, 'a'), (2, 'b'), (3, 'c');")?;
+        s.execute("insert into orders values(10, 1, 1.5), (11, 1, 2.5), (12, 2, 3.0), (13, null, 4.0), (14, 9, 5.0);")?;
+
+        let rows = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows.map(|it| it.unwrap()).collect(),
+            _ => vec![],
+        };
+        let int = |i: i64| Value::Integer(i);
+
+        //等值连接使用哈希连接, 其他条件使用嵌套循环连接, 结果应该一致
+        for sql in [
+            "select u.id, o.id from users u join orders o on u.id = o.user_id order by o.id;",
+            "select u.id, o.id from users as u inner join orders o on o.user_id = u.id order by o.id;",
+            "select u.id, o.id from users u join orders o on u.id = o.user_id and true order by o.id;",
+            "select u.id, o.id from users u, orders o where u.id = o.user_id order by o.id;",
+        ] {
+            assert_eq!(
+                rows(s.execute(sql)?),
+                vec![vec![int(1), int(10)], vec![int(1), int(11)], vec![int(2), int(12)]]
+            );
+        }
+
+        for on in ["u.id = o.user_id", "o.user_id = u.id and o.id > 0"] {
+            let v = s.execute(&format!(
+                "select u.id, o.id from users u left join orders o on {} order by u.id, o.id;",
+                on
+            ))?;
+            assert_eq!(
+                rows(v),
+                vec![
+                    vec![int(1), int(10)],
+                    vec![int(1), int(11)],
+                    vec![int(2), int(12)],
+                    vec![int(3), Value::Null]
+                ]
+            );
+
+            let v = s.execute(&format!(
+                "select u.id, o.id from users u right outer join orders o on {} order by o.id;",
+                on
+            ))?;
+            assert_eq!(
+                rows(v),
+                vec![
+                    vec![int(1), int(10)],
+                    vec![int(1), int(11)],
+                    vec![int(2), int(12)],
+                    vec![Value::Null, int(13)],
+                    vec![Value::Null, int(14)]
+                ]
+            );
+
+            let v = s.execute(&format!(
+                "select count(*), count(u.id), count(o.id) from users u full join orders o on {};",
+                on
+            ))?;
+            assert_eq!(rows(v), vec![vec![int(6), int(4), int(5)]]);
+        }
+
+        let v = s.execute("select count(*) from users cross join orders;")?;
+        assert_eq!(rows(v), vec![vec![int(15)]]);
+
+        //同名的列在输出时保留表名
+        match s.execute("select * from users join orders on users.id = orders.user_id;")? {
+            ResultSet::Scan { columns, .. } => {
+                assert_eq!(columns, vec!["users.id", "name", "orders.id", "user_id", "amount"])
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(query(&mut s, "select id from users u join orders o on u.id = o.user_id;").is_err());
+        assert!(query(&mut s, "select * from users join users on true;").is_err());
+        assert!(query(&mut s, "select users.id from users u;").is_err());
+        assert!(query(&mut s, "select * from users join orders on 1;").is_err());
+
+        //类型不兼容的列做等值连接时, 哈希连接和嵌套循环连接一样报错
+        for on in ["u.name = o.user_id", "u.name = o.user_id and true"] {
+            let sql = format!("select * from users u join orders o on {};", on);
+            assert!(query(&mut s, &sql).is_err());
+        }
+        //整数和浮点数可以比较
+        let sql = "select u.id, o.id from users u join orders o on u.id = o.amount;";
+        assert_eq!(query(&mut s, sql)?, vec![vec![int(3), int(12)]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b text, c integer default 100);")?;
+        s.execute("insert into t1 values(1, 'a', 1), (2, 'b', 2), (3, 'c', 3);")?;
+
+        let v = s.execute("update t1 set b = 'x', c = c * 10 where a >= 2;")?;
+        assert!(matches!(v, ResultSet::Update { count: 2 }));
+
+        //修改主键
+        let v = s.execute("update t1 set a = a + 10 where b = 'a';")?;
+        assert!(matches!(v, ResultSet::Update { count: 1 }));
+
+        match s.execute("select * from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows.collect::<Result<Vec<_>>>()?,
+                vec![
+                    vec![Value::Integer(2), Value::String("x".to_string()), Value::Integer(20)],
+                    vec![Value::Integer(3), Value::String("x".to_string()), Value::Integer(30)],
+                    vec![Value::Integer(11), Value::String("a".to_string()), Value::Integer(1)],
+                ]
+            ),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        //类型不匹配或者列不存在时报错, 数据保持不变
+        assert!(s.execute("update t1 set c = 'str';").is_err());
+        assert!(s.execute("update t1 set d = 1;").is_err());
+        let v = s.execute("update t1 set c = null where a = 100;")?;
+        assert!(matches!(v, ResultSet::Update { count: 0 }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b text, c integer default 100);")?;
+        s.execute("insert into t1 values(1, 'a', 1), (2, 'b', 2), (3, 'c', 3), (4, null, 4);")?;
+
+        let v = s.execute("delete from t1 where c % 2 = 0 and b is not null;")?;
+        assert!(matches!(v, ResultSet::Delete { count: 1 }));
+
+        match s.execute("select * from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows.map(|it| it.map(|it| it[0].clone())).collect::<Result<Vec<_>>>()?,
+                vec![Value::Integer(1), Value::Integer(3), Value::Integer(4)]
+            ),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        let v = s.execute("delete from t1;")?;
+        assert!(matches!(v, ResultSet::Delete { count: 3 }));
+        match s.execute("select * from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.count(), 0),
+            r => panic!("unexpected result {:?}", r),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_primary_key() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b text primary key, c integer);")?;
+        s.execute("insert into t1 values(1, 'a', 1), (2, 'b', 2);")?;
+
+        //主键重复, 整条语句回滚
+        assert_eq!(
+            s.execute("insert into t1 values(3, 'c', 3), (1, 'a', 3);").err(),
+            Some(Error::DuplicateKey("a in table t1".to_string()))
+        );
+        assert!(s.execute("insert into t1 values(3, null, 3);").is_err());
+        assert!(matches!(
+            s.execute("update t1 set b = 'a' where a = 2;"),
+            Err(Error::DuplicateKey(_))
+        ));
+        assert!(s.execute("update t1 set b = null where a = 2;").is_err());
+
+        match s.execute("select * from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows.collect::<Result<Vec<_>>>()?,
+                vec![
+                    vec![Value::Integer(1), Value::String("a".to_string()), Value::Integer(1)],
+                    vec![Value::Integer(2), Value::String("b".to_string()), Value::Integer(2)],
+                ]
+            ),
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        //主键不能为空, 也不能有多个主键
+        assert!(s.execute("create table t2 (a int primary key null);").is_err());
+        assert!(s.execute("create table t2 (a int primary key, b int primary key);").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c integer);")?;
+        s.execute("insert into t1 values(1, 'a', 10), (2, 'b', 20), (3, 'a', null);")?;
+        s.execute("create index idx_b on t1 (b);")?;
+        s.execute("create unique index idx_c on t1 (c);")?;
+
+        let ids = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows.map(|it| it.unwrap()[0].clone()).collect(),
+            _ => vec![],
+        };
+
+        //已有数据建立了索引
+        let v = s.execute("select * from t1 where b = 'a';")?;
+        assert_eq!(ids(v), vec![Value::Integer(1), Value::Integer(3)]);
+
+        //插入, 更新和删除都会维护索引
+        s.execute("insert into t1 values(4, 'a', 40);")?;
+        s.execute("update t1 set b = 'c' where a = 1;")?;
+        s.execute("delete from t1 where a = 3;")?;
+        let v = s.execute("select * from t1 where b = 'a';")?;
+        assert_eq!(ids(v), vec![Value::Integer(4)]);
+        let v = s.execute("select * from t1 where b = 'c' and c = 10;")?;
+        assert_eq!(ids(v), vec![Value::Integer(1)]);
+
+        //修改主键后索引指向新的主键
+        s.execute("update t1 set a = 5 where a = 4;")?;
+        let v = s.execute("select * from t1 where b = 'a';")?;
+        assert_eq!(ids(v), vec![Value::Integer(5)]);
+
+        //唯一索引不允许重复值, NULL 除外
+        assert_eq!(
+            s.execute("insert into t1 values(6, 'd', 20);").err(),
+            Some(Error::UniqueViolation("20 in unique index idx_c".to_string()))
+        );
+        assert!(matches!(
+            s.execute("update t1 set c = 20 where a = 1;"),
+            Err(Error::UniqueViolation(_))
+        ));
+        s.execute("insert into t1 values(6, 'd', null), (7, 'd', null);")?;
+        assert!(s.execute("create unique index idx_b2 on t1 (a);").is_ok());
+        assert!(s.execute("create unique index idx_d on t1 (b);").is_err());
+        assert!(s.execute("create index idx_b on t1 (a);").is_err());
+
+        //删除索引后仍然可以通过全表扫描查询
+        s.execute("drop index idx_b;")?;
+        let v = s.execute("select * from t1 where b = 'a';")?;
+        assert_eq!(ids(v), vec![Value::Integer(5)]);
+        assert!(s.execute("drop index idx_b;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unique_index_concurrent() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t1 (a int primary key, b int);")?;
+        s1.execute("create unique index idx_b on t1 (b);")?;
+
+        //两个事务同时写入相同的值, 后写入的发生写冲突
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        s1.execute("insert into t1 values(1, 10);")?;
+        assert_eq!(s2.execute("insert into t1 values(2, 10);").err(), Some(Error::WriteConflict));
+        s2.execute("rollback;")?;
+
+        //其他事务已经提交, 但是在当前事务的快照中不可见
+        s2.execute("begin;")?;
+        s1.execute("commit;")?;
+        assert_eq!(s2.execute("insert into t1 values(3, 10);").err(), Some(Error::WriteConflict));
+        s2.execute("rollback;")?;
+
+        //不同的值互不影响, NULL 可以重复
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        s1.execute("insert into t1 values(4, 20), (5, null);")?;
+        s2.execute("insert into t1 values(6, 30), (7, null);")?;
+        s1.execute("commit;")?;
+        s2.execute("commit;")?;
+        assert_eq!(
+            query(&mut s1, "select a from t1 where b = 10 or b is null;")?,
+            vec![vec![Value::Integer(1)], vec![Value::Integer(5)], vec![Value::Integer(7)]]
+        );
+        assert_eq!(query(&mut s1, "select a from t1 where b = 30;")?, vec![vec![Value::Integer(6)]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_drop_truncate_table() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create index idx_b on t1 (b);")?;
+        s.execute("insert into t1 values(1, 'a'), (2, 'b');")?;
+
+        match s.execute("truncate table t1;")? {
+            ResultSet::TruncateTable { count, .. } => assert_eq!(count, 2),
+            _ => unreachable!(),
+        }
+        match s.execute("select * from t1 where b = 'a';")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.count(), 0),
+            _ => unreachable!(),
+        }
+        s.execute("insert into t1 values(1, 'a');")?;
+
+        s.execute("drop table t1;")?;
+        assert!(query(&mut s, "select * from t1;").is_err());
+        assert!(s.execute("drop table t1;").is_err());
+        assert!(matches!(
+            s.execute("drop table if exists t1;")?,
+            ResultSet::DropTable { dropped: false, .. }
+        ));
+
+        //删除之后可以重新创建, 之前的数据和索引都不存在了
+        s.execute("create table t1 (a int primary key, b text);")?;
+        s.execute("create index idx_b on t1 (b);")?;
+        match s.execute("select * from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.count(), 0),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_alter_table() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b text, c int);")?;
+        s.execute("create index idx_b on t1 (b);")?;
+        s.execute("insert into t1 values(1, 'x', 10), (2, 'y', 20);")?;
+
+        let scan = |result: ResultSet| match result {
+            ResultSet::Scan { columns, rows } => (columns, rows.map(|it| it.unwrap()).collect::<Vec<_>>()),
+            _ => unreachable!(),
+        };
+
+        //新增的列使用默认值填充已有的行
+        s.execute("alter table t1 add column d float not null default 1.5;")?;
+        s.execute("alter table t1 add e bool;")?;
+        s.execute("insert into t1 values(3, 'x', 30, 2.5, true);")?;
+        let (columns, rows) = scan(s.execute("select * from t1;")?);
+        assert_eq!(columns, vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(rows[0], vec![
+            Value::Integer(1),
+            Value::String("x".to_string()),
+            Value::Integer(10),
+            Value::Float(1.5),
+            Value::Null
+        ]);
+        assert!(s.execute("alter table t1 add f int not null;").is_err());
+        assert!(s.execute("alter table t1 add d int;").is_err());
+        assert!(s.execute("alter table t1 add f int default 'a';").is_err());
+
+        //删除列
+        s.execute("alter table t1 drop column c;")?;
+        let (columns, rows) = scan(s.execute("select * from t1 where a = 2;")?);
+        assert_eq!(columns, vec!["a", "b", "d", "e"]);
+        assert_eq!(rows[0][2], Value::Float(1.5));
+        assert!(s.execute("alter table t1 drop a;").is_err());
+
+        //重命名列之后索引仍然可用
+        s.execute("alter table t1 rename column b to bb;")?;
+        let (_, rows) = scan(s.execute("select a from t1 where bb = 'x' order by a;")?);
+        assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(3)]]);
+        s.execute("update t1 set bb = 'z' where a = 1;")?;
+        let (_, rows) = scan(s.execute("select a from t1 where bb = 'x';")?);
+        assert_eq!(rows, vec![vec![Value::Integer(3)]]);
+        assert!(s.execute("alter table t1 rename bb to d;").is_err());
+
+        //重命名表
+        s.execute("create table t2 (a int);")?;
+        assert!(s.execute("alter table t1 rename to t2;").is_err());
+        s.execute("alter table t1 rename to t3;")?;
+        assert!(query(&mut s, "select * from t1;").is_err());
+        let (_, rows) = scan(s.execute("select a from t3 where bb = 'z';")?);
+        assert_eq!(rows, vec![vec![Value::Integer(1)]]);
+        let (_, rows) = scan(s.execute("select count(*) from t3;")?);
+        assert_eq!(rows, vec![vec![Value::Integer(3)]]);
+
+        //删除有索引的列, 之后可以在新列上重新建同名索引
+        s.execute("alter table t3 drop bb;")?;
+        s.execute("alter table t3 add bb text;")?;
+        s.execute("create index idx_b on t3 (bb);")?;
+        let (_, rows) = scan(s.execute("select a from t3 where bb = 'x';")?);
+        assert!(rows.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("create table t1 (a int primary key, b int);")?;
+        s1.execute("insert into t1 values(1, 1);")?;
+
+        let count = |s: &mut crate::sql::engine::Session<_>| match s.execute("select * from t1;") {
+            Ok(ResultSet::Scan { rows, .. }) => rows.count(),
+            _ => usize::MAX,
+        };
+
+        //显式事务中的修改在提交之前对其他 session 不可见
+        assert!(matches!(s1.execute("begin;")?, ResultSet::Begin { read_only: false, .. }));
+        s1.execute("insert into t1 values(2, 2);")?;
+        s1.execute("update t1 set b = 10 where a = 1;")?;
+        assert_eq!(count(&mut s1), 2);
+        assert_eq!(count(&mut s2), 1);
+        s1.execute("commit;")?;
+        assert_eq!(count(&mut s2), 2);
+
+        //回滚之后所有的修改都被撤销
+        s1.execute("begin;")?;
+        s1.execute("delete from t1;")?;
+        s1.execute("create table t2 (a int);")?;
+        assert_eq!(count(&mut s1), 0);
+        s1.execute("rollback;")?;
+        assert_eq!(count(&mut s1), 2);
+        assert!(s1.execute("select * from t2;").is_err());
+
+        assert!(s1.execute("commit;").is_err());
+        assert!(s1.execute("rollback;").is_err());
+        s1.execute("begin;")?;
+        assert!(s1.execute("begin;").is_err());
+        s1.execute("rollback;")?;
+
+        //写冲突之后事务被中止, 之后的语句都会报错, 直到 COMMIT 或 ROLLBACK
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        s1.execute("update t1 set b = 20 where a = 1;")?;
+        assert_eq!(
+            s2.execute("update t1 set b = 30 where a = 1;").err(),
+            Some(Error::WriteConflict)
+        );
+        assert!(s2.execute("select * from t1;").is_err());
+        assert!(s2.execute("commit;").is_err());
+        assert_eq!(count(&mut s2), 2);
+        s1.execute("commit;")?;
+
+        s2.execute("begin;")?;
+        assert!(s2.execute("insert into t1 values(1, 1);").is_err());
+        assert!(matches!(s2.execute("rollback;")?, ResultSet::Rollback { .. }));
+
+        //只读事务看到的是开启时的快照, 不能写入数据
+        assert!(matches!(
+            s1.execute("begin read only;")?,
+            ResultSet::Begin { read_only: true, .. }
+        ));
+        s2.execute("insert into t1 values(3, 3);")?;
+        assert_eq!(count(&mut s1), 2);
+        assert_eq!(s1.execute("insert into t1 values(4, 4);").err(), Some(Error::ReadOnly));
+        s1.execute("rollback;")?;
+        assert_eq!(count(&mut s1), 3);
+
+        //可串行化事务中的写偏斜在提交时报错
+        s1.execute("begin isolation level serializable;")?;
+        s2.execute("begin isolation level serializable;")?;
+        s1.execute("select * from t1 where a = 2;")?;
+        s1.execute("update t1 set b = 0 where a = 1;")?;
+        s2.execute("select * from t1 where a = 1;")?;
+        s2.execute("update t1 set b = 0 where a = 2;")?;
+        s1.execute("commit;")?;
+        assert_eq!(s2.execute("commit;").err(), Some(Error::Serialization));
+        assert!(s2.execute("rollback;").is_err());
+
+        //session 关闭时回滚没有提交的事务
+        s1.execute("begin;")?;
+        s1.execute("insert into t1 values(4, 4);")?;
+        drop(s1);
+        assert_eq!(count(&mut s2), 3);
+        s2.execute("insert into t1 values(4, 4);")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_savepoint() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+
+        let rows = |s: &mut crate::sql::engine::Session<_>| match s.execute("select * from t1;") {
+            Ok(ResultSet::Scan { rows, .. }) => rows.map(|it| it.unwrap()).collect(),
+            _ => Vec::new(),
+        };
+
+        //保存点只能在显式事务中使用
+        assert!(s.execute("savepoint sp1;").is_err());
+
+        s.execute("begin;")?;
+        s.execute("insert into t1 values(1, 1);")?;
+        s.execute("savepoint sp1;")?;
+        s.execute("update t1 set b = 10 where a = 1;")?;
+        s.execute("insert into t1 values(2, 2);")?;
+        s.execute("savepoint sp2;")?;
+        s.execute("delete from t1 where a = 1;")?;
+        assert_eq!(rows(&mut s), vec![vec![Value::Integer(2), Value::Integer(2)]]);
+
+        s.execute("rollback to savepoint sp2;")?;
+        assert_eq!(rows(&mut s).len(), 2);
+        s.execute("rollback to sp1;")?;
+        assert_eq!(rows(&mut s), vec![vec![Value::Integer(1), Value::Integer(1)]]);
+        //回滚到 sp1 之后, sp2 已经失效
+        assert!(s.execute("rollback to savepoint sp2;").is_err());
+
+        //语句失败之后可以回滚到保存点继续执行
+        s.execute("rollback to savepoint sp1;")?;
+        assert!(s.execute("insert into t1 values(1, 1);").is_err());
+        assert!(query(&mut s, "select * from t1;").is_err());
+        assert!(s.execute("savepoint sp3;").is_err());
+        s.execute("rollback to savepoint sp1;")?;
+        s.execute("insert into t1 values(3, 3);")?;
+
+        s.execute("release savepoint sp1;")?;
+        assert!(s.execute("rollback to savepoint sp1;").is_err());
+        s.execute("rollback;")?;
+
+        s.execute("begin;")?;
+        s.execute("insert into t1 values(1, 1);")?;
+        s.execute("savepoint sp1;")?;
+        s.execute("insert into t1 values(3, 3);")?;
+        s.execute("release sp1;")?;
+        s.execute("commit;")?;
+        assert_eq!(
+            rows(&mut s),
+            vec![
+                vec![Value::Integer(1), Value::Integer(1)],
+                vec![Value::Integer(3), Value::Integer(3)],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_vacuum() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("insert into t1 values(1, 1), (2, 2), (3, 3);")?;
+        s.execute("update t1 set b = 10 where a = 1;")?;
+        s.execute("delete from t1 where a = 2;")?;
+
+        //旧版本的行 1 以及已经删除的行 2
+        assert!(matches!(s.execute("vacuum;")?, ResultSet::Vacuum { count: 3 }));
+        assert!(matches!(s.execute("vacuum;")?, ResultSet::Vacuum { count: 0 }));
+        match s.execute("select * from t1;")? {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows.collect::<Result<Vec<_>>>()?,
+                vec![
+                    vec![Value::Integer(1), Value::Integer(10)],
+                    vec![Value::Integer(3), Value::Integer(3)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        s.execute("begin;")?;
+        assert!(s.execute("vacuum;").is_err());
+        s.execute("rollback;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_of() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("insert into t1 values(1, 1), (2, 2);")?;
+        let version = match s.execute("show version;")? {
+            ResultSet::ShowVersion { version } => version,
+            _ => unreachable!(),
+        };
+        s.execute("update t1 set b = 10 where a = 1;")?;
+        s.execute("delete from t1 where a = 2;")?;
+
+        let rows = |s: &mut crate::sql::engine::Session<_>, sql: &str| match s.execute(sql) {
+            Ok(ResultSet::Scan { rows, .. }) => rows.map(|it| it.unwrap()).collect(),
+            _ => Vec::new(),
+        };
+        assert_eq!(
+            rows(&mut s, &format!("select * from t1 as of version {};", version)),
+            vec![
+                vec![Value::Integer(1), Value::Integer(1)],
+                vec![Value::Integer(2), Value::Integer(2)],
+            ]
+        );
+        assert_eq!(
+            rows(&mut s, &format!("select b from t1 x as of version {} where x.a = 1;", version + 1)),
+            vec![vec![Value::Integer(10)]]
+        );
+        assert_eq!(rows(&mut s, "select * from t1;"), vec![vec![Value::Integer(1), Value::Integer(10)]]);
+        //建表之前的版本中表不存在
+        assert!(query(&mut s, "select * from t1 as of version 1;").is_err());
+        assert!(s.execute(&format!("select * from t1 as of version {};", version + 100)).is_err());
+
+        //显式事务中 SHOW VERSION 返回事务的版本号, 不能使用 AS OF
+        let version = match s.execute("begin;")? {
+            ResultSet::Begin { version, .. } => version,
+            _ => unreachable!(),
+        };
+        assert!(matches!(s.execute("show version;")?, ResultSet::ShowVersion { version: v } if v == version));
+        assert!(query(&mut s, "select * from t1 as of version 1;").is_err());
+        s.execute("rollback;")?;
+
+        //VERSION 和 SHOW 不是保留关键字, 可以作为列名
+        s.execute("create table t2 (id int primary key, version int, show text);")?;
+        s.execute("insert into t2 (id, version, show) values(1, 7, 'x');")?;
+        assert_eq!(
+            query(&mut s, "select show from t2 where version = 7;")?,
+            vec![vec![Value::String("x".to_string())]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_streaming_rows() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s.execute("create table t1 (a int primary key);")?;
+        s.execute("insert into t1 values(1), (2), (3), (4);")?;
+        let scan = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows,
+            r => panic!("unexpected result {:?}", r),
+        };
+
+        //单独执行的查询逐行读取, 读到出错的行时才返回错误, 之后结束
+        let mut rows = scan(s.execute("select a / (a - 3) from t1;")?);
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(0)]));
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(-2)]));
+        assert!(rows.next().unwrap().is_err());
+        assert!(rows.next().is_none());
+
+        //读取完之前一直使用查询开始时的快照, VACUUM 不会回收其中的数据
+        let mut rows = scan(s.execute("select * from t1;")?);
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(1)]));
+        s2.execute("delete from t1 where a > 1;")?;
+        assert!(matches!(s2.execute("vacuum;")?, ResultSet::Vacuum { count: 0 }));
+        assert_eq!(rows.count(), 3);
+        assert!(matches!(s2.execute("vacuum;")?, ResultSet::Vacuum { count: 6 }));
+        s.execute("insert into t1 values(2), (3);")?;
+
+        //显式事务中读取结果出错时中止事务
+        s.execute("begin;")?;
+        let rows = scan(s.execute("select a / (a - 3) from t1;")?);
+        assert!(rows.collect::<Result<Vec<_>>>().is_err());
+        assert!(query(&mut s, "select * from t1;").is_err());
+        s.execute("rollback;")?;
+
+        //事务结束之后不能再读取事务中的查询结果
+        s.execute("begin;")?;
+        let mut rows = scan(s.execute("select * from t1;")?);
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(1)]));
+        s.execute("commit;")?;
+        assert!(rows.next().unwrap().is_err());
+        assert!(rows.next().is_none());
+        assert_eq!(query(&mut s, "select * from t1;")?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_txn_rows_snapshot() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        let values = (0..200).map(|i| format!("({}, {})", i, i)).collect::<Vec<_>>();
+        s.execute(&format!("insert into t1 values {};", values.join(", ")))?;
+        let scan = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows,
+            r => panic!("unexpected result {:?}", r),
+        };
+
+        //事务中没有读取完的查询结果, 不会看到之后的语句在同一个事务中写入的数据
+        s.execute("begin;")?;
+        let mut rows = scan(s.execute("select * from t1;")?);
+        let mut other = scan(s.execute("select b from t1 where a >= 100;")?);
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(0), Value::Integer(0)]));
+        s.execute("update t1 set b = -1;")?;
+        s.execute("insert into t1 values(1000, 1000);")?;
+        let rest = rows.collect::<Result<Vec<_>>>()?;
+        assert_eq!(rest.len(), 199);
+        for (i, row) in rest.into_iter().enumerate() {
+            let i = i as i64 + 1;
+            assert_eq!(row, vec![Value::Integer(i), Value::Integer(i)]);
+        }
+        assert_eq!(other.next().transpose()?, Some(vec![Value::Integer(100)]));
+        assert_eq!(other.count(), 99);
+
+        //之后的查询可以看到事务中的修改
+        let rows = query(&mut s, "select * from t1;")?;
+        assert_eq!(rows.len(), 201);
+        assert_eq!(rows[0], vec![Value::Integer(0), Value::Integer(-1)]);
+        assert_eq!(rows[200], vec![Value::Integer(1000), Value::Integer(1000)]);
+
+        //回滚到保存点之前也会先读取完查询结果
+        s.execute("savepoint sp;")?;
+        s.execute("delete from t1;")?;
+        let rows = scan(s.execute("select * from t1;")?);
+        s.execute("rollback to savepoint sp;")?;
+        assert_eq!(rows.count(), 0);
+
+        //读取出错的查询结果, 在客户端读到出错的行时才中止事务
+        let mut rows = scan(s.execute("select 10 / (a - 1) from t1;")?);
+        s.execute("update t1 set b = 0;")?;
+        assert_eq!(rows.next().transpose()?, Some(vec![Value::Integer(-10)]));
+        assert!(rows.next().unwrap().is_err());
+        assert!(query(&mut s, "select * from t1;").is_err());
+        s.execute("rollback;")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_scan() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b int);")?;
+        s.execute("insert into t1 values(-5, 1), (-1, 2), (0, 3), (3, 4), (7, 5), (10, 6);")?;
+        s.execute("create table t2 (a float primary key);")?;
+        s.execute("insert into t2 values(-0.0), (0.0), (-1.5), (2.5);")?;
+        s.execute("create table t3 (a text primary key);")?;
+        s.execute("insert into t3 values('a'), ('ab'), ('b'), ('ba'), ('c');")?;
+
+        let first_column = |s: &mut Session<_>, sql: &str| -> Result<Vec<Value>> {
+            Ok(query(s, sql)?.into_iter().map(|it| it[0].clone()).collect())
+        };
+        let ints = |v: &[i64]| v.iter().copied().map(Value::Integer).collect::<Vec<_>>();
+
+        //主键的范围扫描只返回范围内的行, 结果和全表扫描之后过滤一致
+        for (sql, expected) in [
+            ("select a from t1 where a = 3;", vec![3]),
+            ("select a from t1 where a = 4;", vec![]),
+            ("select a from t1 where a > -1;", vec![0, 3, 7, 10]),
+            ("select a from t1 where a >= -1 and a < 7;", vec![-1, 0, 3]),
+            ("select a from t1 where 3 <= a and 10 > a and b > 4;", vec![7]),
+            ("select a from t1 where a <= -9223372036854775808;", vec![]),
+            ("select a from t1 where a > 3 and a < 3;", vec![]),
+            ("select a from t1 where a > 7 and a < 3;", vec![]),
+            ("select a from t1 where a >= 3 and a <= 3 and a = 3;", vec![3]),
+        ] {
+            assert_eq!(first_column(&mut s, sql)?, ints(&expected), "{}", sql);
+        }
+        assert_eq!(first_column(&mut s, "select a from t1 where a < 7 order by a desc;")?, ints(&[3, 0, -1, -5]));
+
+        //-0.0 和 0.0 是不同的主键, 但是比较时相等
+        assert_eq!(query(&mut s, "select a from t2 where a = 0.0;")?.len(), 2);
+        assert_eq!(query(&mut s, "select a from t2 where a >= 0.0;")?.len(), 3);
+        assert_eq!(query(&mut s, "select a from t2 where a > 0.0;")?, vec![vec![Value::Float(2.5)]]);
+        assert_eq!(query(&mut s, "select a from t2 where a < 0.0;")?, vec![vec![Value::Float(-1.5)]]);
+
+        let strings = |v: &[&str]| v.iter().map(|it| Value::String(it.to_string())).collect::<Vec<_>>();
+        assert_eq!(
+            first_column(&mut s, "select a from t3 where a > 'a' and a <= 'b';")?,
+            strings(&["ab", "b"])
+        );
+
+        //更新和删除也只读取范围内的行
+        s.execute("update t1 set b = 0 where a >= 0 and a <= 3;")?;
+        s.execute("delete from t1 where a = -5;")?;
+        assert_eq!(
+            query(&mut s, "select a, b from t1 where b = 0 or a < 0;")?,
+            vec![
+                vec![Value::Integer(-1), Value::Integer(2)],
+                vec![Value::Integer(0), Value::Integer(0)],
+                vec![Value::Integer(3), Value::Integer(0)],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_order() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key, b float);")?;
+        s.execute("insert into t1 values(10, 1.5), (-3, -0.5), (256, -10.25), (0, 0.0), (-300, 7.0);")?;
+        s.execute("create table t2 (a float primary key);")?;
+        s.execute("insert into t2 values(1.5), (-0.5), (-10.25), (0.0), (7.0);")?;
+        s.execute("create table t3 (a text primary key);")?;
+        s.execute("insert into t3 values('b'), ('ab'), ('a'), ('');")?;
+
+        let first_column = |result: ResultSet| match result {
+            ResultSet::Scan { rows, .. } => rows.map(|it| it.unwrap()[0].clone()).collect(),
+            _ => vec![],
+        };
+
+        //整数、浮点数、字符串主键都按照值的大小顺序返回
+        assert_eq!(
+            first_column(s.execute("select * from t1;")?),
+            vec![-300, -3, 0, 10, 256].into_iter().map(Value::Integer).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            first_column(s.execute("select * from t2;")?),
+            vec![-10.25, -0.5, 0.0, 1.5, 7.0].into_iter().map(Value::Float).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            first_column(s.execute("select * from t3;")?),
+            vec!["", "a", "ab", "b"]
+                .into_iter()
+                .map(|it| Value::String(it.to_string()))
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_encode() -> Result<()> {
+        let key = Key::Row("t\0".to_string(), Value::String("a\0b".to_string()));
+        assert_eq!(Key::decode(&key.encode()?)?, key);
+
+        let key = Key::Row("t".to_string(), Value::Integer(-1));
+        assert_eq!(
+            key.encode()?,
+            vec![1, 116, 0, 0, 2, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(Key::decode(&key.encode()?)?, key);
+
+        //行数据的 key 以表的前缀开头
+        let prefix = KeyPrefix::Row("t".to_string()).encode()?;
+        assert!(key.encode()?.starts_with(&prefix));
+        Ok(())
+    }
 }
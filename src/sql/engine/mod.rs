@@ -1,16 +1,56 @@
+use std::{
+    cell::RefCell,
+    mem,
+    ops::Bound,
+    rc::{Rc, Weak},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
 use crate::error::{Error, Result};
 
-use super::{executor::ResultSet, parser::Parser, plan::Plan, schema::Table, types::Row};
+use super::{
+    executor::{ResultSet, Rows},
+    parser::{ast, Parser},
+    plan::Plan,
+    schema::{AlterTable, Index, Table},
+    types::{Row, Value},
+};
+
+pub mod kv;
+
+//扫描表的行数据迭代器, 按主键顺序返回, 也可以反向迭代
+//迭代器不借用事务, 执行节点可以在读取的过程中继续使用事务
+pub type ScanIterator = Box<dyn DoubleEndedIterator<Item = Result<Row>>>;
 
-mod kv;
 pub trait Engine: Clone {
-    type Transaction: Transaction;
+    type Transaction: Transaction + 'static;
 
     fn begin(&self) -> Result<Self::Transaction>;
 
+    //开启只读事务, 只能看到开启之前已经提交的数据
+    fn begin_read_only(&self) -> Result<Self::Transaction>;
+
+    //开启可串行化事务, 提交时读取过的数据被并发修改会返回 Error::Serialization
+    fn begin_serializable(&self) -> Result<Self::Transaction>;
+
+    //开启历史版本的只读事务, 看到的数据和版本号为 version 的事务开启时一致
+    fn begin_as_of(&self, version: u64) -> Result<Self::Transaction>;
+
+    //当前版本号, 即下一个事务将要分配的版本号
+    fn current_version(&self) -> Result<u64>;
+
+    //回收所有事务都不会再读取的旧版本数据, 返回删除的版本个数
+    fn vacuum(&self) -> Result<usize>;
+
     fn session(&self) -> Result<Session<Self>> {
         Ok(Session {
             engine: self.clone(),
+            txn: None,
+            status: Arc::new(TxnStatus::default()),
+            open_rows: Vec::new(),
         })
     }
 }
@@ -20,15 +60,70 @@ pub trait Transaction {
 
     fn rollback(&self) -> Result<()>;
 
+    //事务的版本号
+    fn version(&self) -> u64;
+
+    //创建保存点
+    fn savepoint(&mut self, name: String) -> Result<()>;
+
+    //回滚到保存点, 撤销保存点之后的修改
+    fn rollback_to_savepoint(&mut self, name: &str) -> Result<()>;
+
+    //释放保存点, 保存点之后的修改仍然保留
+    fn release_savepoint(&mut self, name: &str) -> Result<()>;
+
     //创建行
     fn create_row(&mut self, table_name: String, row: Row) -> Result<()>;
 
-    //扫描表
-    fn scan_table(&self, table_name: String) -> Result<Vec<Row>>;
+    //更新行, id 为更新前的主键
+    fn update_row(&mut self, table: &Table, id: &Value, row: Row) -> Result<()>;
+
+    //根据主键删除行
+    fn delete_row(&mut self, table: &Table, id: &Value) -> Result<()>;
+
+    //根据主键读取行
+    fn read_by_id(&self, table_name: String, id: &Value) -> Result<Option<Row>>;
+
+    //流式扫描表, 结果按主键顺序排列
+    fn scan_table(&self, table_name: String) -> Result<ScanIterator>;
+
+    //按照主键的范围流式扫描表, 结果按主键顺序排列
+    fn scan_table_range(
+        &self,
+        table_name: String,
+        start: Bound<Value>,
+        end: Bound<Value>,
+    ) -> Result<ScanIterator>;
 
     //ddl创建表相关
     fn create_table(&mut self, table: Table) -> Result<()>;
 
+    //删除表, 包括表中所有的数据和索引
+    fn drop_table(&mut self, table_name: String) -> Result<()>;
+
+    //清空表中的数据, 返回删除的行数
+    fn truncate_table(&mut self, table_name: String) -> Result<usize>;
+
+    //修改表结构, 已有的行数据会按照新的表结构重写
+    fn alter_table(&mut self, table_name: String, operation: AlterTable) -> Result<()>;
+
+    //获取所有表的信息
+    fn scan_tables(&self) -> Result<Vec<Table>>;
+
+    //在表上创建二级索引, 并为已有的数据建立索引
+    fn create_index(&mut self, table_name: String, index: Index) -> Result<()>;
+
+    //删除索引以及所有的索引数据
+    fn drop_index(&mut self, index_name: String) -> Result<()>;
+
+    //根据索引列的值获取对应行的主键
+    fn read_by_index(
+        &self,
+        table_name: String,
+        column_name: String,
+        value: &Value,
+    ) -> Result<Vec<Value>>;
+
     //获取表相关信息
     fn get_table(&self, table_name: String) -> Result<Option<Table>>;
     // 必须获取表信息,否则报错
@@ -44,26 +139,297 @@ pub trait Transaction {
 //客户端session定义
 pub struct Session<E: Engine> {
     engine: E,
+    //显式开启的事务, 为空时每条语句在单独的事务中执行
+    txn: Option<E::Transaction>,
+    //显式事务的状态, 和事务中返回的查询结果共享
+    status: Arc<TxnStatus>,
+    //显式事务中返回的还没有读取完的查询结果
+    open_rows: Vec<Weak<RefCell<Rows>>>,
+}
+
+/**
+ * 显式事务的状态
+ * 事务中有语句执行失败, 或者读取查询结果时出错, 事务被标记为中止
+ * 在执行 COMMIT, ROLLBACK 或者 ROLLBACK TO SAVEPOINT 之前, 其他语句都会报错
+ */
+#[derive(Default)]
+struct TxnStatus {
+    aborted: AtomicBool,
+    //事务已经结束, 之后不能再读取事务中的查询结果
+    ended: AtomicBool,
 }
 
 impl<E: Engine> Session<E> {
     //执行客户端语句
     pub fn execute(&mut self, sql: &str) -> Result<ResultSet> {
         match Parser::new(sql).parse()? {
-            stmt => {
-                let mut txn = self.engine.begin()?;
-                //构建plan, 执行sql语句
-                match Plan::build(stmt).execute(&mut txn) {
-                    Ok(result) => {
-                        txn.commit()?;
-                        Ok(result)
-                    }
-                    Err(err) => {
-                        txn.rollback()?;
-                        Err(err)
+            ast::Statement::Begin {
+                read_only,
+                serializable,
+            } => {
+                if self.txn.is_some() {
+                    return Err(Error::Internal("already in a transaction".into()));
+                }
+                //只读事务不会写入数据, 快照读已经满足可串行化
+                let txn = match (read_only, serializable) {
+                    (true, _) => self.engine.begin_read_only()?,
+                    (false, true) => self.engine.begin_serializable()?,
+                    (false, false) => self.engine.begin()?,
+                };
+                let version = txn.version();
+                self.txn = Some(txn);
+                Ok(ResultSet::Begin { version, read_only })
+            }
+            ast::Statement::Commit => {
+                let aborted = self.is_aborted();
+                let txn = self.take_txn()?;
+                let version = txn.version();
+                //中止的事务提交时回滚
+                if aborted {
+                    txn.rollback()?;
+                    return Err(Error::Internal(format!(
+                        "transaction {} was aborted and has been rolled back",
+                        version
+                    )));
+                }
+                txn.commit()?;
+                Ok(ResultSet::Commit { version })
+            }
+            ast::Statement::Rollback => {
+                let txn = self.take_txn()?;
+                let version = txn.version();
+                txn.rollback()?;
+                Ok(ResultSet::Rollback { version })
+            }
+            ast::Statement::Savepoint { name } => {
+                if self.is_aborted() {
+                    return Err(Self::aborted_error());
+                }
+                self.txn_mut()?.savepoint(name.clone())?;
+                Ok(ResultSet::Savepoint { name })
+            }
+            //回滚到保存点之后, 中止的事务可以继续执行
+            ast::Statement::RollbackToSavepoint { name } => {
+                self.buffer_open_rows();
+                self.txn_mut()?.rollback_to_savepoint(&name)?;
+                self.status.aborted.store(false, Ordering::Release);
+                Ok(ResultSet::RollbackToSavepoint { name })
+            }
+            ast::Statement::ReleaseSavepoint { name } => {
+                if self.is_aborted() {
+                    return Err(Self::aborted_error());
+                }
+                self.txn_mut()?.release_savepoint(&name)?;
+                Ok(ResultSet::ReleaseSavepoint { name })
+            }
+            ast::Statement::Vacuum => {
+                if self.txn.is_some() {
+                    return Err(Error::Internal("VACUUM can not run inside a transaction".into()));
+                }
+                let count = self.engine.vacuum()?;
+                Ok(ResultSet::Vacuum { count })
+            }
+            //显式事务中为事务的版本号
+            ast::Statement::ShowVersion => {
+                let version = match &self.txn {
+                    Some(txn) => txn.version(),
+                    None => self.engine.current_version()?,
+                };
+                Ok(ResultSet::ShowVersion { version })
+            }
+            _ if self.is_aborted() => Err(Self::aborted_error()),
+            //历史版本查询在单独的只读事务中执行
+            ast::Statement::Select {
+                as_of: Some(_), ..
+            } if self.txn.is_some() => Err(Error::Internal(
+                "AS OF VERSION can not be used inside a transaction".into(),
+            )),
+            stmt @ ast::Statement::Select {
+                as_of: Some(version),
+                ..
+            } => {
+                let txn = self.engine.begin_as_of(version)?;
+                Self::execute_autocommit(stmt, txn)
+            }
+            stmt => match self.txn.as_mut() {
+                //显式事务中执行, 语句失败时中止事务
+                //查询结果在客户端读取时才从存储中拉取, 只能在事务结束之前读取
+                Some(_) => {
+                    if !matches!(stmt, ast::Statement::Select { .. }) {
+                        self.buffer_open_rows();
                     }
+                    let txn = self.txn_mut()?;
+                    let result = Plan::build(stmt, &*txn).and_then(|plan| plan.execute(txn));
+                    self.status.aborted.store(result.is_err(), Ordering::Release);
+                    Ok(match result? {
+                        ResultSet::Scan { columns, rows } => {
+                            let rows = Rc::new(RefCell::new(rows));
+                            self.open_rows.push(Rc::downgrade(&rows));
+                            ResultSet::Scan {
+                                columns,
+                                rows: Box::new(TxnRows {
+                                    rows: Some(rows),
+                                    status: self.status.clone(),
+                                }),
+                            }
+                        }
+                        result => result,
+                    })
                 }
+                None => {
+                    //单独执行的查询使用只读事务, 不需要分配版本号, 不和其他事务争抢写锁
+                    let txn = match stmt {
+                        ast::Statement::Select { .. } => self.engine.begin_read_only()?,
+                        _ => self.engine.begin()?,
+                    };
+                    Self::execute_autocommit(stmt, txn)
+                }
+            },
+        }
+    }
+
+    /**
+     * 在单独的事务中执行语句
+     * 查询结果持有事务, 客户端读取完所有的行之后才提交, 没有读取完就丢弃时回滚
+     * 其他语句执行完直接提交
+     */
+    fn execute_autocommit(stmt: ast::Statement, mut txn: E::Transaction) -> Result<ResultSet> {
+        //构建plan, 执行sql语句
+        match Plan::build(stmt, &txn).and_then(|plan| plan.execute(&mut txn)) {
+            Ok(ResultSet::Scan { columns, rows }) => Ok(ResultSet::Scan {
+                columns,
+                rows: Box::new(AutocommitRows {
+                    rows,
+                    txn: Some(txn),
+                }),
+            }),
+            Ok(result) => {
+                txn.commit()?;
+                Ok(result)
+            }
+            Err(err) => {
+                txn.rollback()?;
+                Err(err)
             }
         }
     }
+
+    //结束显式事务, 事务中返回的查询结果不能再读取, 下一个事务使用新的状态
+    fn take_txn(&mut self) -> Result<E::Transaction> {
+        let txn = self.txn.take().ok_or(Error::Internal("not in a transaction".into()))?;
+        self.status.ended.store(true, Ordering::Release);
+        self.status = Arc::new(TxnStatus::default());
+        self.open_rows.clear();
+        Ok(txn)
+    }
+
+    /**
+     * 事务中的查询结果按照事务自己写入的最新数据读取
+     * 执行修改数据的语句之前, 把还没有读取完的查询结果全部读取到内存中,
+     * 保证一个查询结果中不会同时出现修改之前和之后的数据
+     * 读取出错时只保存到出错的行为止, 客户端读到这一行时才中止事务
+     */
+    fn buffer_open_rows(&mut self) {
+        for rows in mem::take(&mut self.open_rows) {
+            //客户端已经丢弃的查询结果不需要读取
+            let Some(rows) = rows.upgrade() else {
+                continue;
+            };
+            let mut rows = rows.borrow_mut();
+            let mut buffered = Vec::new();
+            for row in rows.by_ref() {
+                let failed = row.is_err();
+                buffered.push(row);
+                if failed {
+                    break;
+                }
+            }
+            *rows = Box::new(buffered.into_iter());
+        }
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.status.aborted.load(Ordering::Acquire)
+    }
+
+    fn txn_mut(&mut self) -> Result<&mut E::Transaction> {
+        self.txn.as_mut().ok_or(Error::Internal("not in a transaction".into()))
+    }
+
+    fn aborted_error() -> Error {
+        Error::Internal(
+            "current transaction is aborted, commands ignored until end of transaction".into(),
+        )
+    }
+}
+
+//session 关闭时回滚没有提交的事务
+impl<E: Engine> Drop for Session<E> {
+    fn drop(&mut self) {
+        if let Ok(txn) = self.take_txn() {
+            txn.rollback().ok();
+        }
+    }
+}
+
+//显式事务中的查询结果, 事务结束之后读取返回错误, 读取出错时中止事务
+struct TxnRows {
+    //返回事务结束的错误之后为 None, 和 session 共享, 事务中修改数据之前由 session 读取到内存中
+    rows: Option<Rc<RefCell<Rows>>>,
+    status: Arc<TxnStatus>,
+}
+
+impl Iterator for TxnRows {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.status.ended.load(Ordering::Acquire) {
+            //只返回一次错误, 之后结束迭代
+            return self.rows.take().map(|_| {
+                Err(Error::Internal(
+                    "transaction has ended, rows of the query can no longer be read".into(),
+                ))
+            });
+        }
+        let row = self.rows.as_ref()?.borrow_mut().next();
+        if let Some(Err(_)) = row {
+            self.status.aborted.store(true, Ordering::Release);
+        }
+        row
+    }
+}
+
+//单独执行的查询结果, 持有查询所在的事务, 读取完之后提交, 出错时回滚
+struct AutocommitRows<T: Transaction> {
+    rows: Rows,
+    //事务结束之后为 None
+    txn: Option<T>,
+}
+
+impl<T: Transaction> Iterator for AutocommitRows<T> {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.txn.as_ref()?;
+        match self.rows.next() {
+            Some(Ok(row)) => Some(Ok(row)),
+            Some(Err(err)) => {
+                let txn = self.txn.take()?;
+                Some(txn.rollback().and(Err(err)))
+            }
+            None => {
+                let txn = self.txn.take()?;
+                txn.commit().err().map(Err)
+            }
+        }
+    }
+}
+
+//没有读取完的查询结果被丢弃时回滚事务
+impl<T: Transaction> Drop for AutocommitRows<T> {
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            txn.rollback().ok();
+        }
+    }
 }
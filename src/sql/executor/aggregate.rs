@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::Expression,
+        plan::Aggregate as AggregateFunction,
+        types::{HashKey, Value},
+    },
+};
+
+use super::{Executor, ResultSet};
+
+pub struct Aggregate<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    group_by: Vec<Expression>,
+    aggregates: Vec<(AggregateFunction, Option<Expression>)>,
+}
+
+impl<T: Transaction> Aggregate<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<(AggregateFunction, Option<Expression>)>,
+    ) -> Box<Self> {
+        Box::new(Aggregate {
+            source,
+            group_by,
+            aggregates,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Aggregate<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match self.source.execute(txn)? {
+            ResultSet::Scan { columns, rows } => {
+                //按照分组第一次出现的顺序输出
+                let mut groups: Vec<(Vec<Value>, Vec<Accumulator>)> = Vec::new();
+                //分组的键按照 HashKey 比较, 1 和 1.0, 0.0 和 -0.0 分在同一组
+                let mut positions: HashMap<Vec<HashKey>, usize> = HashMap::new();
+
+                //没有 group by 时, 即使没有数据也要输出一行
+                if self.group_by.is_empty() {
+                    positions.insert(Vec::new(), 0);
+                    groups.push((Vec::new(), Accumulator::from_aggregates(&self.aggregates)));
+                }
+
+                for row in rows {
+                    let row = row?;
+                    let mut key = Vec::with_capacity(self.group_by.len());
+                    for expr in &self.group_by {
+                        key.push(expr.evaluate(&columns, &row)?);
+                    }
+                    let hash_key = key.iter().map(HashKey::new).collect::<Vec<_>>();
+                    let position = match positions.get(&hash_key) {
+                        Some(position) => *position,
+                        None => {
+                            positions.insert(hash_key, groups.len());
+                            groups.push((key, Accumulator::from_aggregates(&self.aggregates)));
+                            groups.len() - 1
+                        }
+                    };
+
+                    let accumulators = &mut groups[position].1;
+                    for ((_, arg), accumulator) in self.aggregates.iter().zip(accumulators) {
+                        //COUNT(*) 统计所有行
+                        let value = match arg {
+                            Some(expr) => expr.evaluate(&columns, &row)?,
+                            None => Value::Boolean(true),
+                        };
+                        accumulator.add(value)?;
+                    }
+                }
+
+                let mut new_rows = Vec::with_capacity(groups.len());
+                for (mut key, accumulators) in groups {
+                    for accumulator in accumulators {
+                        key.push(accumulator.value());
+                    }
+                    new_rows.push(key);
+                }
+
+                let new_columns = self
+                    .group_by
+                    .iter()
+                    .map(|expr| expr.to_string())
+                    .chain(self.aggregates.iter().map(|(f, arg)| f.label(arg)))
+                    .collect();
+                Ok(ResultSet::Scan {
+                    columns: new_columns,
+                    rows: Box::new(new_rows.into_iter().map(Ok)),
+                })
+            }
+            _ => Err(Error::Internal("unexpected result set".into())),
+        }
+    }
+}
+
+//聚集函数的中间状态, 所有聚集函数都会忽略 NULL
+enum Accumulator {
+    Count(i64),
+    Sum(Option<Value>),
+    Avg(Option<Value>, i64),
+    Min(Option<Value>),
+    Max(Option<Value>),
+}
+
+impl Accumulator {
+    fn new(f: AggregateFunction) -> Self {
+        match f {
+            AggregateFunction::Count => Accumulator::Count(0),
+            AggregateFunction::Sum => Accumulator::Sum(None),
+            AggregateFunction::Avg => Accumulator::Avg(None, 0),
+            AggregateFunction::Min => Accumulator::Min(None),
+            AggregateFunction::Max => Accumulator::Max(None),
+        }
+    }
+
+    fn from_aggregates(aggregates: &[(AggregateFunction, Option<Expression>)]) -> Vec<Self> {
+        aggregates.iter().map(|(f, _)| Accumulator::new(*f)).collect()
+    }
+
+    fn add(&mut self, value: Value) -> Result<()> {
+        if value == Value::Null {
+            return Ok(());
+        }
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => *sum = Some(Self::sum(sum.take(), value)?),
+            Accumulator::Avg(sum, count) => {
+                *sum = Some(Self::sum(sum.take(), value)?);
+                *count += 1;
+            }
+            Accumulator::Min(min) => match min {
+                Some(current) if !value.compare(current)?.is_some_and(|o| o.is_lt()) => {}
+                _ => *min = Some(value),
+            },
+            Accumulator::Max(max) => match max {
+                Some(current) if !value.compare(current)?.is_some_and(|o| o.is_gt()) => {}
+                _ => *max = Some(value),
+            },
+        }
+        Ok(())
+    }
+
+    //整数求和的结果为整数, 有浮点数参与时结果为浮点数
+    fn sum(sum: Option<Value>, value: Value) -> Result<Value> {
+        match (sum, value) {
+            (_, v @ (Value::Boolean(_) | Value::String(_))) => {
+                Err(Error::Internal(format!("can not sum {}", v)))
+            }
+            (None, v) => Ok(v),
+            (Some(sum), v) => sum.checked_add(&v),
+        }
+    }
+
+    fn value(self) -> Value {
+        match self {
+            Accumulator::Count(count) => Value::Integer(count),
+            Accumulator::Sum(sum) | Accumulator::Min(sum) | Accumulator::Max(sum) => {
+                sum.unwrap_or(Value::Null)
+            }
+            //平均值总是浮点数
+            Accumulator::Avg(sum, count) => match sum {
+                Some(Value::Integer(i)) => Value::Float(i as f64 / count as f64),
+                Some(Value::Float(f)) => Value::Float(f / count as f64),
+                _ => Value::Null,
+            },
+        }
+    }
+}
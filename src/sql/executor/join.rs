@@ -0,0 +1,288 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::{Expression, JoinType},
+        types::{HashKey, Row, Value},
+    },
+};
+
+use super::{Executor, ResultSet, Rows};
+
+pub struct NestedLoopJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+    predicate: Option<Expression>,
+    join_type: JoinType,
+}
+
+impl<T: Transaction> NestedLoopJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        right: Box<dyn Executor<T>>,
+        predicate: Option<Expression>,
+        join_type: JoinType,
+    ) -> Box<Self> {
+        Box::new(NestedLoopJoin {
+            left,
+            right,
+            predicate,
+            join_type,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (left_columns, left_rows) = scan_result(self.left.execute(txn)?)?;
+        let (right_columns, right_rows) = scan_result(self.right.execute(txn)?)?;
+        let right_rows = right_rows.collect::<Result<Vec<_>>>()?;
+        let (left_width, right_width) = (left_columns.len(), right_columns.len());
+
+        let mut columns = left_columns;
+        columns.extend(right_columns);
+
+        //右侧的每一行和左侧的行拼接之后计算连接条件
+        let names = columns.clone();
+        let predicate = self.predicate;
+        let matcher = move |left_row: &Row, right_rows: &[Row]| -> Result<Vec<usize>> {
+            let mut matches = Vec::new();
+            for (i, right_row) in right_rows.iter().enumerate() {
+                let keep = match &predicate {
+                    Some(predicate) => {
+                        let mut row = left_row.clone();
+                        row.extend(right_row.iter().cloned());
+                        match predicate.evaluate(&names, &row)? {
+                            Value::Boolean(b) => b,
+                            Value::Null => false,
+                            v => {
+                                return Err(Error::Internal(format!(
+                                    "join predicate returned {}, expected boolean",
+                                    v
+                                )))
+                            }
+                        }
+                    }
+                    None => true,
+                };
+                if keep {
+                    matches.push(i);
+                }
+            }
+            Ok(matches)
+        };
+
+        Ok(ResultSet::Scan {
+            columns,
+            rows: Box::new(JoinIterator::new(
+                left_rows,
+                (left_width, right_width),
+                right_rows,
+                self.join_type,
+                Box::new(matcher),
+            )),
+        })
+    }
+}
+
+pub struct HashJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    left_column: usize,
+    right: Box<dyn Executor<T>>,
+    right_column: usize,
+    join_type: JoinType,
+}
+
+impl<T: Transaction> HashJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        left_column: usize,
+        right: Box<dyn Executor<T>>,
+        right_column: usize,
+        join_type: JoinType,
+    ) -> Box<Self> {
+        Box::new(HashJoin {
+            left,
+            left_column,
+            right,
+            right_column,
+            join_type,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for HashJoin<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let (left_columns, left_rows) = scan_result(self.left.execute(txn)?)?;
+        let (right_columns, right_rows) = scan_result(self.right.execute(txn)?)?;
+        let right_rows = right_rows.collect::<Result<Vec<_>>>()?;
+        let (left_width, right_width) = (left_columns.len(), right_columns.len());
+
+        let mut columns = left_columns;
+        columns.extend(right_columns);
+
+        //使用右侧的数据构建哈希表, NULL 不和任何值相等, 不放入哈希表
+        //和嵌套循环连接一样, 类型不兼容的值比较时报错, 每种类型保留右侧的一个值用于检查
+        let mut table: HashMap<HashKey, Vec<usize>> = HashMap::new();
+        let mut samples: Vec<Value> = Vec::new();
+        for (i, row) in right_rows.iter().enumerate() {
+            let value = &row[self.right_column];
+            if *value != Value::Null
+                && !samples.iter().any(|it| mem::discriminant(it) == mem::discriminant(value))
+            {
+                samples.push(value.clone());
+            }
+            if let Some(key) = hash_key(value) {
+                table.entry(key).or_default().push(i);
+            }
+        }
+
+        let left_column = self.left_column;
+        let matcher = move |left_row: &Row, _: &[Row]| -> Result<Vec<usize>> {
+            for sample in &samples {
+                left_row[left_column].compare(sample)?;
+            }
+            Ok(hash_key(&left_row[left_column])
+                .and_then(|key| table.get(&key))
+                .cloned()
+                .unwrap_or_default())
+        };
+
+        Ok(ResultSet::Scan {
+            columns,
+            rows: Box::new(JoinIterator::new(
+                left_rows,
+                (left_width, right_width),
+                right_rows,
+                self.join_type,
+                Box::new(matcher),
+            )),
+        })
+    }
+}
+
+//根据左侧的行找到右侧匹配的行的下标
+type Matcher = Box<dyn FnMut(&Row, &[Row]) -> Result<Vec<usize>>>;
+
+/**
+ * 连接的输出迭代器
+ * 右侧的数据在连接之前全部读取, 左侧的数据逐行拉取, 每一行的连接结果放入缓冲区
+ * 左侧读取完之后, 右外连接和全外连接再输出右侧没有匹配的行
+ */
+struct JoinIterator {
+    left: Rows,
+    //左右两侧的列数, 外连接时用于填充 NULL
+    widths: (usize, usize),
+    right: Vec<Row>,
+    right_matched: Vec<bool>,
+    join_type: JoinType,
+    matcher: Matcher,
+    buffer: VecDeque<Row>,
+    finished: bool,
+}
+
+impl JoinIterator {
+    fn new(
+        left: Rows,
+        widths: (usize, usize),
+        right: Vec<Row>,
+        join_type: JoinType,
+        matcher: Matcher,
+    ) -> Self {
+        Self {
+            left,
+            widths,
+            right_matched: vec![false; right.len()],
+            right,
+            join_type,
+            matcher,
+            buffer: VecDeque::new(),
+            finished: false,
+        }
+    }
+
+    fn join_row(&mut self, left_row: Row) -> Result<()> {
+        let matches = (self.matcher)(&left_row, &self.right)?;
+        //左外连接保留没有匹配的左侧行, 右侧填充 NULL
+        if matches.is_empty() && matches!(self.join_type, JoinType::Left | JoinType::Full) {
+            self.buffer.push_back(pad_right(left_row, self.widths.1));
+            return Ok(());
+        }
+        for i in matches {
+            self.right_matched[i] = true;
+            let mut row = left_row.clone();
+            row.extend(self.right[i].iter().cloned());
+            self.buffer.push_back(row);
+        }
+        Ok(())
+    }
+
+    //右外连接保留没有匹配的右侧行, 左侧填充 NULL
+    fn finish(&mut self) {
+        self.finished = true;
+        if matches!(self.join_type, JoinType::Right | JoinType::Full) {
+            let right = std::mem::take(&mut self.right);
+            for (right_row, matched) in right.into_iter().zip(&self.right_matched) {
+                if !matched {
+                    self.buffer.push_back(pad_left(right_row, self.widths.0));
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for JoinIterator {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.buffer.pop_front() {
+                return Some(Ok(row));
+            }
+            if self.finished {
+                return None;
+            }
+            match self.left.next() {
+                Some(Ok(left_row)) => {
+                    if let Err(err) = self.join_row(left_row) {
+                        return Some(Err(err));
+                    }
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => self.finish(),
+            }
+        }
+    }
+}
+
+fn scan_result(result: ResultSet) -> Result<(Vec<String>, Rows)> {
+    match result {
+        ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+        _ => Err(Error::Internal("unexpected result set".into())),
+    }
+}
+
+//哈希表的键, 和 = 运算的语义保持一致, NULL 和 NaN 不和任何值相等
+fn hash_key(value: &Value) -> Option<HashKey> {
+    match value {
+        Value::Null => None,
+        Value::Float(f) if f.is_nan() => None,
+        v => Some(HashKey::new(v)),
+    }
+}
+
+fn pad_right(mut row: Row, width: usize) -> Row {
+    row.extend(std::iter::repeat_n(Value::Null, width));
+    row
+}
+
+fn pad_left(row: Row, width: usize) -> Row {
+    let mut padded = vec![Value::Null; width];
+    padded.extend(row);
+    padded
+}
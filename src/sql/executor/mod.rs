@@ -1,43 +1,177 @@
-use mutation::Insert;
-use query::Scan;
-use schema::CreateTable;
+use aggregate::Aggregate;
+use join::{HashJoin, NestedLoopJoin};
+use mutation::{Delete, Insert, Update};
+use query::{Filter, IndexLookup, KeyScan, Limit, Nothing, Offset, Order, Projection, Scan};
+use schema::{AlterTable, CreateIndex, CreateTable, DropIndex, DropTable, TruncateTable};
+
+use std::fmt;
 
 use crate::error::Result;
 
 use super::{engine::Transaction, plan::Node, types::Row};
 
+/**
+ * 执行节点
+ * 查询类的节点返回行迭代器, 上层节点按需从下层拉取数据
+ * 例如 LIMIT 拿到足够的行之后就不再读取, 底层的扫描也随之停止
+ */
 pub trait Executor<T:Transaction> {
     fn execute(self : Box<Self>,txn:&mut T) -> Result<ResultSet>;
 }
 
+//执行节点输出的行迭代器
+pub trait RowIterator: Iterator<Item = Result<Row>> {}
+
+impl<I: Iterator<Item = Result<Row>>> RowIterator for I {}
+
+impl fmt::Debug for dyn RowIterator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RowIterator")
+    }
+}
+
+pub type Rows = Box<dyn RowIterator>;
+
+mod aggregate;
+mod join;
 mod mutation;
 mod schema;
 mod query;
 
-impl<T:Transaction> dyn Executor<T> {
+impl<T: Transaction + 'static> dyn Executor<T> {
     pub fn build(node: Node) -> Box<dyn Executor<T>> {
         match node {
             Node::CreateTable { schema } => CreateTable::new(schema),
+            Node::DropTable {
+                table_name,
+                if_exists,
+            } => DropTable::new(table_name, if_exists),
+            Node::TruncateTable { table_name } => TruncateTable::new(table_name),
+            Node::AlterTable {
+                table_name,
+                operation,
+            } => AlterTable::new(table_name, operation),
+            Node::CreateIndex { table_name, index } => CreateIndex::new(table_name, index),
+            Node::DropIndex { name } => DropIndex::new(name),
             Node::Insert {
                 table_name,
                 columns,
                 values,
             } => Insert::new(table_name, columns, values),
-            Node::Scan { table_name } => Scan::new(table_name),
+            Node::Update {
+                table_name,
+                source,
+                columns,
+            } => Update::new(table_name, Self::build(*source), columns),
+            Node::Delete { table_name, source } => Delete::new(table_name, Self::build(*source)),
+            Node::Scan { table_name, alias } => Scan::new(table_name, alias),
+            Node::KeyScan {
+                table_name,
+                alias,
+                start,
+                end,
+            } => KeyScan::new(table_name, alias, start, end),
+            Node::IndexLookup {
+                table_name,
+                alias,
+                column_name,
+                value,
+            } => IndexLookup::new(table_name, alias, column_name, value),
+            Node::NestedLoopJoin {
+                left,
+                right,
+                predicate,
+                join_type,
+            } => NestedLoopJoin::new(Self::build(*left), Self::build(*right), predicate, join_type),
+            Node::HashJoin {
+                left,
+                left_column,
+                right,
+                right_column,
+                join_type,
+            } => HashJoin::new(
+                Self::build(*left),
+                left_column,
+                Self::build(*right),
+                right_column,
+                join_type,
+            ),
+            Node::Nothing => Nothing::new(),
+            Node::Projection {
+                source,
+                expressions,
+            } => Projection::new(Self::build(*source), expressions),
+            Node::Aggregate {
+                source,
+                group_by,
+                aggregates,
+            } => Aggregate::new(Self::build(*source), group_by, aggregates),
+            Node::Order { source, order_by } => Order::new(Self::build(*source), order_by),
+            Node::Offset { source, offset } => Offset::new(Self::build(*source), offset),
+            Node::Limit { source, limit } => Limit::new(Self::build(*source), limit),
+            Node::Filter { source, predicate } => Filter::new(Self::build(*source), predicate),
         }
     }
 }
 
 #[derive(Debug)]
 pub enum ResultSet {
+    Begin {
+        version: u64,
+        read_only: bool,
+    },
+    Commit {
+        version: u64,
+    },
+    Rollback {
+        version: u64,
+    },
+    Savepoint {
+        name: String,
+    },
+    RollbackToSavepoint {
+        name: String,
+    },
+    ReleaseSavepoint {
+        name: String,
+    },
+    Vacuum {
+        count: usize,
+    },
+    ShowVersion {
+        version: u64,
+    },
     CreateTable {
         table_name: String,
     },
+    DropTable {
+        table_name: String,
+        dropped: bool,
+    },
+    TruncateTable {
+        table_name: String,
+        count: usize,
+    },
+    AlterTable {
+        table_name: String,
+    },
+    CreateIndex {
+        index_name: String,
+    },
+    DropIndex {
+        index_name: String,
+    },
     Insert {
         count: usize,
     },
+    Update {
+        count: usize,
+    },
+    Delete {
+        count: usize,
+    },
     Scan {
         columns: Vec<String>,
-        rows: Vec<Row>,
+        rows: Rows,
     },
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::{
     error::{Error, Result},
@@ -97,8 +97,8 @@ impl<T: Transaction> Executor<T> for Insert {
             //表达式转换为value
             let row = exprs
                 .into_iter()
-                .map(|it| Value::from_expression(it))
-                .collect::<Vec<_>>();
+                .map(Value::from_expression)
+                .collect::<Result<Vec<_>>>()?;
 
             let insert_row = if self.columns.is_empty() {
                 pad_row(&table, &row)?
@@ -114,3 +114,83 @@ impl<T: Transaction> Executor<T> for Insert {
         Ok(ResultSet::Insert { count })
     }
 }
+
+pub struct Update<T: Transaction> {
+    table_name: String,
+    source: Box<dyn Executor<T>>,
+    columns: BTreeMap<String, Expression>,
+}
+
+impl<T: Transaction> Update<T> {
+    pub fn new(
+        table_name: String,
+        source: Box<dyn Executor<T>>,
+        columns: BTreeMap<String, Expression>,
+    ) -> Box<Self> {
+        Box::new(Update {
+            table_name,
+            source,
+            columns,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Update<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let mut count = 0;
+        //先执行扫描, 拿到需要更新的行
+        //在修改之前读取所有的行, 避免扫描时读到本次更新写入的数据
+        match self.source.execute(txn)? {
+            ResultSet::Scan { columns, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                let table = txn.must_get_table(self.table_name.clone())?;
+                for row in rows {
+                    let id = table.get_primary_key(&row)?;
+                    let mut new_row = row.clone();
+                    //set 表达式基于更新前的行计算
+                    for (column_name, expr) in &self.columns {
+                        let index = table.get_column_index(column_name)?;
+                        new_row[index] = expr.evaluate(&columns, &row)?;
+                    }
+                    txn.update_row(&table, &id, new_row)?;
+                    count += 1;
+                }
+            }
+            _ => return Err(Error::Internal("unexpected result set".into())),
+        }
+
+        Ok(ResultSet::Update { count })
+    }
+}
+
+pub struct Delete<T: Transaction> {
+    table_name: String,
+    source: Box<dyn Executor<T>>,
+}
+
+impl<T: Transaction> Delete<T> {
+    pub fn new(table_name: String, source: Box<dyn Executor<T>>) -> Box<Self> {
+        Box::new(Delete { table_name, source })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Delete<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let mut count = 0;
+        //先执行扫描, 拿到需要删除的行
+        match self.source.execute(txn)? {
+            ResultSet::Scan { columns: _, rows } => {
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                let table = txn.must_get_table(self.table_name.clone())?;
+                for row in rows {
+                    let id = table.get_primary_key(&row)?;
+                    txn.delete_row(&table, &id)?;
+                    count += 1;
+                }
+            }
+            _ => return Err(Error::Internal("unexpected result set".into())),
+        }
+
+        Ok(ResultSet::Delete { count })
+    }
+}
@@ -1,14 +1,25 @@
-use crate::{error::Result, sql::engine::Transaction};
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::{Expression, OrderDirection},
+        schema::Table,
+        types::{Row, Value},
+    },
+};
 
-use super::{Executor, ResultSet};
+use std::{cmp::Ordering, ops::Bound};
+
+use super::{Executor, ResultSet, Rows};
 
 pub struct Scan {
     table_name: String,
+    alias: Option<String>,
 }
 
 impl Scan {
-    pub fn new(table_name: String) -> Box<Self> {
-        Box::new(Scan { table_name })
+    pub fn new(table_name: String, alias: Option<String>) -> Box<Self> {
+        Box::new(Scan { table_name, alias })
     }
 }
 
@@ -17,8 +28,393 @@ impl<T: Transaction> Executor<T> for Scan {
         let table = txn.must_get_table(self.table_name.clone())?;
         let rows = txn.scan_table(self.table_name.clone())?;
         Ok(ResultSet::Scan {
-                    columns: table.columns.into_iter().map(|it| it.name).collect(),
-                    rows,
+            columns: qualified_columns(&table, self.alias.as_ref()),
+            rows: Box::new(rows),
+        })
+    }
+}
+
+//扫描输出的列名, 形如 表名.列名, 有别名时使用别名
+fn qualified_columns(table: &Table, alias: Option<&String>) -> Vec<String> {
+    let qualifier = alias.unwrap_or(&table.name);
+    table
+        .columns
+        .iter()
+        .map(|it| format!("{}.{}", qualifier, it.name))
+        .collect()
+}
+
+pub struct KeyScan {
+    table_name: String,
+    alias: Option<String>,
+    start: Bound<Value>,
+    end: Bound<Value>,
+}
+
+impl KeyScan {
+    pub fn new(
+        table_name: String,
+        alias: Option<String>,
+        start: Bound<Value>,
+        end: Bound<Value>,
+    ) -> Box<Self> {
+        Box::new(KeyScan {
+            table_name,
+            alias,
+            start,
+            end,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for KeyScan {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        let rows = txn.scan_table_range(self.table_name.clone(), self.start, self.end)?;
+        Ok(ResultSet::Scan {
+            columns: qualified_columns(&table, self.alias.as_ref()),
+            rows: Box::new(rows),
+        })
+    }
+}
+
+pub struct IndexLookup {
+    table_name: String,
+    alias: Option<String>,
+    column_name: String,
+    value: Value,
+}
+
+impl IndexLookup {
+    pub fn new(
+        table_name: String,
+        alias: Option<String>,
+        column_name: String,
+        value: Value,
+    ) -> Box<Self> {
+        Box::new(IndexLookup {
+            table_name,
+            alias,
+            column_name,
+            value,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for IndexLookup {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let table = txn.must_get_table(self.table_name.clone())?;
+        //先从索引中获取主键, 再根据主键读取行数据
+        let pks = txn.read_by_index(self.table_name.clone(), self.column_name, &self.value)?;
+        let mut rows = Vec::new();
+        for pk in pks {
+            if let Some(row) = txn.read_by_id(self.table_name.clone(), &pk)? {
+                rows.push(row);
+            }
+        }
+        Ok(ResultSet::Scan {
+            columns: qualified_columns(&table, self.alias.as_ref()),
+            rows: Box::new(rows.into_iter().map(Ok)),
+        })
+    }
+}
+
+pub struct Nothing;
+
+impl Nothing {
+    pub fn new() -> Box<Self> {
+        Box::new(Nothing)
+    }
+}
+
+impl<T: Transaction> Executor<T> for Nothing {
+    fn execute(self: Box<Self>, _txn: &mut T) -> Result<ResultSet> {
+        Ok(ResultSet::Scan {
+            columns: Vec::new(),
+            rows: Box::new(std::iter::once(Ok(Vec::new()))),
+        })
+    }
+}
+
+pub struct Projection<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    expressions: Vec<(Expression, Option<String>)>,
+}
+
+impl<T: Transaction> Projection<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        expressions: Vec<(Expression, Option<String>)>,
+    ) -> Box<Self> {
+        Box::new(Projection {
+            source,
+            expressions,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Projection<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match self.source.execute(txn)? {
+            ResultSet::Scan { columns, rows } => {
+                //输出列名: 优先使用别名, 其次是列名, 最后是表达式的文本形式
+                let mut new_columns = Vec::with_capacity(self.expressions.len());
+                let mut expressions = Vec::with_capacity(self.expressions.len());
+                for (expr, alias) in self.expressions {
+                    new_columns.push(match (alias, &expr) {
+                        (Some(alias), _) => alias,
+                        (None, Expression::Field(_, name)) => name.clone(),
+                        (None, expr) => expr.to_string(),
+                    });
+                    expressions.push(expr);
+                }
+
+                let rows = rows.map(move |row| {
+                    let row = row?;
+                    expressions
+                        .iter()
+                        .map(|expr| expr.evaluate(&columns, &row))
+                        .collect()
+                });
+                Ok(ResultSet::Scan {
+                    columns: new_columns,
+                    rows: Box::new(rows),
                 })
+            }
+            _ => Err(Error::Internal("unexpected result set".into())),
+        }
+    }
+}
+
+pub struct Filter<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    predicate: Expression,
+}
+
+impl<T: Transaction> Filter<T> {
+    pub fn new(source: Box<dyn Executor<T>>, predicate: Expression) -> Box<Self> {
+        Box::new(Filter { source, predicate })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Filter<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match self.source.execute(txn)? {
+            ResultSet::Scan { columns, rows } => {
+                let predicate = self.predicate;
+                let names = columns.clone();
+                let rows = rows.filter_map(move |row| {
+                    row.and_then(|row| filter_row(&predicate, &names, row))
+                        .transpose()
+                });
+                Ok(ResultSet::Scan {
+                    columns,
+                    rows: Box::new(rows),
+                })
+            }
+            _ => Err(Error::Internal("unexpected result set".into())),
+        }
+    }
+}
+
+//只有条件为 TRUE 的行才会保留, FALSE 和 NULL 都会被过滤掉
+fn filter_row(predicate: &Expression, columns: &[String], row: Row) -> Result<Option<Row>> {
+    match predicate.evaluate(columns, &row)? {
+        Value::Boolean(true) => Ok(Some(row)),
+        Value::Boolean(false) | Value::Null => Ok(None),
+        v => Err(Error::Internal(format!(
+            "filter predicate returned {}, expected boolean",
+            v
+        ))),
+    }
+}
+
+pub struct Order<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    order_by: Vec<(Expression, OrderDirection, bool)>,
+}
+
+impl<T: Transaction> Order<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        order_by: Vec<(Expression, OrderDirection, bool)>,
+    ) -> Box<Self> {
+        Box::new(Order { source, order_by })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Order<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match self.source.execute(txn)? {
+            //排序需要读取所有的行
+            ResultSet::Scan { columns, rows } => {
+                //先计算每一行的排序键, 排序时不需要重复计算表达式
+                let mut keyed = Vec::new();
+                for row in rows {
+                    let row = row?;
+                    let mut keys = Vec::with_capacity(self.order_by.len());
+                    for (expr, _, _) in &self.order_by {
+                        keys.push(expr.evaluate(&columns, &row)?);
+                    }
+                    keyed.push((keys, row));
+                }
+
+                //稳定排序, 排序键相同的行保持原来的顺序
+                keyed.sort_by(|(l, _), (r, _)| {
+                    for ((lv, rv), (_, direction, nulls_first)) in
+                        l.iter().zip(r.iter()).zip(self.order_by.iter())
+                    {
+                        let ordering = match (lv, rv) {
+                            (Value::Null, Value::Null) => Ordering::Equal,
+                            //NULL 的位置不受排序方向影响
+                            (Value::Null, _) if *nulls_first => return Ordering::Less,
+                            (Value::Null, _) => return Ordering::Greater,
+                            (_, Value::Null) if *nulls_first => return Ordering::Greater,
+                            (_, Value::Null) => return Ordering::Less,
+                            (lv, rv) => match direction {
+                                OrderDirection::Asc => lv.total_cmp(rv),
+                                OrderDirection::Desc => rv.total_cmp(lv),
+                            },
+                        };
+                        if ordering != Ordering::Equal {
+                            return ordering;
+                        }
+                    }
+                    Ordering::Equal
+                });
+
+                Ok(ResultSet::Scan {
+                    columns,
+                    rows: Box::new(keyed.into_iter().map(|(_, row)| Ok(row))),
+                })
+            }
+            _ => Err(Error::Internal("unexpected result set".into())),
+        }
+    }
+}
+
+pub struct Offset<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    offset: usize,
+}
+
+impl<T: Transaction> Offset<T> {
+    pub fn new(source: Box<dyn Executor<T>>, offset: usize) -> Box<Self> {
+        Box::new(Offset { source, offset })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Offset<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match self.source.execute(txn)? {
+            ResultSet::Scan { columns, rows } => Ok(ResultSet::Scan {
+                columns,
+                rows: skip_rows(rows, self.offset),
+            }),
+            _ => Err(Error::Internal("unexpected result set".into())),
+        }
+    }
+}
+
+//跳过前 offset 行, 读取过程中的错误不会被跳过
+fn skip_rows(rows: Rows, offset: usize) -> Rows {
+    let mut skipped = 0;
+    Box::new(rows.filter(move |row| {
+        if row.is_err() || skipped >= offset {
+            return true;
+        }
+        skipped += 1;
+        false
+    }))
+}
+
+pub struct Limit<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    limit: usize,
+}
+
+impl<T: Transaction> Limit<T> {
+    pub fn new(source: Box<dyn Executor<T>>, limit: usize) -> Box<Self> {
+        Box::new(Limit { source, limit })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Limit<T> {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        match self.source.execute(txn)? {
+            //读取到 limit 行之后不再从下层拉取数据
+            ResultSet::Scan { columns, rows } => Ok(ResultSet::Scan {
+                columns,
+                rows: Box::new(rows.take(self.limit)),
+            }),
+            _ => Err(Error::Internal("unexpected result set".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{
+        error::Result,
+        sql::{
+            engine::{kv::KVEngine, Engine, Transaction},
+            executor::{Executor, ResultSet},
+        },
+        storage::memory::MemoryEngine,
+    };
+
+    use super::{Limit, Scan};
+
+    //记录从下层节点拉取的行数
+    struct Counted<T: Transaction> {
+        source: Box<dyn Executor<T>>,
+        pulled: Rc<Cell<usize>>,
+    }
+
+    impl<T: Transaction> Executor<T> for Counted<T> {
+        fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+            match self.source.execute(txn)? {
+                ResultSet::Scan { columns, rows } => {
+                    let pulled = self.pulled;
+                    Ok(ResultSet::Scan {
+                        columns,
+                        rows: Box::new(rows.inspect(move |_| pulled.set(pulled.get() + 1))),
+                    })
+                }
+                r => Ok(r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_limit_stops_pulling() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key);")?;
+        let values = (0..200).map(|i| format!("({})", i)).collect::<Vec<_>>();
+        s.execute(&format!("insert into t1 values {};", values.join(", ")))?;
+
+        let mut txn = kvengine.begin()?;
+        let pulled = Rc::new(Cell::new(0));
+        let source = Box::new(Counted {
+            source: Scan::new("t1".into(), None),
+            pulled: pulled.clone(),
+        });
+        let mut rows = match Limit::new(source, 3).execute(&mut txn)? {
+            ResultSet::Scan { rows, .. } => rows,
+            r => panic!("unexpected result {:?}", r),
+        };
+
+        //执行时不读取数据, 每次拉取一行
+        assert_eq!(pulled.get(), 0);
+        assert!(rows.next().transpose()?.is_some());
+        assert_eq!(pulled.get(), 1);
+
+        //拿够 limit 行之后不再从 Scan 拉取
+        assert_eq!(rows.count(), 2);
+        assert_eq!(pulled.get(), 3);
+        txn.commit()
     }
 }
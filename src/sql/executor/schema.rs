@@ -1,6 +1,9 @@
 use crate::{
     error::Result,
-    sql::{engine::Transaction, schema::Table},
+    sql::{
+        engine::Transaction,
+        schema::{AlterTable as AlterTableOperation, Index, Table},
+    },
 };
 
 use super::{Executor, ResultSet};
@@ -22,3 +25,115 @@ impl<T: Transaction> Executor<T> for CreateTable {
         Ok(ResultSet::CreateTable { table_name })
     }
 }
+
+pub struct DropTable {
+    table_name: String,
+    if_exists: bool,
+}
+
+impl DropTable {
+    pub fn new(table_name: String, if_exists: bool) -> Box<Self> {
+        Box::new(DropTable {
+            table_name,
+            if_exists,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        //if exists 时表不存在不报错
+        if self.if_exists && txn.get_table(self.table_name.clone())?.is_none() {
+            return Ok(ResultSet::DropTable {
+                table_name: self.table_name,
+                dropped: false,
+            });
+        }
+        txn.drop_table(self.table_name.clone())?;
+        Ok(ResultSet::DropTable {
+            table_name: self.table_name,
+            dropped: true,
+        })
+    }
+}
+
+pub struct TruncateTable {
+    table_name: String,
+}
+
+impl TruncateTable {
+    pub fn new(table_name: String) -> Box<Self> {
+        Box::new(TruncateTable { table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for TruncateTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let count = txn.truncate_table(self.table_name.clone())?;
+        Ok(ResultSet::TruncateTable {
+            table_name: self.table_name,
+            count,
+        })
+    }
+}
+
+pub struct AlterTable {
+    table_name: String,
+    operation: AlterTableOperation,
+}
+
+impl AlterTable {
+    pub fn new(table_name: String, operation: AlterTableOperation) -> Box<Self> {
+        Box::new(AlterTable {
+            table_name,
+            operation,
+        })
+    }
+}
+
+impl<T: Transaction> Executor<T> for AlterTable {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.alter_table(self.table_name.clone(), self.operation)?;
+        Ok(ResultSet::AlterTable {
+            table_name: self.table_name,
+        })
+    }
+}
+
+pub struct CreateIndex {
+    table_name: String,
+    index: Index,
+}
+
+impl CreateIndex {
+    pub fn new(table_name: String, index: Index) -> Box<Self> {
+        Box::new(CreateIndex { table_name, index })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateIndex {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        let index_name = self.index.name.clone();
+        txn.create_index(self.table_name, self.index)?;
+        Ok(ResultSet::CreateIndex { index_name })
+    }
+}
+
+pub struct DropIndex {
+    name: String,
+}
+
+impl DropIndex {
+    pub fn new(name: String) -> Box<Self> {
+        Box::new(DropIndex { name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropIndex {
+    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+        txn.drop_index(self.name.clone())?;
+        Ok(ResultSet::DropIndex {
+            index_name: self.name,
+        })
+    }
+}
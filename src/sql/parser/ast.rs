@@ -1,32 +1,159 @@
-use crate::sql::types::DataType;
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt::{self, Display},
+};
 
+use crate::{
+    error::{Error, Result},
+    sql::types::{DataType, Row, Value},
+};
+
+//语句只在解析和构建执行计划时短暂存在, 不需要为了变体大小装箱
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug,PartialEq)]
 pub enum Statement{
+    //事务控制
+    Begin{
+        read_only:bool,
+        //可串行化隔离级别, 默认为快照隔离
+        serializable:bool
+    },
+    Commit,
+    Rollback,
+    Savepoint{
+        name:String
+    },
+    RollbackToSavepoint{
+        name:String
+    },
+    ReleaseSavepoint{
+        name:String
+    },
+    //回收旧版本数据
+    Vacuum,
+    //查看当前版本号
+    ShowVersion,
     CreateTable{
         name:String,
         columns:Vec<Column>
     },
+    DropTable{
+        name:String,
+        if_exists:bool
+    },
+    TruncateTable{
+        name:String
+    },
+    AlterTable{
+        name:String,
+        operation:AlterTableOperation
+    },
+    CreateIndex{
+        name:String,
+        table_name:String,
+        column_name:String,
+        unique:bool
+    },
+    DropIndex{
+        name:String
+    },
     Insert{
         table_name:String,
         columns:Option<Vec<String>>,
         values:Vec<Vec<Expression>>
     },
+    //select 列表为空时表示 select *
     Select{
-        table_name:String
+        select:Vec<(Expression,Option<String>)>,
+        from:Option<FromItem>,
+        //查询历史版本的数据
+        as_of:Option<u64>,
+        where_clause:Option<Expression>,
+        group_by:Vec<Expression>,
+        having:Option<Expression>,
+        order_by:Vec<OrderBy>,
+        limit:Option<Expression>,
+        offset:Option<Expression>
+    },
+    Update{
+        table_name:String,
+        columns:BTreeMap<String,Expression>,
+        where_clause:Option<Expression>
+    },
+    Delete{
+        table_name:String,
+        where_clause:Option<Expression>
     }
 }
 
+#[derive(Debug,PartialEq)]
+pub enum AlterTableOperation{
+    AddColumn(Column),
+    DropColumn(String),
+    RenameColumn{
+        old_name:String,
+        new_name:String
+    },
+    RenameTable(String)
+}
+
+//from 子句中的数据源, 可以是表或者两个数据源的连接
+#[derive(Debug,PartialEq)]
+pub enum FromItem{
+    Table{
+        name:String,
+        alias:Option<String>
+    },
+    Join{
+        left:Box<FromItem>,
+        right:Box<FromItem>,
+        join_type:JoinType,
+        predicate:Option<Expression>
+    }
+}
+
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum JoinType{
+    Cross,
+    Inner,
+    Left,
+    Right,
+    Full
+}
+
+#[derive(Debug,PartialEq,Clone,Copy)]
+pub enum OrderDirection{
+    Asc,
+    Desc
+}
+
+//排序项, nulls_first 为空时 ASC 默认 NULL 排在最后, DESC 默认 NULL 排在最前
+#[derive(Debug,PartialEq)]
+pub struct OrderBy{
+    pub expr:Expression,
+    pub direction:OrderDirection,
+    pub nulls_first:Option<bool>
+}
+
 #[derive(Debug,PartialEq)]
 pub struct Column{
     pub name:String,
     pub datatype:DataType,
     pub nullable:Option<bool>,
-    pub default:Option<Expression>
+    pub default:Option<Expression>,
+    pub primary_key:bool
 }
 
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone)]
 pub enum Expression{
-    Consts(Consts)
+    Consts(Consts),
+    //列名, 可以带上表名或者表的别名
+    Field(Option<String>,String),
+    //运算表达式
+    Operation(Operation),
+    //函数调用, 函数名统一为大写, COUNT(*) 的参数为空
+    Function(String,Vec<Expression>)
 }
 
 impl From<Consts> for Expression{
@@ -35,11 +162,340 @@ impl From<Consts> for Expression{
     }
 }
 
-#[derive(Debug,PartialEq)]
+impl From<Operation> for Expression{
+    fn from(value: Operation) -> Self {
+        Self::Operation(value)
+    }
+}
+
+#[derive(Debug,PartialEq,Clone)]
 pub enum Consts{
     Null,
     Boolean(bool),
     Integer(i64),
     Float(f64),
     String(String)
-}
\ No newline at end of file
+}
+
+#[derive(Debug,PartialEq,Clone)]
+pub enum Operation{
+    //逻辑运算
+    And(Box<Expression>,Box<Expression>),
+    Or(Box<Expression>,Box<Expression>),
+    Not(Box<Expression>),
+
+    //比较运算
+    Equal(Box<Expression>,Box<Expression>),
+    NotEqual(Box<Expression>,Box<Expression>),
+    GreaterThan(Box<Expression>,Box<Expression>),
+    GreaterThanOrEqual(Box<Expression>,Box<Expression>),
+    LessThan(Box<Expression>,Box<Expression>),
+    LessThanOrEqual(Box<Expression>,Box<Expression>),
+    IsNull(Box<Expression>),
+
+    //算术运算
+    Add(Box<Expression>,Box<Expression>),
+    Subtract(Box<Expression>,Box<Expression>),
+    Multiply(Box<Expression>,Box<Expression>),
+    Divide(Box<Expression>,Box<Expression>),
+    Modulo(Box<Expression>,Box<Expression>),
+    Negate(Box<Expression>),
+    Identity(Box<Expression>),
+}
+
+impl Expression {
+    /**
+     * 根据行数据计算表达式的值
+     * columns 为行中每一列的名称, 用于解析表达式中引用的列
+     * 逻辑运算遵循 SQL 的三值逻辑, NULL 表示未知
+     */
+    pub fn evaluate(&self, columns: &[String], row: &Row) -> Result<Value> {
+        Ok(match self {
+            Expression::Consts(Consts::Null) => Value::Null,
+            Expression::Consts(Consts::Boolean(b)) => Value::Boolean(*b),
+            Expression::Consts(Consts::Integer(i)) => Value::Integer(*i),
+            Expression::Consts(Consts::Float(f)) => Value::Float(*f),
+            Expression::Consts(Consts::String(s)) => Value::String(s.clone()),
+            Expression::Field(table, name) => {
+                let index = Self::resolve_field(columns, table.as_deref(), name)?;
+                row.get(index).cloned().ok_or(Error::Internal(format!(
+                    "column {} out of row bounds",
+                    name
+                )))?
+            }
+            Expression::Operation(op) => Self::evaluate_operation(op, columns, row)?,
+            //聚集函数由 Aggregate 节点计算, 不能直接求值
+            Expression::Function(name, _) => {
+                return Err(Error::Internal(format!(
+                    "aggregate function {} is not allowed here",
+                    name
+                )))
+            }
+        })
+    }
+
+    /**
+     * 在列名中查找列的位置
+     * 扫描输出的列名带有表名, 形如 t.a, 不带表名的列引用在所有表中查找, 有多个匹配时报错
+     * 完全相同的列名优先匹配, 比如聚集节点输出的 COUNT(*)
+     */
+    pub fn resolve_field(columns: &[String], table: Option<&str>, name: &str) -> Result<usize> {
+        let matches: Vec<usize> = match table {
+            Some(table) => {
+                let qualified = format!("{}.{}", table, name);
+                columns
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, it)| **it == qualified)
+                    .map(|(i, _)| i)
+                    .collect()
+            }
+            None => match columns.iter().position(|it| it == name) {
+                Some(i) => vec![i],
+                None => columns
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, it)| it.split_once('.').is_some_and(|(_, c)| c == name))
+                    .map(|(i, _)| i)
+                    .collect(),
+            },
+        };
+
+        let field = match table {
+            Some(table) => format!("{}.{}", table, name),
+            None => name.to_string(),
+        };
+        match matches[..] {
+            [i] => Ok(i),
+            [] => Err(Error::Internal(format!("column {} does not exists", field))),
+            _ => Err(Error::Internal(format!("column {} is ambiguous", field))),
+        }
+    }
+
+    //是否包含聚集函数
+    pub fn contains_aggregate(&self) -> bool {
+        let mut found = false;
+        self.walk(&mut |expr| found |= matches!(expr, Expression::Function(..)));
+        found
+    }
+
+    //先序遍历表达式树
+    pub fn walk<F: FnMut(&Expression)>(&self, f: &mut F) {
+        f(self);
+        match self {
+            Expression::Operation(op) => op.children().into_iter().for_each(|it| it.walk(f)),
+            Expression::Function(_, args) => args.iter().for_each(|it| it.walk(f)),
+            Expression::Consts(_) | Expression::Field(..) => {}
+        }
+    }
+
+    /**
+     * 自顶向下改写表达式树
+     * f 返回 None 时继续改写子表达式, 返回 Some 时用返回值替换当前节点
+     */
+    pub fn transform<F: FnMut(&Expression) -> Option<Expression>>(self, f: &mut F) -> Expression {
+        if let Some(expr) = f(&self) {
+            return expr;
+        }
+        match self {
+            Expression::Operation(op) => Expression::Operation(op.map_children(f)),
+            Expression::Function(name, args) => {
+                Expression::Function(name, args.into_iter().map(|it| it.transform(f)).collect())
+            }
+            expr => expr,
+        }
+    }
+
+    fn evaluate_operation(op: &Operation, columns: &[String], row: &Row) -> Result<Value> {
+        use Value::*;
+        Ok(match op {
+            Operation::And(lhs, rhs) => {
+                match (lhs.evaluate(columns, row)?, rhs.evaluate(columns, row)?) {
+                    (Boolean(false), Boolean(_) | Null) | (Boolean(_) | Null, Boolean(false)) => {
+                        Boolean(false)
+                    }
+                    (Boolean(true), Boolean(true)) => Boolean(true),
+                    (Boolean(true) | Null, Null) | (Null, Boolean(true)) => Null,
+                    (l, r) => return Err(Error::Internal(format!("can not and {} and {}", l, r))),
+                }
+            }
+            Operation::Or(lhs, rhs) => {
+                match (lhs.evaluate(columns, row)?, rhs.evaluate(columns, row)?) {
+                    (Boolean(true), Boolean(_) | Null) | (Boolean(_) | Null, Boolean(true)) => {
+                        Boolean(true)
+                    }
+                    (Boolean(false), Boolean(false)) => Boolean(false),
+                    (Boolean(false) | Null, Null) | (Null, Boolean(false)) => Null,
+                    (l, r) => return Err(Error::Internal(format!("can not or {} and {}", l, r))),
+                }
+            }
+            Operation::Not(expr) => match expr.evaluate(columns, row)? {
+                Boolean(b) => Boolean(!b),
+                Null => Null,
+                v => return Err(Error::Internal(format!("can not negate {}", v))),
+            },
+            Operation::Equal(lhs, rhs) => Self::compare(lhs, rhs, columns, row, |o| o.is_eq())?,
+            Operation::NotEqual(lhs, rhs) => Self::compare(lhs, rhs, columns, row, |o| o.is_ne())?,
+            Operation::GreaterThan(lhs, rhs) => {
+                Self::compare(lhs, rhs, columns, row, |o| o.is_gt())?
+            }
+            Operation::GreaterThanOrEqual(lhs, rhs) => {
+                Self::compare(lhs, rhs, columns, row, |o| o.is_ge())?
+            }
+            Operation::LessThan(lhs, rhs) => Self::compare(lhs, rhs, columns, row, |o| o.is_lt())?,
+            Operation::LessThanOrEqual(lhs, rhs) => {
+                Self::compare(lhs, rhs, columns, row, |o| o.is_le())?
+            }
+            Operation::IsNull(expr) => Boolean(expr.evaluate(columns, row)? == Null),
+            Operation::Add(lhs, rhs) => lhs
+                .evaluate(columns, row)?
+                .checked_add(&rhs.evaluate(columns, row)?)?,
+            Operation::Subtract(lhs, rhs) => lhs
+                .evaluate(columns, row)?
+                .checked_sub(&rhs.evaluate(columns, row)?)?,
+            Operation::Multiply(lhs, rhs) => lhs
+                .evaluate(columns, row)?
+                .checked_mul(&rhs.evaluate(columns, row)?)?,
+            Operation::Divide(lhs, rhs) => lhs
+                .evaluate(columns, row)?
+                .checked_div(&rhs.evaluate(columns, row)?)?,
+            Operation::Modulo(lhs, rhs) => lhs
+                .evaluate(columns, row)?
+                .checked_rem(&rhs.evaluate(columns, row)?)?,
+            Operation::Negate(expr) => match expr.evaluate(columns, row)? {
+                Integer(i) => Integer(
+                    i.checked_neg()
+                        .ok_or(Error::Internal("integer overflow".into()))?,
+                ),
+                Float(f) => Float(-f),
+                Null => Null,
+                v => return Err(Error::Internal(format!("can not negate {}", v))),
+            },
+            Operation::Identity(expr) => match expr.evaluate(columns, row)? {
+                v @ (Integer(_) | Float(_) | Null) => v,
+                v => return Err(Error::Internal(format!("invalid operand {}", v))),
+            },
+        })
+    }
+
+    //比较运算, 任意一侧为 NULL 时结果为 NULL
+    fn compare<F: Fn(Ordering) -> bool>(
+        lhs: &Expression,
+        rhs: &Expression,
+        columns: &[String],
+        row: &Row,
+        f: F,
+    ) -> Result<Value> {
+        let l = lhs.evaluate(columns, row)?;
+        let r = rhs.evaluate(columns, row)?;
+        Ok(match l.compare(&r)? {
+            Some(ordering) => Value::Boolean(f(ordering)),
+            None => Value::Null,
+        })
+    }
+}
+
+impl Operation {
+    fn children(&self) -> Vec<&Expression> {
+        match self {
+            Operation::And(l, r)
+            | Operation::Or(l, r)
+            | Operation::Equal(l, r)
+            | Operation::NotEqual(l, r)
+            | Operation::GreaterThan(l, r)
+            | Operation::GreaterThanOrEqual(l, r)
+            | Operation::LessThan(l, r)
+            | Operation::LessThanOrEqual(l, r)
+            | Operation::Add(l, r)
+            | Operation::Subtract(l, r)
+            | Operation::Multiply(l, r)
+            | Operation::Divide(l, r)
+            | Operation::Modulo(l, r) => vec![l, r],
+            Operation::Not(expr)
+            | Operation::IsNull(expr)
+            | Operation::Negate(expr)
+            | Operation::Identity(expr) => vec![expr],
+        }
+    }
+
+    fn map_children<F: FnMut(&Expression) -> Option<Expression>>(self, f: &mut F) -> Self {
+        let mut m = |expr: Box<Expression>| Box::new(expr.transform(f));
+        match self {
+            Operation::And(l, r) => Operation::And(m(l), m(r)),
+            Operation::Or(l, r) => Operation::Or(m(l), m(r)),
+            Operation::Not(expr) => Operation::Not(m(expr)),
+            Operation::Equal(l, r) => Operation::Equal(m(l), m(r)),
+            Operation::NotEqual(l, r) => Operation::NotEqual(m(l), m(r)),
+            Operation::GreaterThan(l, r) => Operation::GreaterThan(m(l), m(r)),
+            Operation::GreaterThanOrEqual(l, r) => Operation::GreaterThanOrEqual(m(l), m(r)),
+            Operation::LessThan(l, r) => Operation::LessThan(m(l), m(r)),
+            Operation::LessThanOrEqual(l, r) => Operation::LessThanOrEqual(m(l), m(r)),
+            Operation::IsNull(expr) => Operation::IsNull(m(expr)),
+            Operation::Add(l, r) => Operation::Add(m(l), m(r)),
+            Operation::Subtract(l, r) => Operation::Subtract(m(l), m(r)),
+            Operation::Multiply(l, r) => Operation::Multiply(m(l), m(r)),
+            Operation::Divide(l, r) => Operation::Divide(m(l), m(r)),
+            Operation::Modulo(l, r) => Operation::Modulo(m(l), m(r)),
+            Operation::Negate(expr) => Operation::Negate(m(expr)),
+            Operation::Identity(expr) => Operation::Identity(m(expr)),
+        }
+    }
+}
+
+//表达式的文本形式, 用作 select 列表中没有别名时的列名
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::Consts(Consts::Null) => write!(f, "NULL"),
+            Expression::Consts(Consts::Boolean(b)) if *b => write!(f, "TRUE"),
+            Expression::Consts(Consts::Boolean(_)) => write!(f, "FALSE"),
+            Expression::Consts(Consts::Integer(i)) => write!(f, "{}", i),
+            Expression::Consts(Consts::Float(v)) => write!(f, "{}", Value::Float(*v)),
+            Expression::Consts(Consts::String(s)) => write!(f, "'{}'", s),
+            Expression::Field(Some(table), name) => write!(f, "{}.{}", table, name),
+            Expression::Field(None, name) => write!(f, "{}", name),
+            Expression::Operation(op) => write!(f, "{}", op),
+            Expression::Function(name, args) if args.is_empty() => write!(f, "{}(*)", name),
+            Expression::Function(name, args) => {
+                let args = args.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        //嵌套的运算加上括号, 避免丢失优先级信息
+        let operand = |expr: &Expression| match expr {
+            Expression::Operation(op) => format!("({})", op),
+            expr => expr.to_string(),
+        };
+        //前缀运算的操作数是负数常量时也加上括号, 避免两个负号连在一起
+        let prefix_operand = |expr: &Expression| match expr {
+            Expression::Consts(Consts::Integer(i)) if *i < 0 => format!("({})", expr),
+            Expression::Consts(Consts::Float(v)) if v.is_sign_negative() => format!("({})", expr),
+            expr => operand(expr),
+        };
+        let (l, op, r) = match self {
+            Operation::And(l, r) => (l, "AND", r),
+            Operation::Or(l, r) => (l, "OR", r),
+            Operation::Equal(l, r) => (l, "=", r),
+            Operation::NotEqual(l, r) => (l, "!=", r),
+            Operation::GreaterThan(l, r) => (l, ">", r),
+            Operation::GreaterThanOrEqual(l, r) => (l, ">=", r),
+            Operation::LessThan(l, r) => (l, "<", r),
+            Operation::LessThanOrEqual(l, r) => (l, "<=", r),
+            Operation::Add(l, r) => (l, "+", r),
+            Operation::Subtract(l, r) => (l, "-", r),
+            Operation::Multiply(l, r) => (l, "*", r),
+            Operation::Divide(l, r) => (l, "/", r),
+            Operation::Modulo(l, r) => (l, "%", r),
+            Operation::Not(expr) => return write!(f, "NOT {}", operand(expr)),
+            Operation::IsNull(expr) => return write!(f, "{} IS NULL", operand(expr)),
+            Operation::Negate(expr) => return write!(f, "-{}", prefix_operand(expr)),
+            Operation::Identity(expr) => return write!(f, "+{}", prefix_operand(expr)),
+        };
+        write!(f, "{} {} {}", operand(l), op, operand(r))
+    }
+}
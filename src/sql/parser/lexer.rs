@@ -18,6 +18,8 @@ pub enum Token {
     CloseParen,
     // 逗号 ,
     Comma,
+    // 句点 .
+    Period,
     // 分号 ;
     Semicolon,
     // 星号 *
@@ -28,6 +30,20 @@ pub enum Token {
     Minus,
     // 斜杠 /
     Slash,
+    // 百分号 %
+    Percent,
+    // 等于 =
+    Equal,
+    // 不等于 != 或 <>
+    NotEqual,
+    // 小于 <
+    LessThan,
+    // 小于等于 <=
+    LessThanOrEqual,
+    // 大于 >
+    GreaterThan,
+    // 大于等于 >=
+    GreaterThanOrEqual,
 }
 
 impl Display for Token {
@@ -40,11 +56,19 @@ impl Display for Token {
             Token::OpenParen => "(",
             Token::CloseParen => ")",
             Token::Comma => ",",
+            Token::Period => ".",
             Token::Semicolon => ";",
             Token::Asterisk => "*",
             Token::Plus => "+",
             Token::Minus => "-",
             Token::Slash => "/",
+            Token::Percent => "%",
+            Token::Equal => "=",
+            Token::NotEqual => "!=",
+            Token::LessThan => "<",
+            Token::LessThanOrEqual => "<=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanOrEqual => ">=",
         })
     }
 }
@@ -74,6 +98,61 @@ pub enum Keyword {
     Null,
     Primary,
     Key,
+    Where,
+    And,
+    Or,
+    Is,
+    Update,
+    Set,
+    Delete,
+    Index,
+    Unique,
+    On,
+    Drop,
+    As,
+    Order,
+    By,
+    Asc,
+    Desc,
+    Nulls,
+    First,
+    Last,
+    Limit,
+    Offset,
+    Group,
+    Having,
+    Join,
+    Inner,
+    Left,
+    Right,
+    Full,
+    Outer,
+    Cross,
+    If,
+    Exists,
+    Truncate,
+    Alter,
+    Add,
+    Column,
+    Rename,
+    To,
+    Begin,
+    Commit,
+    Rollback,
+    Transaction,
+    Read,
+    Only,
+    Write,
+    Savepoint,
+    Release,
+    Isolation,
+    Level,
+    Snapshot,
+    Serializable,
+    Vacuum,
+    Of,
+    Version,
+    Show,
 }
 
 impl Keyword {
@@ -102,10 +181,92 @@ impl Keyword {
             "NULL" => Keyword::Null,
             "PRIMARY" => Keyword::Primary,
             "KEY" => Keyword::Key,
+            "WHERE" => Keyword::Where,
+            "AND" => Keyword::And,
+            "OR" => Keyword::Or,
+            "IS" => Keyword::Is,
+            "UPDATE" => Keyword::Update,
+            "SET" => Keyword::Set,
+            "DELETE" => Keyword::Delete,
+            "INDEX" => Keyword::Index,
+            "UNIQUE" => Keyword::Unique,
+            "ON" => Keyword::On,
+            "DROP" => Keyword::Drop,
+            "AS" => Keyword::As,
+            "ORDER" => Keyword::Order,
+            "BY" => Keyword::By,
+            "ASC" => Keyword::Asc,
+            "DESC" => Keyword::Desc,
+            "NULLS" => Keyword::Nulls,
+            "FIRST" => Keyword::First,
+            "LAST" => Keyword::Last,
+            "LIMIT" => Keyword::Limit,
+            "OFFSET" => Keyword::Offset,
+            "GROUP" => Keyword::Group,
+            "HAVING" => Keyword::Having,
+            "JOIN" => Keyword::Join,
+            "INNER" => Keyword::Inner,
+            "LEFT" => Keyword::Left,
+            "RIGHT" => Keyword::Right,
+            "FULL" => Keyword::Full,
+            "OUTER" => Keyword::Outer,
+            "CROSS" => Keyword::Cross,
+            "IF" => Keyword::If,
+            "EXISTS" => Keyword::Exists,
+            "TRUNCATE" => Keyword::Truncate,
+            "ALTER" => Keyword::Alter,
+            "ADD" => Keyword::Add,
+            "COLUMN" => Keyword::Column,
+            "RENAME" => Keyword::Rename,
+            "TO" => Keyword::To,
+            "BEGIN" => Keyword::Begin,
+            "COMMIT" => Keyword::Commit,
+            "ROLLBACK" => Keyword::Rollback,
+            "TRANSACTION" => Keyword::Transaction,
+            "READ" => Keyword::Read,
+            "ONLY" => Keyword::Only,
+            "WRITE" => Keyword::Write,
+            "SAVEPOINT" => Keyword::Savepoint,
+            "RELEASE" => Keyword::Release,
+            "ISOLATION" => Keyword::Isolation,
+            "LEVEL" => Keyword::Level,
+            "SNAPSHOT" => Keyword::Snapshot,
+            "SERIALIZABLE" => Keyword::Serializable,
+            "VACUUM" => Keyword::Vacuum,
+            "OF" => Keyword::Of,
+            "VERSION" => Keyword::Version,
+            "SHOW" => Keyword::Show,
             _ => return None,
         })
     }
 
+    /**
+     * 是否为保留关键字
+     * 非保留关键字只在语句的特定位置有特殊含义, 词法分析时作为标识符返回, 可以用作表名、列名
+     */
+    pub fn is_reserved(&self) -> bool {
+        !matches!(
+            self,
+            Keyword::Nulls
+                | Keyword::First
+                | Keyword::Last
+                | Keyword::Add
+                | Keyword::Column
+                | Keyword::Rename
+                | Keyword::Transaction
+                | Keyword::Read
+                | Keyword::Only
+                | Keyword::Write
+                | Keyword::Release
+                | Keyword::Isolation
+                | Keyword::Level
+                | Keyword::Snapshot
+                | Keyword::Serializable
+                | Keyword::Version
+                | Keyword::Show
+        )
+    }
+
     pub fn to_str(&self) -> &str {
         match self {
             Keyword::Create => "CREATE",
@@ -131,6 +292,61 @@ impl Keyword {
             Keyword::Null => "NULL",
             Keyword::Primary => "PRIMARY",
             Keyword::Key => "KEY",
+            Keyword::Where => "WHERE",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Is => "IS",
+            Keyword::Update => "UPDATE",
+            Keyword::Set => "SET",
+            Keyword::Delete => "DELETE",
+            Keyword::Index => "INDEX",
+            Keyword::Unique => "UNIQUE",
+            Keyword::On => "ON",
+            Keyword::Drop => "DROP",
+            Keyword::As => "AS",
+            Keyword::Order => "ORDER",
+            Keyword::By => "BY",
+            Keyword::Asc => "ASC",
+            Keyword::Desc => "DESC",
+            Keyword::Nulls => "NULLS",
+            Keyword::First => "FIRST",
+            Keyword::Last => "LAST",
+            Keyword::Limit => "LIMIT",
+            Keyword::Offset => "OFFSET",
+            Keyword::Group => "GROUP",
+            Keyword::Having => "HAVING",
+            Keyword::Join => "JOIN",
+            Keyword::Inner => "INNER",
+            Keyword::Left => "LEFT",
+            Keyword::Right => "RIGHT",
+            Keyword::Full => "FULL",
+            Keyword::Outer => "OUTER",
+            Keyword::Cross => "CROSS",
+            Keyword::If => "IF",
+            Keyword::Exists => "EXISTS",
+            Keyword::Truncate => "TRUNCATE",
+            Keyword::Alter => "ALTER",
+            Keyword::Add => "ADD",
+            Keyword::Column => "COLUMN",
+            Keyword::Rename => "RENAME",
+            Keyword::To => "TO",
+            Keyword::Begin => "BEGIN",
+            Keyword::Commit => "COMMIT",
+            Keyword::Rollback => "ROLLBACK",
+            Keyword::Transaction => "TRANSACTION",
+            Keyword::Read => "READ",
+            Keyword::Only => "ONLY",
+            Keyword::Write => "WRITE",
+            Keyword::Savepoint => "SAVEPOINT",
+            Keyword::Release => "RELEASE",
+            Keyword::Isolation => "ISOLATION",
+            Keyword::Level => "LEVEL",
+            Keyword::Snapshot => "SNAPSHOT",
+            Keyword::Serializable => "SERIALIZABLE",
+            Keyword::Vacuum => "VACUUM",
+            Keyword::Of => "OF",
+            Keyword::Version => "VERSION",
+            Keyword::Show => "SHOW",
         }
     }
 }
@@ -141,6 +357,7 @@ impl Display for Keyword {
     }
 }
 
+#[derive(Clone)]
 pub struct Lexer<'a> {
     iter: Peekable<Chars<'a>>,
 }
@@ -268,22 +485,46 @@ impl<'a> Lexer<'a> {
             value.push(c);
         }
 
-        Some(Keyword::from_str(&value).map_or_else(|| Token::Ident(value), Token::Keyword))
+        Some(match Keyword::from_str(&value).filter(Keyword::is_reserved) {
+            Some(keyword) => Token::Keyword(keyword),
+            None => Token::Ident(value),
+        })
     }
 
     fn scan_symbol(&mut self) -> Option<Token> {
-        self.next_if_token(|it| {
+        let token = self.next_if_token(|it| {
             Some(match it {
                 '*' => Token::Asterisk,
                 '(' => Token::OpenParen,
                 ')' => Token::CloseParen,
                 ',' => Token::Comma,
+                '.' => Token::Period,
                 ';' => Token::Semicolon,
                 '+' => Token::Plus,
                 '-' => Token::Minus,
                 '/' => Token::Slash,
+                '%' => Token::Percent,
+                '=' => Token::Equal,
+                '<' => Token::LessThan,
+                '>' => Token::GreaterThan,
+                // ! 必须和 = 组合使用
+                '!' => Token::NotEqual,
                 _ => return None,
             })
+        })?;
+
+        //处理由两个字符组成的符号, 例如 <= >= != <>
+        Some(match token {
+            Token::LessThan if self.next_if(|it| it == '=').is_some() => Token::LessThanOrEqual,
+            Token::LessThan if self.next_if(|it| it == '>').is_some() => Token::NotEqual,
+            Token::GreaterThan if self.next_if(|it| it == '=').is_some() => {
+                Token::GreaterThanOrEqual
+            }
+            Token::NotEqual => {
+                self.next_if(|it| it == '=')?;
+                Token::NotEqual
+            }
+            token => token,
         })
     }
 }
@@ -446,4 +687,40 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_lexer_where() -> Result<()> {
+        let tokens = Lexer::new("select * from tbl where a >= 1 and b <> 'x' or c != 2.5;")
+            .peekable()
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Asterisk,
+                Token::Keyword(Keyword::From),
+                Token::Ident("tbl".to_string()),
+                Token::Keyword(Keyword::Where),
+                Token::Ident("a".to_string()),
+                Token::GreaterThanOrEqual,
+                Token::Number("1".to_string()),
+                Token::Keyword(Keyword::And),
+                Token::Ident("b".to_string()),
+                Token::NotEqual,
+                Token::String("x".to_string()),
+                Token::Keyword(Keyword::Or),
+                Token::Ident("c".to_string()),
+                Token::NotEqual,
+                Token::Number("2.5".to_string()),
+                Token::Semicolon,
+            ]
+        );
+
+        //单独的 ! 不是合法符号
+        assert!(Lexer::new("a ! b")
+            .collect::<Result<Vec<_>>>()
+            .is_err());
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use std::iter::Peekable;
+use std::{collections::BTreeMap, iter::Peekable};
 
 use ast::Column;
 use lexer::{Keyword, Lexer, Token};
@@ -11,6 +11,9 @@ use crate::{
 pub mod ast;
 pub mod lexer;
 
+//后缀运算符 IS NULL 的优先级
+const POSTFIX_PRECEDENCE: u8 = 6;
+
 /**
  * 解析器
  */
@@ -40,15 +43,100 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_statement(&mut self) -> Result<ast::Statement> {
-        match self.peek()? {
-            Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
+        match self.peek()?.map(Self::keyword) {
+            Some(Token::Keyword(Keyword::Create))
+            | Some(Token::Keyword(Keyword::Drop))
+            | Some(Token::Keyword(Keyword::Truncate))
+            | Some(Token::Keyword(Keyword::Alter)) => self.parse_ddl(),
+            Some(Token::Keyword(Keyword::Begin))
+            | Some(Token::Keyword(Keyword::Commit))
+            | Some(Token::Keyword(Keyword::Rollback))
+            | Some(Token::Keyword(Keyword::Savepoint))
+            | Some(Token::Keyword(Keyword::Release)) => self.parse_transaction(),
+            Some(Token::Keyword(Keyword::Vacuum)) => {
+                self.next()?;
+                Ok(ast::Statement::Vacuum)
+            }
+            Some(Token::Keyword(Keyword::Show)) => {
+                self.next()?;
+                self.next_expected(Token::Keyword(Keyword::Version))?;
+                Ok(ast::Statement::ShowVersion)
+            }
             Some(Token::Keyword(Keyword::Select)) => self.parse_select(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
+            Some(Token::Keyword(Keyword::Update)) => self.parse_update(),
+            Some(Token::Keyword(Keyword::Delete)) => self.parse_delete(),
             Some(t) => Err(Error::Parse(format!("[Parser] unexpected token {}", t))),
             None => Err(Error::Parse(format!("[Parser] unexpected end of input"))),
         }
     }
 
+    /**
+     * 解析事务控制语句
+     * begin [transaction] [read only | read write] [isolation level snapshot | serializable]
+     * savepoint name
+     * rollback [to [savepoint] name]
+     * release [savepoint] name
+     */
+    fn parse_transaction(&mut self) -> Result<ast::Statement> {
+        Ok(match Self::keyword(self.next()?) {
+            Token::Keyword(Keyword::Begin) => {
+                self.next_if_token(Token::Keyword(Keyword::Transaction));
+                let mut read_only = false;
+                if self.next_if_token(Token::Keyword(Keyword::Read)).is_some() {
+                    match Self::keyword(self.next()?) {
+                        Token::Keyword(Keyword::Only) => read_only = true,
+                        Token::Keyword(Keyword::Write) => {}
+                        token => {
+                            return Err(Error::Parse(format!(
+                                "[Parser] unexpected token {}",
+                                token
+                            )))
+                        }
+                    }
+                }
+                let mut serializable = false;
+                if self.next_if_token(Token::Keyword(Keyword::Isolation)).is_some() {
+                    self.next_expected(Token::Keyword(Keyword::Level))?;
+                    match Self::keyword(self.next()?) {
+                        Token::Keyword(Keyword::Serializable) => serializable = true,
+                        Token::Keyword(Keyword::Snapshot) => {}
+                        token => {
+                            return Err(Error::Parse(format!(
+                                "[Parser] unexpected token {}",
+                                token
+                            )))
+                        }
+                    }
+                }
+                ast::Statement::Begin {
+                    read_only,
+                    serializable,
+                }
+            }
+            Token::Keyword(Keyword::Commit) => ast::Statement::Commit,
+            Token::Keyword(Keyword::Rollback) => {
+                if self.next_if_token(Token::Keyword(Keyword::To)).is_none() {
+                    return Ok(ast::Statement::Rollback);
+                }
+                self.next_if_token(Token::Keyword(Keyword::Savepoint));
+                ast::Statement::RollbackToSavepoint {
+                    name: self.next_ident()?,
+                }
+            }
+            Token::Keyword(Keyword::Savepoint) => ast::Statement::Savepoint {
+                name: self.next_ident()?,
+            },
+            Token::Keyword(Keyword::Release) => {
+                self.next_if_token(Token::Keyword(Keyword::Savepoint));
+                ast::Statement::ReleaseSavepoint {
+                    name: self.next_ident()?,
+                }
+            }
+            token => return Err(Error::Parse(format!("[Parser] unexpected token {}", token))),
+        })
+    }
+
     /**
      * 解析ddl类型
      */
@@ -56,12 +144,102 @@ impl<'a> Parser<'a> {
         match self.next()? {
             Token::Keyword(Keyword::Create) => match self.next()? {
                 Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(),
+                Token::Keyword(Keyword::Index) => self.parse_ddl_create_index(false),
+                Token::Keyword(Keyword::Unique) => {
+                    self.next_expected(Token::Keyword(Keyword::Index))?;
+                    self.parse_ddl_create_index(true)
+                }
+                token => Err(Error::Parse(format!("[Parser] unexpected token {}", token))),
+            },
+            Token::Keyword(Keyword::Drop) => match self.next()? {
+                Token::Keyword(Keyword::Table) => {
+                    let if_exists = self.next_if_token(Token::Keyword(Keyword::If)).is_some();
+                    if if_exists {
+                        self.next_expected(Token::Keyword(Keyword::Exists))?;
+                    }
+                    Ok(ast::Statement::DropTable {
+                        name: self.next_ident()?,
+                        if_exists,
+                    })
+                }
+                Token::Keyword(Keyword::Index) => Ok(ast::Statement::DropIndex {
+                    name: self.next_ident()?,
+                }),
                 token => Err(Error::Parse(format!("[Parser] unexpected token {}", token))),
             },
+            Token::Keyword(Keyword::Truncate) => {
+                self.next_if_token(Token::Keyword(Keyword::Table));
+                Ok(ast::Statement::TruncateTable {
+                    name: self.next_ident()?,
+                })
+            }
+            Token::Keyword(Keyword::Alter) => {
+                self.next_expected(Token::Keyword(Keyword::Table))?;
+                self.parse_ddl_alter_table()
+            }
             token => Err(Error::Parse(format!("[Parser] unexpected token {}", token))),
         }
     }
 
+    /**
+     * 解析alter table
+     * alter table tbl add [column] col int
+     * alter table tbl drop [column] col
+     * alter table tbl rename [column] a to b
+     * alter table tbl rename to tbl2
+     */
+    fn parse_ddl_alter_table(&mut self) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        let operation = match Self::keyword(self.next()?) {
+            Token::Keyword(Keyword::Add) => {
+                self.next_if_column();
+                ast::AlterTableOperation::AddColumn(self.parse_ddl_column()?)
+            }
+            Token::Keyword(Keyword::Drop) => {
+                self.next_if_column();
+                ast::AlterTableOperation::DropColumn(self.next_ident()?)
+            }
+            Token::Keyword(Keyword::Rename) => {
+                if self.next_if_token(Token::Keyword(Keyword::To)).is_some() {
+                    ast::AlterTableOperation::RenameTable(self.next_ident()?)
+                } else {
+                    self.next_if_column();
+                    let old_name = self.next_ident()?;
+                    self.next_expected(Token::Keyword(Keyword::To))?;
+                    ast::AlterTableOperation::RenameColumn {
+                        old_name,
+                        new_name: self.next_ident()?,
+                    }
+                }
+            }
+            token => return Err(Error::Parse(format!("[Parser] unexpected token {}", token))),
+        };
+        Ok(ast::Statement::AlterTable { name, operation })
+    }
+
+    /**
+     * 解析create index
+     * create [unique] index idx on tbl (col)
+     */
+    fn parse_ddl_create_index(&mut self, unique: bool) -> Result<ast::Statement> {
+        //索引名
+        let name = self.next_ident()?;
+        self.next_expected(Token::Keyword(Keyword::On))?;
+        let table_name = self.next_ident()?;
+
+        //索引列
+        self.next_expected(Token::OpenParen)?;
+        let column_name = self.next_ident()?;
+        self.next_expected(Token::CloseParen)?;
+
+        Ok(ast::Statement::CreateIndex {
+            name,
+            table_name,
+            column_name,
+            unique,
+        })
+    }
+
     /**
      * 解析create table
      */
@@ -105,6 +283,7 @@ impl<'a> Parser<'a> {
             },
             nullable: None,
             default: None,
+            primary_key: false,
         };
 
         //解析列的默认值, 以及是否可以为空
@@ -116,6 +295,10 @@ impl<'a> Parser<'a> {
                     column.nullable = Some(false)
                 }
                 Keyword::Default => column.default = Some(self.parse_expression()?),
+                Keyword::Primary => {
+                    self.next_expected(Token::Keyword(Keyword::Key))?;
+                    column.primary_key = true
+                }
                 k => return Err(Error::Parse(format!("[Parser] Unexpected keyword {}", k))),
             }
         }
@@ -124,6 +307,76 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_expression(&mut self) -> Result<ast::Expression> {
+        self.parse_expression_with(0)
+    }
+
+    /**
+     * 使用优先级爬升算法解析表达式
+     * min_prec 为当前允许解析的最小运算符优先级
+     */
+    fn parse_expression_with(&mut self, min_prec: u8) -> Result<ast::Expression> {
+        //前缀运算符, 例如 NOT a, -a
+        let mut lhs = if let Some(prec) = self.peek()?.as_ref().and_then(Self::prefix_precedence) {
+            let token = self.next()?;
+            match self.parse_min_integer(&token)? {
+                Some(expr) => expr,
+                None => {
+                    let expr = Box::new(self.parse_expression_with(prec)?);
+                    match token {
+                        Token::Keyword(Keyword::Not) => ast::Operation::Not(expr).into(),
+                        Token::Minus => ast::Operation::Negate(expr).into(),
+                        _ => ast::Operation::Identity(expr).into(),
+                    }
+                }
+            }
+        } else {
+            self.parse_expression_atom()?
+        };
+
+        loop {
+            //后缀运算符 IS [NOT] NULL
+            if self.peek()? == Some(Token::Keyword(Keyword::Is)) && POSTFIX_PRECEDENCE >= min_prec {
+                self.next()?;
+                let not = self.next_if_token(Token::Keyword(Keyword::Not)).is_some();
+                self.next_expected(Token::Keyword(Keyword::Null))?;
+                lhs = ast::Operation::IsNull(Box::new(lhs)).into();
+                if not {
+                    lhs = ast::Operation::Not(Box::new(lhs)).into();
+                }
+                continue;
+            }
+
+            //中缀运算符, 优先级低于 min_prec 时交给上一层处理
+            let prec = match self.peek()?.as_ref().and_then(Self::infix_precedence) {
+                Some(prec) if prec >= min_prec => prec,
+                _ => break,
+            };
+            let token = self.next()?;
+            //左结合, 右侧表达式只能包含更高优先级的运算符
+            let rhs = self.parse_expression_with(prec + 1)?;
+            lhs = Self::build_infix(token, lhs, rhs)?;
+        }
+
+        Ok(lhs)
+    }
+
+    //i64::MIN 的绝对值超出了 i64 的范围, 只能和前面的负号一起解析成整数常量
+    fn parse_min_integer(&mut self, prefix: &Token) -> Result<Option<ast::Expression>> {
+        if *prefix != Token::Minus {
+            return Ok(None);
+        }
+        if let Some(Token::Number(n)) = self.peek()? {
+            if n.parse::<i64>().is_err() {
+                if let Ok(i) = format!("-{}", n).parse::<i64>() {
+                    self.next()?;
+                    return Ok(Some(ast::Consts::Integer(i).into()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn parse_expression_atom(&mut self) -> Result<ast::Expression> {
         Ok(match self.next()? {
             Token::Number(n) => {
                 if n.chars().all(|it| it.is_ascii_digit()) {
@@ -138,6 +391,32 @@ impl<'a> Parser<'a> {
             Token::Keyword(Keyword::True) => ast::Consts::Boolean(true).into(),
             Token::Keyword(Keyword::False) => ast::Consts::Boolean(false).into(),
             Token::Keyword(Keyword::Null) => ast::Consts::Null.into(),
+            Token::Ident(name) => {
+                //带表名的列 t.a
+                if self.next_if_token(Token::Period).is_some() {
+                    return Ok(ast::Expression::Field(Some(name), self.next_ident()?));
+                }
+                if self.next_if_token(Token::OpenParen).is_none() {
+                    return Ok(ast::Expression::Field(None, name));
+                }
+                //函数调用, COUNT(*) 没有参数
+                let mut args = Vec::new();
+                if self.next_if_token(Token::Asterisk).is_none() {
+                    loop {
+                        args.push(self.parse_expression()?);
+                        if self.next_if_token(Token::Comma).is_none() {
+                            break;
+                        }
+                    }
+                }
+                self.next_expected(Token::CloseParen)?;
+                ast::Expression::Function(name.to_uppercase(), args)
+            }
+            Token::OpenParen => {
+                let expr = self.parse_expression()?;
+                self.next_expected(Token::CloseParen)?;
+                expr
+            }
             t => {
                 return Err(Error::Parse(format!(
                     "[Parser] Unexpected expression token {}",
@@ -147,6 +426,56 @@ impl<'a> Parser<'a> {
         })
     }
 
+    //前缀运算符的优先级
+    fn prefix_precedence(token: &Token) -> Option<u8> {
+        match token {
+            Token::Keyword(Keyword::Not) => Some(3),
+            Token::Minus | Token::Plus => Some(9),
+            _ => None,
+        }
+    }
+
+    //中缀运算符的优先级, 数值越大优先级越高
+    fn infix_precedence(token: &Token) -> Option<u8> {
+        match token {
+            Token::Keyword(Keyword::Or) => Some(1),
+            Token::Keyword(Keyword::And) => Some(2),
+            Token::Equal | Token::NotEqual => Some(4),
+            Token::GreaterThan
+            | Token::GreaterThanOrEqual
+            | Token::LessThan
+            | Token::LessThanOrEqual => Some(5),
+            Token::Plus | Token::Minus => Some(7),
+            Token::Asterisk | Token::Slash | Token::Percent => Some(8),
+            _ => None,
+        }
+    }
+
+    fn build_infix(
+        token: Token,
+        lhs: ast::Expression,
+        rhs: ast::Expression,
+    ) -> Result<ast::Expression> {
+        let (l, r) = (Box::new(lhs), Box::new(rhs));
+        Ok(match token {
+            Token::Keyword(Keyword::Or) => ast::Operation::Or(l, r),
+            Token::Keyword(Keyword::And) => ast::Operation::And(l, r),
+            Token::Equal => ast::Operation::Equal(l, r),
+            Token::NotEqual => ast::Operation::NotEqual(l, r),
+            Token::GreaterThan => ast::Operation::GreaterThan(l, r),
+            Token::GreaterThanOrEqual => ast::Operation::GreaterThanOrEqual(l, r),
+            Token::LessThan => ast::Operation::LessThan(l, r),
+            Token::LessThanOrEqual => ast::Operation::LessThanOrEqual(l, r),
+            Token::Plus => ast::Operation::Add(l, r),
+            Token::Minus => ast::Operation::Subtract(l, r),
+            Token::Asterisk => ast::Operation::Multiply(l, r),
+            Token::Slash => ast::Operation::Divide(l, r),
+            Token::Percent => ast::Operation::Modulo(l, r),
+            t => return Err(Error::Parse(format!("[Parser] Unexpected operator {}", t))),
+        }
+        .into())
+    }
+
     fn next_ident(&mut self) -> Result<String> {
         match self.next()? {
             Token::Ident(ident) => Ok(ident),
@@ -161,7 +490,7 @@ impl<'a> Parser<'a> {
      * 判断下一个值是否期待值
      */
     fn next_expected(&mut self, expected: Token) -> Result<()> {
-        let token = self.next()?;
+        let token = Self::keyword(self.next()?);
         if token != expected {
             return Err(Error::Parse(format!(
                 "[Parser] Expected token {}, got {}",
@@ -175,6 +504,25 @@ impl<'a> Parser<'a> {
         self.lexer.peek().cloned().transpose()
     }
 
+    //下一个 token 为 AS 时, 判断之后是否为 OF
+    fn peek_as_of(&self) -> bool {
+        let mut lexer = self.lexer.clone();
+        lexer.next();
+        matches!(lexer.next(), Some(Ok(Token::Keyword(Keyword::Of))))
+    }
+
+    //可以省略的 COLUMN 关键字, 后面紧跟列名时才是关键字, 否则 column 本身是列名
+    fn next_if_column(&mut self) {
+        let mut lexer = self.lexer.clone();
+        let column = match lexer.next() {
+            Some(Ok(token)) => Self::keyword(token) == Token::Keyword(Keyword::Column),
+            _ => false,
+        };
+        if column && matches!(lexer.next(), Some(Ok(Token::Ident(_)))) {
+            self.lexer.next();
+        }
+    }
+
     fn next(&mut self) -> Result<Token> {
         self.lexer
             .next()
@@ -191,20 +539,233 @@ impl<'a> Parser<'a> {
     }
 
     fn next_if_token(&mut self, token: Token) -> Option<Token> {
-        self.next_if(|it| it == &token)
+        self.next_if(|it| Self::keyword(it.clone()) == token)
+    }
+
+    //非保留关键字由词法分析作为标识符返回, 在语句中需要关键字的位置转换成关键字
+    fn keyword(token: Token) -> Token {
+        match token {
+            Token::Ident(ident) => match Keyword::from_str(&ident) {
+                Some(keyword) => Token::Keyword(keyword),
+                None => Token::Ident(ident),
+            },
+            token => token,
+        }
     }
 
     fn parse_select(&mut self) -> Result<ast::Statement> {
         self.next_expected(Token::Keyword(Keyword::Select))?;
-        self.next_expected(Token::Asterisk)?;
-        self.next_expected(Token::Keyword(Keyword::From))?;
+        let select = self.parse_select_clause()?;
 
-        let table_name = self.next_ident()?;
+        //没有 from 时只计算常量表达式
+        let from = match self.next_if_token(Token::Keyword(Keyword::From)) {
+            Some(_) => Some(self.parse_from_clause()?),
+            None => None,
+        };
         Ok(ast::Statement::Select {
-            table_name: table_name,
+            select,
+            from,
+            as_of: self.parse_as_of_clause()?,
+            where_clause: self.parse_where_clause()?,
+            group_by: self.parse_group_by_clause()?,
+            having: match self.next_if_token(Token::Keyword(Keyword::Having)) {
+                Some(_) => Some(self.parse_expression()?),
+                None => None,
+            },
+            order_by: self.parse_order_by_clause()?,
+            limit: match self.next_if_token(Token::Keyword(Keyword::Limit)) {
+                Some(_) => Some(self.parse_expression()?),
+                None => None,
+            },
+            offset: match self.next_if_token(Token::Keyword(Keyword::Offset)) {
+                Some(_) => Some(self.parse_expression()?),
+                None => None,
+            },
         })
     }
 
+    //解析 as of version n
+    fn parse_as_of_clause(&mut self) -> Result<Option<u64>> {
+        if self.next_if_token(Token::Keyword(Keyword::As)).is_none() {
+            return Ok(None);
+        }
+        self.next_expected(Token::Keyword(Keyword::Of))?;
+        self.next_expected(Token::Keyword(Keyword::Version))?;
+        match self.next()? {
+            Token::Number(n) => Ok(Some(n.parse()?)),
+            token => Err(Error::Parse(format!("[Parser] unexpected token {}", token))),
+        }
+    }
+
+    //解析 group by expr, ...
+    fn parse_group_by_clause(&mut self) -> Result<Vec<ast::Expression>> {
+        let mut group_by = Vec::new();
+        if self.next_if_token(Token::Keyword(Keyword::Group)).is_none() {
+            return Ok(group_by);
+        }
+        self.next_expected(Token::Keyword(Keyword::By))?;
+
+        loop {
+            group_by.push(self.parse_expression()?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(group_by)
+    }
+
+    //解析 order by expr [ASC|DESC] [NULLS FIRST|LAST], ...
+    fn parse_order_by_clause(&mut self) -> Result<Vec<ast::OrderBy>> {
+        let mut order_by = Vec::new();
+        if self.next_if_token(Token::Keyword(Keyword::Order)).is_none() {
+            return Ok(order_by);
+        }
+        self.next_expected(Token::Keyword(Keyword::By))?;
+
+        loop {
+            let expr = self.parse_expression()?;
+            let direction = match self.next_if(|it| {
+                matches!(
+                    it,
+                    Token::Keyword(Keyword::Asc) | Token::Keyword(Keyword::Desc)
+                )
+            }) {
+                Some(Token::Keyword(Keyword::Desc)) => ast::OrderDirection::Desc,
+                _ => ast::OrderDirection::Asc,
+            };
+            let nulls_first = match self.next_if_token(Token::Keyword(Keyword::Nulls)) {
+                Some(_) => match Self::keyword(self.next()?) {
+                    Token::Keyword(Keyword::First) => Some(true),
+                    Token::Keyword(Keyword::Last) => Some(false),
+                    token => {
+                        return Err(Error::Parse(format!(
+                            "[Parser] unexpected token {}",
+                            token
+                        )))
+                    }
+                },
+                None => None,
+            };
+            order_by.push(ast::OrderBy {
+                expr,
+                direction,
+                nulls_first,
+            });
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(order_by)
+    }
+
+    //解析 from 子句, 连接按照从左到右的顺序结合
+    fn parse_from_clause(&mut self) -> Result<ast::FromItem> {
+        let mut item = self.parse_from_table()?;
+        loop {
+            //逗号连接等价于 cross join
+            if self.next_if_token(Token::Comma).is_some() {
+                item = ast::FromItem::Join {
+                    left: Box::new(item),
+                    right: Box::new(self.parse_from_table()?),
+                    join_type: ast::JoinType::Cross,
+                    predicate: None,
+                };
+                continue;
+            }
+
+            let join_type = match self.parse_join_type()? {
+                Some(join_type) => join_type,
+                None => break,
+            };
+            let right = self.parse_from_table()?;
+            let predicate = match join_type {
+                ast::JoinType::Cross => None,
+                _ => {
+                    self.next_expected(Token::Keyword(Keyword::On))?;
+                    Some(self.parse_expression()?)
+                }
+            };
+            item = ast::FromItem::Join {
+                left: Box::new(item),
+                right: Box::new(right),
+                join_type,
+                predicate,
+            };
+        }
+        Ok(item)
+    }
+
+    //解析表名和别名, 别名前的 AS 可以省略
+    fn parse_from_table(&mut self) -> Result<ast::FromItem> {
+        let name = self.next_ident()?;
+        //as of 是历史版本查询, 不是别名
+        let alias = match self.peek()? {
+            Some(Token::Keyword(Keyword::As)) if !self.peek_as_of() => {
+                self.next()?;
+                Some(self.next_ident()?)
+            }
+            _ => match self.next_if(|it| matches!(it, Token::Ident(_))) {
+                Some(Token::Ident(alias)) => Some(alias),
+                _ => None,
+            },
+        };
+        Ok(ast::FromItem::Table { name, alias })
+    }
+
+    //解析连接类型, 不是连接时返回 None
+    fn parse_join_type(&mut self) -> Result<Option<ast::JoinType>> {
+        let join_type = match self.peek()? {
+            Some(Token::Keyword(Keyword::Join)) => ast::JoinType::Inner,
+            Some(Token::Keyword(Keyword::Inner)) => ast::JoinType::Inner,
+            Some(Token::Keyword(Keyword::Cross)) => ast::JoinType::Cross,
+            Some(Token::Keyword(Keyword::Left)) => ast::JoinType::Left,
+            Some(Token::Keyword(Keyword::Right)) => ast::JoinType::Right,
+            Some(Token::Keyword(Keyword::Full)) => ast::JoinType::Full,
+            _ => return Ok(None),
+        };
+        if self.next()? != Token::Keyword(Keyword::Join) {
+            //外连接的 OUTER 可以省略
+            if matches!(
+                join_type,
+                ast::JoinType::Left | ast::JoinType::Right | ast::JoinType::Full
+            ) {
+                self.next_if_token(Token::Keyword(Keyword::Outer));
+            }
+            self.next_expected(Token::Keyword(Keyword::Join))?;
+        }
+        Ok(Some(join_type))
+    }
+
+    //解析 select 列表, select * 时返回空列表
+    fn parse_select_clause(&mut self) -> Result<Vec<(ast::Expression, Option<String>)>> {
+        if self.next_if_token(Token::Asterisk).is_some() {
+            return Ok(Vec::new());
+        }
+
+        let mut select = Vec::new();
+        loop {
+            let expr = self.parse_expression()?;
+            //别名
+            let alias = match self.next_if_token(Token::Keyword(Keyword::As)) {
+                Some(_) => Some(self.next_ident()?),
+                None => None,
+            };
+            select.push((expr, alias));
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        Ok(select)
+    }
+
+    //解析 where 条件, 没有 where 关键字时返回 None
+    fn parse_where_clause(&mut self) -> Result<Option<ast::Expression>> {
+        if self.next_if_token(Token::Keyword(Keyword::Where)).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(self.parse_expression()?))
+    }
+
     fn parse_insert(&mut self) -> Result<ast::Statement> {
         self.next_expected(Token::Keyword(Keyword::Insert))?;
         self.next_expected(Token::Keyword(Keyword::Into))?;
@@ -257,13 +818,63 @@ impl<'a> Parser<'a> {
             values,
         })
     }
+
+    fn parse_update(&mut self) -> Result<ast::Statement> {
+        self.next_expected(Token::Keyword(Keyword::Update))?;
+        //表名
+        let table_name = self.next_ident()?;
+        self.next_expected(Token::Keyword(Keyword::Set))?;
+
+        //update tbl set a = 1, b = b + 1 where c = 2
+        let mut columns = BTreeMap::new();
+        loop {
+            let column = self.next_ident()?;
+            self.next_expected(Token::Equal)?;
+            let expr = self.parse_expression()?;
+            if columns.contains_key(&column) {
+                return Err(Error::Parse(format!(
+                    "[Parser] Duplicate column {} for update",
+                    column
+                )));
+            }
+            columns.insert(column, expr);
+
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+
+        Ok(ast::Statement::Update {
+            table_name,
+            columns,
+            where_clause: self.parse_where_clause()?,
+        })
+    }
+
+    fn parse_delete(&mut self) -> Result<ast::Statement> {
+        self.next_expected(Token::Keyword(Keyword::Delete))?;
+        self.next_expected(Token::Keyword(Keyword::From))?;
+        //表名
+        let table_name = self.next_ident()?;
+
+        Ok(ast::Statement::Delete {
+            table_name,
+            where_clause: self.parse_where_clause()?,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::error::Result;
 
-    use super::Parser;
+    use super::{
+        ast::{
+            self, AlterTableOperation, Consts, Expression, FromItem, JoinType, Operation, OrderBy,
+            OrderDirection, Statement,
+        },
+        Parser,
+    };
 
     #[test]
     fn test_parse_crate_ddl() -> Result<()> {
@@ -305,6 +916,193 @@ mod tests {
 
         assert!(stmt3.is_err());
 
+        let stmt4 = Parser::new("create table tbl1 (a int primary key, b text not null);").parse()?;
+        match stmt4 {
+            Statement::CreateTable { columns, .. } => {
+                assert!(columns[0].primary_key);
+                assert!(!columns[1].primary_key);
+            }
+            _ => panic!("unexpected statement"),
+        }
+        assert!(Parser::new("create table tbl1 (a int primary);").parse().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_index_ddl() -> Result<()> {
+        let stmt = Parser::new("create unique index idx1 on tbl1 (a);").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::CreateIndex {
+                name: "idx1".to_string(),
+                table_name: "tbl1".to_string(),
+                column_name: "a".to_string(),
+                unique: true,
+            }
+        );
+
+        let stmt = Parser::new("create index idx1 on tbl1 (a);").parse()?;
+        assert!(matches!(stmt, Statement::CreateIndex { unique: false, .. }));
+
+        let stmt = Parser::new("drop index idx1;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::DropIndex {
+                name: "idx1".to_string()
+            }
+        );
+
+        assert!(Parser::new("create index idx1 on tbl1 (a, b);").parse().is_err());
+        assert!(Parser::new("create unique idx1 on tbl1 (a);").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_alter_ddl() -> Result<()> {
+        let stmt = Parser::new("drop table if exists tbl;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::DropTable {
+                name: "tbl".to_string(),
+                if_exists: true
+            }
+        );
+        let stmt = Parser::new("truncate tbl;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::TruncateTable {
+                name: "tbl".to_string()
+            }
+        );
+
+        let stmt = Parser::new("alter table tbl add column c int not null default 1;").parse()?;
+        assert!(matches!(
+            stmt,
+            Statement::AlterTable {
+                operation: AlterTableOperation::AddColumn(ast::Column { nullable: Some(false), .. }),
+                ..
+            }
+        ));
+        let stmt = Parser::new("alter table tbl drop c;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::AlterTable {
+                name: "tbl".to_string(),
+                operation: AlterTableOperation::DropColumn("c".to_string())
+            }
+        );
+        let stmt = Parser::new("alter table tbl rename column a to b;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::AlterTable {
+                name: "tbl".to_string(),
+                operation: AlterTableOperation::RenameColumn {
+                    old_name: "a".to_string(),
+                    new_name: "b".to_string()
+                }
+            }
+        );
+        let stmt = Parser::new("alter table tbl rename to tbl2;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::AlterTable {
+                name: "tbl".to_string(),
+                operation: AlterTableOperation::RenameTable("tbl2".to_string())
+            }
+        );
+
+        assert!(Parser::new("drop table if tbl;").parse().is_err());
+        assert!(Parser::new("alter table tbl rename a;").parse().is_err());
+        assert!(Parser::new("alter table tbl modify a int;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_transaction() -> Result<()> {
+        assert_eq!(
+            Parser::new("begin;").parse()?,
+            Statement::Begin {
+                read_only: false,
+                serializable: false
+            }
+        );
+        assert_eq!(
+            Parser::new("release savepoint release;").parse()?,
+            Statement::ReleaseSavepoint {
+                name: "release".to_string()
+            }
+        );
+        assert_eq!(
+            Parser::new("begin transaction read only;").parse()?,
+            Statement::Begin {
+                read_only: true,
+                serializable: false
+            }
+        );
+        assert_eq!(
+            Parser::new("BEGIN READ WRITE;").parse()?,
+            Statement::Begin {
+                read_only: false,
+                serializable: false
+            }
+        );
+        assert_eq!(Parser::new("commit;").parse()?, Statement::Commit);
+        assert_eq!(Parser::new("rollback;").parse()?, Statement::Rollback);
+        assert_eq!(
+            Parser::new("savepoint sp1;").parse()?,
+            Statement::Savepoint { name: "sp1".into() }
+        );
+        assert_eq!(
+            Parser::new("rollback to savepoint sp1;").parse()?,
+            Statement::RollbackToSavepoint { name: "sp1".into() }
+        );
+        assert_eq!(
+            Parser::new("rollback to sp1;").parse()?,
+            Statement::RollbackToSavepoint { name: "sp1".into() }
+        );
+        assert_eq!(
+            Parser::new("release savepoint sp1;").parse()?,
+            Statement::ReleaseSavepoint { name: "sp1".into() }
+        );
+        assert_eq!(
+            Parser::new("release sp1;").parse()?,
+            Statement::ReleaseSavepoint { name: "sp1".into() }
+        );
+        assert_eq!(
+            Parser::new("release savepoint release;").parse()?,
+            Statement::ReleaseSavepoint {
+                name: "release".to_string()
+            }
+        );
+        assert_eq!(
+            Parser::new("begin transaction read only;").parse()?,
+            Statement::Begin {
+                read_only: true,
+                serializable: false
+            }
+        );
+        assert_eq!(
+            Parser::new("begin isolation level serializable;").parse()?,
+            Statement::Begin {
+                read_only: false,
+                serializable: true
+            }
+        );
+        assert_eq!(
+            Parser::new("begin transaction read write isolation level snapshot;").parse()?,
+            Statement::Begin {
+                read_only: false,
+                serializable: false
+            }
+        );
+        assert_eq!(Parser::new("vacuum;").parse()?, Statement::Vacuum);
+        assert_eq!(Parser::new("show version;").parse()?, Statement::ShowVersion);
+        assert!(Parser::new("begin read;").parse().is_err());
+        assert!(Parser::new("begin isolation level;").parse().is_err());
+        assert!(Parser::new("savepoint;").parse().is_err());
+        assert!(Parser::new("rollback to;").parse().is_err());
+        assert!(Parser::new("commit transaction work;").parse().is_err());
         Ok(())
     }
 
@@ -329,4 +1127,532 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parser_select_where() -> Result<()> {
+        let stmt = Parser::new("select * from tbl1 where a = 1 + 2 * 3 or not b is null and c < -1;")
+            .parse()?;
+
+        let field = |name: &str| Box::new(Expression::Field(None, name.to_string()));
+        let int = |i: i64| Box::new(Expression::Consts(Consts::Integer(i)));
+        assert_eq!(
+            stmt,
+            Statement::Select {
+                select: vec![],
+                from: Some(FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None
+                }),
+                where_clause: Some(
+                    Operation::Or(
+                        Box::new(
+                            Operation::Equal(
+                                field("a"),
+                                Box::new(
+                                    Operation::Add(
+                                        int(1),
+                                        Box::new(Operation::Multiply(int(2), int(3)).into())
+                                    )
+                                    .into()
+                                )
+                            )
+                            .into()
+                        ),
+                        Box::new(
+                            Operation::And(
+                                Box::new(
+                                    Operation::Not(Box::new(Operation::IsNull(field("b")).into()))
+                                        .into()
+                                ),
+                                Box::new(
+                                    Operation::LessThan(
+                                        field("c"),
+                                        Box::new(Operation::Negate(int(1)).into())
+                                    )
+                                    .into()
+                                )
+                            )
+                            .into()
+                        )
+                    )
+                    .into()
+                ),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+                as_of: None,
+            }
+        );
+
+        let stmt = Parser::new("select * from tbl1 where (a + 1) * 2 >= b is not null;").parse();
+        assert!(stmt.is_ok());
+
+        let stmt = Parser::new("select * from tbl1 where a = ;").parse();
+        assert!(stmt.is_err());
+
+        //i64::MIN 只有带着负号才能解析成整数常量, 其它负数仍然是取反运算
+        let where_clause = |sql: &str| match Parser::new(sql).parse() {
+            Ok(Statement::Select { where_clause, .. }) => where_clause,
+            stmt => panic!("unexpected statement {:?}", stmt),
+        };
+        assert_eq!(
+            where_clause("select * from tbl1 where a = -9223372036854775808;"),
+            Some(Operation::Equal(field("a"), int(i64::MIN)).into())
+        );
+        assert_eq!(
+            where_clause("select * from tbl1 where a = -9223372036854775807;"),
+            Some(
+                Operation::Equal(
+                    field("a"),
+                    Box::new(Operation::Negate(int(i64::MAX)).into())
+                )
+                .into()
+            )
+        );
+        assert_eq!(
+            where_clause("select * from tbl1 where a = -9223372036854775808 + 1;"),
+            Some(
+                Operation::Equal(
+                    field("a"),
+                    Box::new(Operation::Add(int(i64::MIN), int(1)).into())
+                )
+                .into()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_select_projection() -> Result<()> {
+        let stmt = Parser::new("select a, b + 1 as c, 'x' from tbl1;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::Select {
+                select: vec![
+                    (Expression::Field(None, "a".to_string()), None),
+                    (
+                        Operation::Add(
+                            Box::new(Expression::Field(None, "b".to_string())),
+                            Box::new(Consts::Integer(1).into())
+                        )
+                        .into(),
+                        Some("c".to_string())
+                    ),
+                    (Consts::String("x".to_string()).into(), None),
+                ],
+                from: Some(FromItem::Table {
+                    name: "tbl1".to_string(),
+                    alias: None
+                }),
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+                limit: None,
+                offset: None,
+                as_of: None,
+            }
+        );
+
+        let stmt = Parser::new("select 1 + 2;").parse()?;
+        assert!(matches!(stmt, Statement::Select { from: None, .. }));
+
+        assert!(Parser::new("select a, from tbl1;").parse().is_err());
+        assert!(Parser::new("select a as from tbl1;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_order_by_limit() -> Result<()> {
+        let stmt = Parser::new(
+            "select a from tbl1 order by a desc, b nulls first, c asc nulls last limit 10 offset 2 + 3;",
+        )
+        .parse()?;
+        let field = |name: &str| Expression::Field(None, name.to_string());
+        match stmt {
+            Statement::Select {
+                order_by,
+                limit,
+                offset,
+                ..
+            } => {
+                assert_eq!(
+                    order_by,
+                    vec![
+                        OrderBy {
+                            expr: field("a"),
+                            direction: OrderDirection::Desc,
+                            nulls_first: None
+                        },
+                        OrderBy {
+                            expr: field("b"),
+                            direction: OrderDirection::Asc,
+                            nulls_first: Some(true)
+                        },
+                        OrderBy {
+                            expr: field("c"),
+                            direction: OrderDirection::Asc,
+                            nulls_first: Some(false)
+                        },
+                    ]
+                );
+                assert_eq!(limit, Some(Consts::Integer(10).into()));
+                assert!(offset.is_some());
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(Parser::new("select * from tbl1 order a;").parse().is_err());
+        assert!(Parser::new("select * from tbl1 order by a nulls;").parse().is_err());
+        assert!(Parser::new("select * from tbl1 offset 1 limit 2;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_group_by() -> Result<()> {
+        let sql = "select a, count(*), sum(b + 1) from tbl1 group by a, c having max(b) > 1 order by a;";
+        let field = |name: &str| Expression::Field(None, name.to_string());
+        match Parser::new(sql).parse()? {
+            Statement::Select {
+                select,
+                group_by,
+                having,
+                order_by,
+                ..
+            } => {
+                assert_eq!(select[1].0, Expression::Function("COUNT".to_string(), vec![]));
+                assert_eq!(select[2].0.to_string(), "SUM(b + 1)");
+                assert_eq!(group_by, vec![field("a"), field("c")]);
+                assert_eq!(
+                    having,
+                    Some(
+                        Operation::GreaterThan(
+                            Box::new(Expression::Function("MAX".to_string(), vec![field("b")])),
+                            Box::new(Consts::Integer(1).into())
+                        )
+                        .into()
+                    )
+                );
+                assert_eq!(order_by.len(), 1);
+            }
+            _ => unreachable!(),
+        }
+
+        assert!(Parser::new("select count( from tbl1;").parse().is_err());
+        assert!(Parser::new("select a from tbl1 group a;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_join() -> Result<()> {
+        let table = |name: &str, alias: Option<&str>| {
+            Box::new(FromItem::Table {
+                name: name.to_string(),
+                alias: alias.map(|it| it.to_string()),
+            })
+        };
+        let field = |table: &str, name: &str| {
+            Box::new(Expression::Field(Some(table.to_string()), name.to_string()))
+        };
+
+        let sql = "select a.id, x.b from a join b as x on a.id = x.id left outer join c on c.id = a.id, d cross join e;";
+        match Parser::new(sql).parse()? {
+            Statement::Select { select, from, .. } => {
+                assert_eq!(select[0].0, *field("a", "id"));
+                assert_eq!(
+                    from,
+                    Some(FromItem::Join {
+                        left: Box::new(FromItem::Join {
+                            left: Box::new(FromItem::Join {
+                                left: Box::new(FromItem::Join {
+                                    left: table("a", None),
+                                    right: table("b", Some("x")),
+                                    join_type: JoinType::Inner,
+                                    predicate: Some(
+                                        Operation::Equal(field("a", "id"), field("x", "id")).into()
+                                    ),
+                                }),
+                                right: table("c", None),
+                                join_type: JoinType::Left,
+                                predicate: Some(
+                                    Operation::Equal(field("c", "id"), field("a", "id")).into()
+                                ),
+                            }),
+                            right: table("d", None),
+                            join_type: JoinType::Cross,
+                            predicate: None,
+                        }),
+                        right: table("e", None),
+                        join_type: JoinType::Cross,
+                        predicate: None,
+                    })
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        let stmt = Parser::new("select * from a t1 right join b t2 on t1.id = t2.id;").parse()?;
+        assert!(matches!(
+            stmt,
+            Statement::Select { from: Some(FromItem::Join { join_type: JoinType::Right, .. }), .. }
+        ));
+        let stmt = Parser::new("select * from a full join b on true;").parse()?;
+        assert!(matches!(
+            stmt,
+            Statement::Select { from: Some(FromItem::Join { join_type: JoinType::Full, .. }), .. }
+        ));
+
+        assert!(Parser::new("select * from a join b;").parse().is_err());
+        assert!(Parser::new("select * from a inner outer join b on true;").parse().is_err());
+        assert!(Parser::new("select a. from a;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_expression_display() -> Result<()> {
+        let sql = "select (a + 1) * -b, c is not null, 'x' = d or true from tbl1;";
+        match Parser::new(sql).parse()? {
+            Statement::Select { select, .. } => {
+                let names = select.iter().map(|(e, _)| e.to_string()).collect::<Vec<_>>();
+                assert_eq!(
+                    names,
+                    vec!["(a + 1) * (-b)", "NOT (c IS NULL)", "('x' = d) OR TRUE"]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        //浮点数常量带小数点, 负数常量取反时加上括号, 显示的结果可以解析回相同的表达式
+        let sql = "select 5 / 0.0, 2.50, 1000.000, -(-9223372036854775808), -(-1), +-9223372036854775808;";
+        match Parser::new(sql).parse()? {
+            Statement::Select { select, .. } => {
+                let names = select.iter().map(|(e, _)| e.to_string()).collect::<Vec<_>>();
+                assert_eq!(
+                    names,
+                    vec![
+                        "5 / 0.0",
+                        "2.5",
+                        "1000.0",
+                        "-(-9223372036854775808)",
+                        "-(-1)",
+                        "+(-9223372036854775808)"
+                    ]
+                );
+                for (expr, name) in select.iter().map(|(e, _)| e).zip(names) {
+                    match Parser::new(&format!("select {};", name)).parse()? {
+                        Statement::Select { select, .. } => assert_eq!(&select[0].0, expr),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_update() -> Result<()> {
+        let stmt = Parser::new("update tbl set a = 1, b = b + 1 where c = 'x';").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::Update {
+                table_name: "tbl".to_string(),
+                columns: vec![
+                    ("a".to_string(), Consts::Integer(1).into()),
+                    (
+                        "b".to_string(),
+                        Operation::Add(
+                            Box::new(Expression::Field(None, "b".to_string())),
+                            Box::new(Consts::Integer(1).into())
+                        )
+                        .into()
+                    ),
+                ]
+                .into_iter()
+                .collect(),
+                where_clause: Some(
+                    Operation::Equal(
+                        Box::new(Expression::Field(None, "c".to_string())),
+                        Box::new(Consts::String("x".to_string()).into())
+                    )
+                    .into()
+                ),
+            }
+        );
+
+        assert!(Parser::new("update tbl set a = 1;").parse().is_ok());
+        assert!(Parser::new("update tbl set a = 1, a = 2;").parse().is_err());
+        assert!(Parser::new("update tbl where a = 1;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_delete() -> Result<()> {
+        let stmt = Parser::new("delete from tbl where a > 1;").parse()?;
+        assert_eq!(
+            stmt,
+            Statement::Delete {
+                table_name: "tbl".to_string(),
+                where_clause: Some(
+                    Operation::GreaterThan(
+                        Box::new(Expression::Field(None, "a".to_string())),
+                        Box::new(Consts::Integer(1).into())
+                    )
+                    .into()
+                ),
+            }
+        );
+
+        assert!(Parser::new("delete from tbl;").parse().is_ok());
+        assert!(Parser::new("delete tbl;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_unreserved_keywords() -> Result<()> {
+        //非保留关键字可以用作表名、列名和别名, 保持原来的大小写
+        let stmt = Parser::new("create table show (id int, Version int);").parse()?;
+        match stmt {
+            Statement::CreateTable { name, columns } => {
+                assert_eq!(name, "show");
+                assert_eq!(columns[1].name, "Version");
+            }
+            _ => panic!("unexpected statement"),
+        }
+        let stmt = Parser::new("select version as show from show as of version 3 where Version > 1;")
+            .parse()?;
+        match stmt {
+            Statement::Select {
+                select,
+                from,
+                as_of,
+                where_clause,
+                ..
+            } => {
+                assert_eq!(
+                    select,
+                    vec![(Expression::Field(None, "version".to_string()), Some("show".to_string()))]
+                );
+                assert_eq!(
+                    from,
+                    Some(FromItem::Table {
+                        name: "show".to_string(),
+                        alias: None
+                    })
+                );
+                assert_eq!(as_of, Some(3));
+                assert_eq!(
+                    where_clause,
+                    Some(
+                        Operation::GreaterThan(
+                            Box::new(Expression::Field(None, "Version".to_string())),
+                            Box::new(Consts::Integer(1).into())
+                        )
+                        .into()
+                    )
+                );
+            }
+            _ => panic!("unexpected statement"),
+        }
+
+        let stmt = Parser::new("select * from t order by first desc nulls last, last nulls first;")
+            .parse()?;
+        match stmt {
+            Statement::Select { order_by, .. } => assert_eq!(
+                order_by,
+                vec![
+                    OrderBy {
+                        expr: Expression::Field(None, "first".to_string()),
+                        direction: OrderDirection::Desc,
+                        nulls_first: Some(false),
+                    },
+                    OrderBy {
+                        expr: Expression::Field(None, "last".to_string()),
+                        direction: OrderDirection::Asc,
+                        nulls_first: Some(true),
+                    },
+                ]
+            ),
+            _ => panic!("unexpected statement"),
+        }
+        assert!(Parser::new("create table t (nulls int, first int, last int);").parse().is_ok());
+
+        assert!(Parser::new("create table t (transaction int, read int, only int, write int);")
+            .parse()
+            .is_ok());
+        assert!(Parser::new("update t set read = write + 1 where only;").parse().is_ok());
+        assert!(Parser::new("create table release (release int);").parse().is_ok());
+        assert!(Parser::new("select add, column, rename from t;").parse().is_ok());
+        let operation = |sql: &str| match Parser::new(sql).parse() {
+            Ok(Statement::AlterTable { operation, .. }) => operation,
+            stmt => panic!("unexpected statement {:?}", stmt),
+        };
+        assert!(matches!(
+            operation("alter table t add column int;"),
+            AlterTableOperation::AddColumn(column) if column.name == "column"
+        ));
+        assert!(matches!(
+            operation("alter table t add column add int;"),
+            AlterTableOperation::AddColumn(column) if column.name == "add"
+        ));
+        assert_eq!(
+            operation("alter table t drop column;"),
+            AlterTableOperation::DropColumn("column".to_string())
+        );
+        assert_eq!(
+            operation("alter table t drop column column;"),
+            AlterTableOperation::DropColumn("column".to_string())
+        );
+        assert_eq!(
+            operation("alter table t rename column rename to column;"),
+            AlterTableOperation::RenameColumn {
+                old_name: "rename".to_string(),
+                new_name: "column".to_string()
+            }
+        );
+        assert_eq!(
+            operation("alter table t rename column to a;"),
+            AlterTableOperation::RenameColumn {
+                old_name: "column".to_string(),
+                new_name: "a".to_string()
+            }
+        );
+        assert!(Parser::new("select release from release;").parse().is_ok());
+        assert!(Parser::new(
+            "create table t (isolation int, level int, snapshot int, serializable int);"
+        )
+        .parse()
+        .is_ok());
+        assert!(Parser::new("select level from snapshot where serializable = 1;").parse().is_ok());
+
+        //在需要关键字的位置仍然按照关键字解析
+        assert_eq!(Parser::new("SHOW version;").parse()?, Statement::ShowVersion);
+        assert!(Parser::new("show;").parse().is_err());
+        assert_eq!(
+            Parser::new("release savepoint release;").parse()?,
+            Statement::ReleaseSavepoint {
+                name: "release".to_string()
+            }
+        );
+        assert_eq!(
+            Parser::new("begin transaction read only;").parse()?,
+            Statement::Begin {
+                read_only: true,
+                serializable: false
+            }
+        );
+        assert_eq!(
+            Parser::new("begin isolation level serializable;").parse()?,
+            Statement::Begin {
+                read_only: false,
+                serializable: true
+            }
+        );
+        //保留关键字不能用作列名
+        assert!(Parser::new("create table t (id int, select int);").parse().is_err());
+        Ok(())
+    }
 }
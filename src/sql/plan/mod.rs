@@ -1,12 +1,15 @@
+use std::{collections::BTreeMap, ops::Bound};
+
 use planner::Planner;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 use super::{
     engine::Transaction,
     executor::{Executor, ResultSet},
-    parser::ast::{self, Expression},
-    schema::Table,
+    parser::ast::{self, Expression, JoinType, OrderDirection},
+    schema::{AlterTable, Index, Table},
+    types::Value,
 };
 
 mod planner;
@@ -18,6 +21,34 @@ pub enum Node {
         schema: Table,
     },
 
+    //删除表
+    DropTable {
+        table_name: String,
+        if_exists: bool,
+    },
+
+    //清空表
+    TruncateTable {
+        table_name: String,
+    },
+
+    //修改表结构
+    AlterTable {
+        table_name: String,
+        operation: AlterTable,
+    },
+
+    //创建索引
+    CreateIndex {
+        table_name: String,
+        index: Index,
+    },
+
+    //删除索引
+    DropIndex {
+        name: String,
+    },
+
     //插入数据
     Insert {
         table_name: String,
@@ -25,35 +56,198 @@ pub enum Node {
         values: Vec<Vec<Expression>>,
     },
 
-    //扫描节点
+    //更新数据
+    Update {
+        table_name: String,
+        source: Box<Node>,
+        columns: BTreeMap<String, Expression>,
+    },
+
+    //删除数据
+    Delete {
+        table_name: String,
+        source: Box<Node>,
+    },
+
+    //扫描节点, 输出的列名带有表名或者别名
     Scan {
         table_name: String,
+        alias: Option<String>,
+    },
+
+    //主键范围扫描节点, 只扫描主键在范围内的行, 等值条件时上下界相同
+    KeyScan {
+        table_name: String,
+        alias: Option<String>,
+        start: Bound<Value>,
+        end: Bound<Value>,
+    },
+
+    //索引查找节点, 根据索引列的值查找对应的行
+    IndexLookup {
+        table_name: String,
+        alias: Option<String>,
+        column_name: String,
+        value: Value,
+    },
+
+    //嵌套循环连接, 对左侧的每一行扫描右侧所有的行
+    NestedLoopJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        predicate: Option<Expression>,
+        join_type: JoinType,
+    },
+
+    //哈希连接, 用于等值连接, 使用右侧的数据构建哈希表
+    HashJoin {
+        left: Box<Node>,
+        left_column: usize,
+        right: Box<Node>,
+        right_column: usize,
+        join_type: JoinType,
+    },
+
+    //没有 from 时的数据源, 只产生一行没有列的数据
+    Nothing,
+
+    //投影节点, 计算 select 列表中的表达式, 别名为空时使用表达式本身作为列名
+    Projection {
+        source: Box<Node>,
+        expressions: Vec<(Expression, Option<String>)>,
+    },
+
+    //聚集节点, 输出列为分组表达式, 然后是聚集函数的结果
+    //COUNT(*) 的参数为 None
+    Aggregate {
+        source: Box<Node>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<(Aggregate, Option<Expression>)>,
+    },
+
+    //排序节点, 每个排序项为 表达式, 排序方向, NULL 是否排在最前
+    Order {
+        source: Box<Node>,
+        order_by: Vec<(Expression, OrderDirection, bool)>,
+    },
+
+    //跳过前 offset 行
+    Offset {
+        source: Box<Node>,
+        offset: usize,
+    },
+
+    //最多返回 limit 行
+    Limit {
+        source: Box<Node>,
+        limit: usize,
+    },
+
+    //过滤节点, 只保留满足条件的行
+    Filter {
+        source: Box<Node>,
+        predicate: Expression,
     },
 }
 
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    pub fn from_name(name: &str) -> Result<Self> {
+        Ok(match name.to_uppercase().as_ref() {
+            "COUNT" => Aggregate::Count,
+            "SUM" => Aggregate::Sum,
+            "AVG" => Aggregate::Avg,
+            "MIN" => Aggregate::Min,
+            "MAX" => Aggregate::Max,
+            _ => return Err(Error::Internal(format!("unknown function {}", name))),
+        })
+    }
+
+    pub fn to_str(&self) -> &str {
+        match self {
+            Aggregate::Count => "COUNT",
+            Aggregate::Sum => "SUM",
+            Aggregate::Avg => "AVG",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+        }
+    }
+
+    //聚集结果的列名, 和 select 列表中函数调用的文本形式一致
+    pub fn label(&self, arg: &Option<Expression>) -> String {
+        Expression::Function(self.to_str().to_string(), arg.iter().cloned().collect()).to_string()
+    }
+}
+
 //执行计划定义, 底层是不同类型的执行节点
 #[derive(Debug, PartialEq)]
 pub struct Plan(pub Node);
 
 impl Plan {
-    pub fn build(stmt: ast::Statement) -> Self {
-        Planner::new().build(stmt)
+    pub fn build<T: Transaction>(stmt: ast::Statement, txn: &T) -> Result<Self> {
+        Planner::new(txn).build(stmt)
+    }
+
+    pub fn execute<T: Transaction + 'static>(self, txn: &mut T) -> Result<ResultSet> {
+        Ok(match <dyn Executor<T>>::build(self.0).execute(txn)? {
+            ResultSet::Scan { columns, rows } => ResultSet::Scan {
+                columns: Self::strip_qualifiers(columns),
+                rows,
+            },
+            result => result,
+        })
     }
 
-    pub fn execute<T: Transaction>(self, txn: &mut T) -> Result<ResultSet> {
-        <dyn Executor<T>>::build(self.0).execute(txn)
+    //输出时去掉列名中的表名, 有多个同名列时保留表名以便区分
+    fn strip_qualifiers(columns: Vec<String>) -> Vec<String> {
+        let is_ident = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_');
+        let split = |column: &str| {
+            column
+                .split_once('.')
+                .filter(|(t, c)| is_ident(t) && is_ident(c))
+                .map(|(_, c)| c.to_string())
+        };
+        let names: Vec<String> = columns
+            .iter()
+            .map(|it| split(it).unwrap_or(it.clone()))
+            .collect();
+        columns
+            .into_iter()
+            .zip(names.iter())
+            .map(|(column, name)| match names.iter().filter(|it| *it == name).count() {
+                1 => name.clone(),
+                _ => column,
+            })
+            .collect()
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::ops::Bound;
+
     use crate::{
         error::Result,
-        sql::{parser::Parser, plan::Plan},
+        sql::{
+            engine::{kv::KVEngine, Engine, Transaction},
+            parser::{ast::JoinType, Parser},
+            plan::{Node, Plan},
+            types::Value,
+        },
+        storage::memory::MemoryEngine,
     };
 
     #[test]
     fn test_plan_create_table() -> Result<()> {
+        let txn = KVEngine::new(MemoryEngine::new()).begin()?;
         let sql1 = "
             create table tbl1 (
                 a int default 100,
@@ -64,7 +258,7 @@ mod test {
         ";
 
         let stmt1 = Parser::new(&sql1).parse()?;
-        let p1 = Plan::build(stmt1);
+        let p1 = Plan::build(stmt1, &txn)?;
         // println!("{:?}",p1);
 
         let sql2 = "
@@ -77,7 +271,7 @@ mod test {
     ";
 
         let stmt2 = Parser::new(&sql2).parse()?;
-        let p2 = Plan::build(stmt2);
+        let p2 = Plan::build(stmt2, &txn)?;
 
         assert_eq!(p1, p2);
 
@@ -86,15 +280,16 @@ mod test {
 
     #[test]
     fn test_plan_insert() -> Result<()> {
+        let txn = KVEngine::new(MemoryEngine::new()).begin()?;
         let sql1 = "insert into tbl values(1,2,3,'a',true);";
         let stmt1 = Parser::new(&sql1).parse()?;
-        let p1 = Plan::build(stmt1);
+        let p1 = Plan::build(stmt1, &txn)?;
         println!("{:?}", p1);
         // assert!(stmt1.is_ok());
 
         let sql2 = "insert into tb2(c1,c2,c3) values(1,2,3),(4,5,6);";
         let stmt2 = Parser::new(&sql2).parse()?;
-        let p2 = Plan::build(stmt2);
+        let p2 = Plan::build(stmt2, &txn)?;
         println!("{:?}", p2);
         // assert!(stmt2.is_ok());
 
@@ -103,10 +298,165 @@ mod test {
 
     #[test]
     fn test_plan_select() -> Result<()> {
+        let txn = KVEngine::new(MemoryEngine::new()).begin()?;
         let sql = "select * from tbl1;";
         let stmt = Parser::new(&sql).parse()?;
-        let p1 = Plan::build(stmt);
+        let p1 = Plan::build(stmt, &txn)?;
         println!("{:?}", p1);
         Ok(())
     }
+
+    #[test]
+    fn test_plan_select_where() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        kvengine.session()?.execute("create table tbl1 (a int, b int);")?;
+        let txn = kvengine.begin()?;
+        let stmt = Parser::new("select * from tbl1 where b > 1;").parse()?;
+        let p = Plan::build(stmt, &txn)?;
+        assert!(matches!(
+            p,
+            Plan(Node::Filter { source, .. }) if *source == Node::Scan { table_name: "tbl1".to_string(), alias: None }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_key_scan() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        kvengine
+            .session()?
+            .execute("create table tbl1 (a int primary key, b int, c float);")?;
+        kvengine.session()?.execute("create table tbl2 (a float primary key);")?;
+        kvengine.session()?.execute("create index idx_b on tbl1 (b);")?;
+        let txn = kvengine.begin()?;
+        let source = |sql: &str| -> Result<Node> {
+            match Plan::build(Parser::new(sql).parse()?, &txn)? {
+                Plan(Node::Filter { source, .. }) => Ok(*source),
+                p => panic!("unexpected plan {:?}", p),
+            }
+        };
+        let key_scan = |table_name: &str, start: Bound<Value>, end: Bound<Value>| Node::KeyScan {
+            table_name: table_name.to_string(),
+            alias: None,
+            start,
+            end,
+        };
+        let int = Value::Integer;
+
+        //主键上的等值和范围条件合并成主键的范围, 常量在左侧时方向相反
+        assert_eq!(
+            source("select * from tbl1 where a = 3;")?,
+            key_scan("tbl1", Bound::Included(int(3)), Bound::Included(int(3)))
+        );
+        assert_eq!(
+            source("select * from tbl1 where a > 1 and 10 >= a and a < 8 and b > 0;")?,
+            key_scan("tbl1", Bound::Excluded(int(1)), Bound::Excluded(int(8)))
+        );
+        assert_eq!(
+            source("select * from tbl1 where a >= 2 and a > 2;")?,
+            key_scan("tbl1", Bound::Excluded(int(2)), Bound::Unbounded)
+        );
+        //以 0 为界时同时包含 -0.0 和 0.0
+        assert_eq!(
+            source("select * from tbl2 where a > 0.0 and a <= 0.0;")?,
+            key_scan("tbl2", Bound::Included(Value::Float(-0.0)), Bound::Included(Value::Float(0.0)))
+        );
+
+        //两端都有界的主键范围优先于索引查找, 只有一端有界时使用索引
+        assert_eq!(
+            source("select * from tbl1 where b = 2 and a <= 5 and a >= 1;")?,
+            key_scan("tbl1", Bound::Included(int(1)), Bound::Included(int(5)))
+        );
+        assert!(matches!(
+            source("select * from tbl1 where b = 2 and a > 1;")?,
+            Node::IndexLookup { .. }
+        ));
+
+        //OR 条件, 非主键列, 类型不一致和 NULL 仍然全表扫描
+        for sql in [
+            "select * from tbl1 where a = 1 or a = 2;",
+            "select * from tbl1 where c < 1.0;",
+            "select * from tbl1 where a = 1.0;",
+            "select * from tbl1 where a > null;",
+            "select * from tbl1 x where tbl1.a = 1;",
+        ] {
+            assert!(matches!(source(sql)?, Node::Scan { .. }), "{}", sql);
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_index_lookup() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        kvengine
+            .session()?
+            .execute("create table tbl1 (a int primary key, b int, c text);")?;
+        kvengine.session()?.execute("create index idx_b on tbl1 (b);")?;
+        let txn = kvengine.begin()?;
+
+        //索引列上的等值条件使用索引查找
+        let stmt = Parser::new("select * from tbl1 where c = 'x' and 2 = b;").parse()?;
+        let p = Plan::build(stmt, &txn)?;
+        assert!(matches!(
+            p,
+            Plan(Node::Filter { source, .. }) if *source == Node::IndexLookup {
+                table_name: "tbl1".to_string(),
+                alias: None,
+                column_name: "b".to_string(),
+                value: Value::Integer(2),
+            }
+        ));
+
+        //非等值条件或者 OR 条件仍然全表扫描
+        for sql in [
+            "select * from tbl1 where b > 2;",
+            "select * from tbl1 where b = 2 or a = 1;",
+            "select * from tbl1 where c = 'x';",
+        ] {
+            let p = Plan::build(Parser::new(sql).parse()?, &txn)?;
+            assert!(matches!(
+                p,
+                Plan(Node::Filter { source, .. }) if *source == Node::Scan { table_name: "tbl1".to_string(), alias: None }
+            ));
+        }
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new());
+        let mut s = kvengine.session()?;
+        s.execute("create table a (id int primary key, v int);")?;
+        s.execute("create table b (id int primary key, a_id int);")?;
+        let txn = kvengine.begin()?;
+
+        let stmt = Parser::new("select * from a join b on b.a_id = a.id;").parse()?;
+        assert_eq!(
+            Plan::build(stmt, &txn)?,
+            Plan(Node::HashJoin {
+                left: Box::new(Node::Scan { table_name: "a".to_string(), alias: None }),
+                left_column: 0,
+                right: Box::new(Node::Scan { table_name: "b".to_string(), alias: None }),
+                right_column: 1,
+                join_type: JoinType::Inner,
+            })
+        );
+
+        //两侧都是同一个表的列, 或者不是等值条件时使用嵌套循环连接
+        for sql in [
+            "select * from a x left join b on x.id = x.v;",
+            "select * from a x left join b on x.id < b.a_id;",
+            "select * from a cross join b;",
+        ] {
+            let p = Plan::build(Parser::new(sql).parse()?, &txn)?;
+            assert!(matches!(p, Plan(Node::NestedLoopJoin { .. })));
+        }
+
+        let stmt = Parser::new("select * from a join b on id = a_id;").parse()?;
+        assert!(Plan::build(stmt, &txn).is_err());
+        txn.commit()?;
+        Ok(())
+    }
 }
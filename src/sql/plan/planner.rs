@@ -1,42 +1,499 @@
-use crate::sql::{parser::ast, schema::{self, Table},types::Value};
+use std::{cmp::Ordering, ops::Bound};
 
-use super::{Node, Plan};
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::{self, Consts, Expression, Operation, OrderDirection},
+        schema::{self, AlterTable, Table},
+        types::Value,
+    },
+};
 
+use super::{Aggregate, Node, Plan};
 
-pub struct Planner;
 
-impl Planner {
-    pub fn new() -> Self{
-        Self{}
+pub struct Planner<'a, T: Transaction> {
+    txn: &'a T,
+}
+
+impl<'a, T: Transaction> Planner<'a, T> {
+    pub fn new(txn: &'a T) -> Self{
+        Self{ txn }
     }
 
-    pub fn build(&mut self,stmt : ast::Statement) -> Plan{
-        Plan(self.build_statement(stmt))
+    pub fn build(&mut self,stmt : ast::Statement) -> Result<Plan>{
+        Ok(Plan(self.build_statement(stmt)?))
     }
 
-    fn build_statement(&self,stmt:ast::Statement) -> Node{
-        match stmt {
-            ast::Statement::CreateTable { name, columns } => 
+    fn build_statement(&self,stmt:ast::Statement) -> Result<Node>{
+        Ok(match stmt {
+            //事务控制语句由 session 处理
+            ast::Statement::Begin { .. }
+            | ast::Statement::Commit
+            | ast::Statement::Rollback
+            | ast::Statement::Savepoint { .. }
+            | ast::Statement::RollbackToSavepoint { .. }
+            | ast::Statement::ReleaseSavepoint { .. }
+            | ast::Statement::Vacuum
+            | ast::Statement::ShowVersion =>
+                return Err(Error::Internal("unexpected transaction statement".into())),
+            ast::Statement::CreateTable { name, columns } => {
+                //没有声明主键时, 默认第一列为主键
+                let has_primary_key = columns.iter().any(|it| it.primary_key);
                 Node::CreateTable { schema: Table{
                     name:name,
-                    columns:columns.into_iter().map(|it| {
-                        let nullable = it.nullable.unwrap_or(true);
-                        let default = match it.default {
-                            Some(expr) => Some(Value::from_expression(expr)),
-                            None if nullable => Some(Value::Null),
-                            None => None
-                        };
-                        schema::Column{
-                            name : it.name,
-                            datatype : it.datatype,
-                            nullable,
-                            default
-                        }
-                    }).collect()
-                 } },
+                    indexes:Vec::new(),
+                    columns:columns.into_iter().enumerate().map(|(i, it)| {
+                        let primary_key = it.primary_key || (!has_primary_key && i == 0);
+                        Self::build_column(it, primary_key)
+                    }).collect::<Result<_>>()?
+                 } }
+            },
+            ast::Statement::DropTable { name, if_exists } =>
+                Node::DropTable { table_name: name, if_exists },
+            ast::Statement::TruncateTable { name } => Node::TruncateTable { table_name: name },
+            ast::Statement::AlterTable { name, operation } => Node::AlterTable {
+                table_name: name,
+                operation: match operation {
+                    ast::AlterTableOperation::AddColumn(column) => {
+                        let primary_key = column.primary_key;
+                        AlterTable::AddColumn(Self::build_column(column, primary_key)?)
+                    }
+                    ast::AlterTableOperation::DropColumn(column_name) =>
+                        AlterTable::DropColumn(column_name),
+                    ast::AlterTableOperation::RenameColumn { old_name, new_name } =>
+                        AlterTable::RenameColumn(old_name, new_name),
+                    ast::AlterTableOperation::RenameTable(new_name) =>
+                        AlterTable::RenameTable(new_name),
+                },
+            },
+            ast::Statement::CreateIndex { name, table_name, column_name, unique } =>
+                Node::CreateIndex {
+                    table_name,
+                    index: schema::Index { name, column_name, unique },
+                },
+            ast::Statement::DropIndex { name } => Node::DropIndex { name },
             ast::Statement::Insert { table_name, columns, values } => 
                 Node::Insert { table_name, columns: columns.unwrap_or_default(), values },
-            ast::Statement::Select { table_name } => Node::Scan { table_name } ,
+            stmt @ ast::Statement::Select { .. } => self.build_select(stmt)?,
+            ast::Statement::Update { table_name, columns, where_clause } => 
+                Node::Update {
+                    table_name: table_name.clone(),
+                    source: Box::new(self.build_scan(table_name, None, where_clause)?),
+                    columns,
+                },
+            ast::Statement::Delete { table_name, where_clause } => 
+                Node::Delete {
+                    table_name: table_name.clone(),
+                    source: Box::new(self.build_scan(table_name, None, where_clause)?),
+                },
+        })
+    }
+
+    fn build_column(column: ast::Column, primary_key: bool) -> Result<schema::Column>{
+        //主键默认不能为空
+        let nullable = column.nullable.unwrap_or(!primary_key);
+        let default = match column.default {
+            Some(expr) => Some(Value::from_expression(expr)?),
+            None if nullable => Some(Value::Null),
+            None => None
+        };
+        Ok(schema::Column{
+            name : column.name,
+            datatype : column.datatype,
+            nullable,
+            default,
+            primary_key
+        })
+    }
+
+    fn build_select(&self, stmt: ast::Statement) -> Result<Node>{
+        //as of 由 session 开启对应版本的事务处理
+        let ast::Statement::Select {
+            mut select, from, where_clause, group_by, having, order_by, limit, offset, ..
+        } = stmt else {
+            return Err(Error::Internal("unexpected statement".into()));
+        };
+
+        let mut node = match from {
+            Some(ast::FromItem::Table { name, alias }) => self.build_scan(name, alias, where_clause)?,
+            Some(item) => {
+                let (node, _) = self.build_from(item)?;
+                match where_clause {
+                    Some(predicate) => Node::Filter { source: Box::new(node), predicate },
+                    None => node,
+                }
+            }
+            None => {
+                if select.is_empty() {
+                    return Err(Error::Internal("select * requires a from clause".into()));
+                }
+                match where_clause {
+                    Some(predicate) => Node::Filter { source: Box::new(Node::Nothing), predicate },
+                    None => Node::Nothing,
+                }
+            }
+        };
+
+        //排序表达式引用的别名需要替换成对应的表达式
+        let mut order_by: Vec<_> = order_by.into_iter().map(|it| {
+            let expr = match &it.expr {
+                Expression::Field(None, name) => select
+                    .iter()
+                    .find(|(_, alias)| alias.as_ref() == Some(name))
+                    .map(|(expr, _)| expr.clone())
+                    .unwrap_or(it.expr),
+                _ => it.expr,
+            };
+            let nulls_first = it.nulls_first.unwrap_or(it.direction == OrderDirection::Desc);
+            (expr, it.direction, nulls_first)
+        }).collect();
+
+        //有 group by 或者使用了聚集函数时, 需要先进行聚集
+        let aggregation = !group_by.is_empty()
+            || having.is_some()
+            || select.iter().any(|(expr, _)| expr.contains_aggregate())
+            || order_by.iter().any(|(expr, _, _)| expr.contains_aggregate());
+        let mut having = having;
+        if aggregation {
+            if select.is_empty() {
+                return Err(Error::Internal("select * is not allowed with aggregation".into()));
+            }
+            let aggregates = Self::collect_aggregates(
+                select.iter().map(|(expr, _)| expr)
+                    .chain(having.iter())
+                    .chain(order_by.iter().map(|(expr, _, _)| expr)),
+            )?;
+
+            //聚集之后的表达式引用聚集节点的输出列
+            //改写前先记下原来的列名, 保证输出的列名不变
+            for (expr, alias) in select.iter_mut() {
+                if alias.is_none() && !matches!(expr, Expression::Field(..)) {
+                    *alias = Some(expr.to_string());
+                }
+            }
+            let rewrite = |expr: Expression| expr.transform(&mut |e| match e {
+                Expression::Function(..) => Some(Expression::Field(None, e.to_string())),
+                Expression::Field(..) => None,
+                e => group_by
+                    .iter()
+                    .any(|it| it == e)
+                    .then(|| Expression::Field(None, e.to_string())),
+            });
+            select = select.into_iter().map(|(expr, alias)| (rewrite(expr), alias)).collect();
+            having = having.map(rewrite);
+            order_by = order_by
+                .into_iter()
+                .map(|(expr, direction, nulls_first)| (rewrite(expr), direction, nulls_first))
+                .collect();
+
+            node = Node::Aggregate { source: Box::new(node), group_by, aggregates };
+        }
+
+        if let Some(predicate) = having {
+            node = Node::Filter { source: Box::new(node), predicate };
+        }
+
+        //排序在投影之前执行, 这样可以按照没有出现在 select 列表中的列排序
+        if !order_by.is_empty() {
+            node = Node::Order { source: Box::new(node), order_by };
+        }
+
+        //select * 不需要投影
+        if !select.is_empty() {
+            node = Node::Projection { source: Box::new(node), expressions: select };
+        }
+
+        if let Some(expr) = offset {
+            node = Node::Offset { source: Box::new(node), offset: Self::evaluate_count(expr)? };
+        }
+        if let Some(expr) = limit {
+            node = Node::Limit { source: Box::new(node), limit: Self::evaluate_count(expr)? };
+        }
+        Ok(node)
+    }
+
+    //收集表达式中的聚集函数, 相同的聚集函数只计算一次
+    fn collect_aggregates<'e>(
+        exprs: impl Iterator<Item = &'e Expression>,
+    ) -> Result<Vec<(Aggregate, Option<Expression>)>>{
+        let mut functions = Vec::new();
+        for expr in exprs {
+            expr.walk(&mut |e| {
+                if let Expression::Function(name, args) = e {
+                    functions.push((name.clone(), args.clone()));
+                }
+            });
+        }
+
+        let mut aggregates = Vec::new();
+        for (name, mut args) in functions {
+            let aggregate = Aggregate::from_name(&name)?;
+            let arg = match (aggregate, args.len()) {
+                (Aggregate::Count, 0) => None,
+                (_, 1) => args.pop(),
+                _ => return Err(Error::Internal(format!(
+                    "invalid number of arguments for function {}",
+                    name
+                ))),
+            };
+            //聚集函数不能嵌套
+            if arg.as_ref().is_some_and(|it| it.contains_aggregate()) {
+                return Err(Error::Internal(format!(
+                    "aggregate function {} can not be nested",
+                    name
+                )));
+            }
+            if !aggregates.contains(&(aggregate, arg.clone())) {
+                aggregates.push((aggregate, arg));
+            }
+        }
+        Ok(aggregates)
+    }
+
+    //计算 limit 和 offset 的值, 必须是非负整数
+    fn evaluate_count(expr: Expression) -> Result<usize>{
+        match Value::from_expression(expr)? {
+            Value::Integer(i) if i >= 0 => Ok(i as usize),
+            v => Err(Error::Internal(format!("invalid limit or offset value {}", v))),
+        }
+    }
+
+    /**
+     * 构建 from 子句中的连接, 同时返回输出的列名, 列名的形式为 表名.列名
+     * 连接条件是两侧列的等值判断时使用 HashJoin, 否则使用 NestedLoopJoin
+     */
+    fn build_from(&self, item: ast::FromItem) -> Result<(Node, Vec<String>)>{
+        Ok(match item {
+            ast::FromItem::Table { name, alias } => {
+                let table = self.txn.must_get_table(name.clone())?;
+                let qualifier = alias.clone().unwrap_or(name.clone());
+                let columns = table.columns.iter()
+                    .map(|it| format!("{}.{}", qualifier, it.name))
+                    .collect();
+                (Node::Scan { table_name: name, alias }, columns)
+            }
+            ast::FromItem::Join { left, right, join_type, predicate } => {
+                let (left, left_columns) = self.build_from(*left)?;
+                let (right, right_columns) = self.build_from(*right)?;
+
+                //同一个表名或别名不能出现多次
+                let qualifier = |column: &String| column.split_once('.').map(|(q, _)| q.to_string());
+                if let Some(q) = right_columns.iter()
+                    .filter_map(qualifier)
+                    .find(|q| left_columns.iter().filter_map(qualifier).any(|it| it == *q))
+                {
+                    return Err(Error::Internal(format!("table {} specified more than once", q)));
+                }
+
+                let join_type = match join_type {
+                    ast::JoinType::Cross => ast::JoinType::Inner,
+                    join_type => join_type,
+                };
+                let mut columns = left_columns;
+                let left_len = columns.len();
+                columns.extend(right_columns);
+
+                let node = match Self::find_hash_join(&columns, left_len, predicate.as_ref())? {
+                    Some((left_column, right_column)) => Node::HashJoin {
+                        left: Box::new(left),
+                        left_column,
+                        right: Box::new(right),
+                        right_column,
+                        join_type,
+                    },
+                    None => Node::NestedLoopJoin {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        predicate,
+                        join_type,
+                    },
+                };
+                (node, columns)
+            }
+        })
+    }
+
+    //连接条件为 左侧列 = 右侧列 时, 返回两侧列在各自输出中的位置
+    fn find_hash_join(
+        columns: &[String],
+        left_len: usize,
+        predicate: Option<&Expression>,
+    ) -> Result<Option<(usize, usize)>>{
+        let (lhs, rhs) = match predicate {
+            Some(Expression::Operation(Operation::Equal(lhs, rhs))) => (lhs, rhs),
+            _ => return Ok(None),
+        };
+        let (l, r) = match (lhs.as_ref(), rhs.as_ref()) {
+            (Expression::Field(lt, l), Expression::Field(rt, r)) => (
+                Expression::resolve_field(columns, lt.as_deref(), l)?,
+                Expression::resolve_field(columns, rt.as_deref(), r)?,
+            ),
+            _ => return Ok(None),
+        };
+        Ok(match (l < left_len, r < left_len) {
+            (true, false) => Some((l, r - left_len)),
+            (false, true) => Some((r, l - left_len)),
+            _ => None,
+        })
+    }
+
+    //扫描表, 有 where 条件时在扫描结果上过滤
+    //条件中包含主键的范围判断或者索引列的等值判断时, 只读取可能满足条件的行, 代替全表扫描
+    fn build_scan(
+        &self,
+        table_name: String,
+        alias: Option<String>,
+        where_clause: Option<Expression>,
+    ) -> Result<Node>{
+        let predicate = match where_clause {
+            Some(predicate) => predicate,
+            None => return Ok(Node::Scan { table_name, alias }),
+        };
+
+        let table = self.txn.must_get_table(table_name.clone())?;
+        let qualifier = alias.as_deref().unwrap_or(&table_name);
+        let range = Self::find_key_range(&table, qualifier, &predicate);
+        let node = match (range, Self::find_index_lookup(&table, qualifier, &predicate)) {
+            //两端都有界的主键范围优先, 只有一端有界时优先使用索引查找
+            (Some((start, end)), _) if start != Bound::Unbounded && end != Bound::Unbounded =>
+                Node::KeyScan { table_name, alias, start, end },
+            (_, Some((column_name, value))) => Node::IndexLookup { table_name, alias, column_name, value },
+            (Some((start, end)), None) => Node::KeyScan { table_name, alias, start, end },
+            (None, None) => Node::Scan { table_name, alias },
+        };
+        //主键范围扫描和索引查找的结果仍然需要经过完整的条件过滤
+        Ok(Node::Filter { source: Box::new(node), predicate })
+    }
+
+    /**
+     * 在 AND 连接的条件中查找 主键 比较运算 常量 形式的判断, 合并成主键的范围
+     * 得到的范围不会比条件更窄, 扫描的结果仍然需要经过完整的条件过滤
+     */
+    fn find_key_range(
+        table: &Table,
+        qualifier: &str,
+        predicate: &Expression,
+    ) -> Option<(Bound<Value>, Bound<Value>)>{
+        let mut ranges = Vec::new();
+        Self::collect_key_ranges(table, qualifier, predicate, &mut ranges);
+        let mut ranges = ranges.into_iter();
+        let first = ranges.next()?;
+        let (start, end) = ranges.fold(first, |(start, end), (s, e)| (
+            Self::tighter_bound(start, s, Ordering::Greater),
+            Self::tighter_bound(end, e, Ordering::Less),
+        ));
+        //-0.0 和 0.0 相等, 但是编码成不同的 key, 以 0 为界时把两个都包含在范围内
+        let widen = |bound: Bound<Value>, zero: f64| match bound {
+            Bound::Included(Value::Float(f)) | Bound::Excluded(Value::Float(f)) if f == 0.0 =>
+                Bound::Included(Value::Float(zero)),
+            bound => bound,
+        };
+        Some((widen(start, -0.0), widen(end, 0.0)))
+    }
+
+    fn collect_key_ranges(
+        table: &Table,
+        qualifier: &str,
+        predicate: &Expression,
+        ranges: &mut Vec<(Bound<Value>, Bound<Value>)>,
+    ){
+        let Expression::Operation(op) = predicate else {
+            return;
+        };
+        let (lhs, rhs) = match op {
+            Operation::And(lhs, rhs) => {
+                Self::collect_key_ranges(table, qualifier, lhs, ranges);
+                Self::collect_key_ranges(table, qualifier, rhs, ranges);
+                return;
+            }
+            Operation::Equal(lhs, rhs)
+            | Operation::GreaterThan(lhs, rhs)
+            | Operation::GreaterThanOrEqual(lhs, rhs)
+            | Operation::LessThan(lhs, rhs)
+            | Operation::LessThanOrEqual(lhs, rhs) => (lhs, rhs),
+            _ => return,
+        };
+        //常量在左侧时比较的方向相反
+        let (column_table, column_name, consts, reversed) = match (lhs.as_ref(), rhs.as_ref()) {
+            (Expression::Field(t, name), Expression::Consts(c)) => (t, name, c, false),
+            (Expression::Consts(c), Expression::Field(t, name)) => (t, name, c, true),
+            _ => return,
+        };
+        if column_table.as_ref().is_some_and(|it| it != qualifier) || *consts == Consts::Null {
+            return;
+        }
+        let Some(column) = table.columns.iter().find(|it| it.primary_key && it.name == *column_name) else {
+            return;
+        };
+        //值的类型和主键类型一致时, key 的顺序才和值的大小一致
+        let Ok(value) = Value::from_expression(Expression::Consts(consts.clone())) else {
+            return;
+        };
+        if value.datatype().as_ref() != Some(&column.datatype) {
+            return;
+        }
+        ranges.push(match (op, reversed) {
+            (Operation::Equal(..), _) => (Bound::Included(value.clone()), Bound::Included(value)),
+            (Operation::GreaterThan(..), false) | (Operation::LessThan(..), true) =>
+                (Bound::Excluded(value), Bound::Unbounded),
+            (Operation::GreaterThanOrEqual(..), false) | (Operation::LessThanOrEqual(..), true) =>
+                (Bound::Included(value), Bound::Unbounded),
+            (Operation::LessThan(..), false) | (Operation::GreaterThan(..), true) =>
+                (Bound::Unbounded, Bound::Excluded(value)),
+            _ => (Bound::Unbounded, Bound::Included(value)),
+        });
+    }
+
+    //取两个下界中更大的, 或者两个上界中更小的, 值相等时开区间更严格
+    fn tighter_bound(a: Bound<Value>, b: Bound<Value>, tighter: Ordering) -> Bound<Value>{
+        let ordering = match (&a, &b) {
+            (Bound::Unbounded, _) => return b,
+            (_, Bound::Unbounded) => return a,
+            (Bound::Included(x) | Bound::Excluded(x), Bound::Included(y) | Bound::Excluded(y)) =>
+                x.compare(y).ok().flatten(),
+        };
+        match ordering {
+            Some(ordering) if ordering == tighter => a,
+            Some(Ordering::Equal) if matches!(a, Bound::Excluded(_)) => a,
+            _ => b,
+        }
+    }
+
+    //在 AND 连接的条件中查找 索引列 = 常量 形式的判断
+    fn find_index_lookup(
+        table: &Table,
+        qualifier: &str,
+        predicate: &Expression,
+    ) -> Option<(String, Value)>{
+        match predicate {
+            Expression::Operation(Operation::And(lhs, rhs)) =>
+                Self::find_index_lookup(table, qualifier, lhs)
+                    .or_else(|| Self::find_index_lookup(table, qualifier, rhs)),
+            Expression::Operation(Operation::Equal(lhs, rhs)) => {
+                let (column_table, column_name, consts) = match (lhs.as_ref(), rhs.as_ref()) {
+                    (Expression::Field(t, name), Expression::Consts(c))
+                    | (Expression::Consts(c), Expression::Field(t, name)) => (t, name, c),
+                    _ => return None,
+                };
+                if column_table.as_ref().is_some_and(|it| it != qualifier) {
+                    return None;
+                }
+                //NULL 不等于任何值, 不需要走索引
+                if *consts == Consts::Null {
+                    return None;
+                }
+                let index = table.get_index_by_column(column_name)?;
+                let value = Value::from_expression(Expression::Consts(consts.clone())).ok()?;
+                //值的类型和列类型一致时才能在索引中精确匹配
+                let column = table.columns.iter().find(|it| it.name == *column_name)?;
+                if value.datatype()? != column.datatype {
+                    return None;
+                }
+                Some((index.column_name.clone(), value))
+            }
+            _ => None,
         }
     }
-}
\ No newline at end of file
+}
@@ -1,12 +1,17 @@
 use serde::{Deserialize, Serialize};
 
-use crate::sql::types::{DataType, Value};
+use crate::{
+    error::{Error, Result},
+    sql::types::{DataType, Row, Value},
+};
 
 
 #[derive(Debug,PartialEq,Serialize,Deserialize)]
 pub struct Table{
     pub name:String,
     pub columns:Vec<Column>,
+    //表上的二级索引
+    pub indexes:Vec<Index>,
 }
 
 #[derive(Debug,PartialEq,Serialize,Deserialize)]
@@ -14,6 +19,132 @@ pub struct Column{
     pub name:String,
     pub datatype:DataType,
     pub nullable:bool,
-    pub default:Option<Value>
-}   
+    pub default:Option<Value>,
+    pub primary_key:bool
+}
+
+//二级索引定义, 和表结构一起存储
+#[derive(Debug,PartialEq,Serialize,Deserialize,Clone)]
+pub struct Index{
+    pub name:String,
+    pub column_name:String,
+    pub unique:bool
+}
+
+//修改表结构的操作
+#[derive(Debug,PartialEq)]
+pub enum AlterTable{
+    AddColumn(Column),
+    DropColumn(String),
+    RenameColumn(String,String),
+    RenameTable(String)
+}
+
+impl Table {
+    //校验行数据和表结构是否一致, 包括列数、类型以及是否可以为空
+    pub fn validate_row(&self, row: &Row) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(Error::Internal(format!(
+                "table {} expects {} columns, got {}",
+                self.name,
+                self.columns.len(),
+                row.len()
+            )));
+        }
+
+        for (col, value) in self.columns.iter().zip(row.iter()) {
+            match value.datatype() {
+                None if col.nullable => {}
+                None => {
+                    return Err(Error::Internal(format!(
+                        "column {} can not be null",
+                        col.name
+                    )))
+                }
+                Some(datatype) if datatype != col.datatype => {
+                    return Err(Error::Internal(format!(
+                        "column {} type mismatch",
+                        col.name
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
 
+    //校验表结构, 必须有且只有一个主键, 并且主键不能为空
+    pub fn validate(&self) -> Result<()> {
+        if self.columns.is_empty() {
+            return Err(Error::Internal(format!(
+                "table {} has no columns",
+                self.name
+            )));
+        }
+
+        match self.columns.iter().filter(|it| it.primary_key).count() {
+            1 => {}
+            0 => {
+                return Err(Error::Internal(format!(
+                    "table {} has no primary key",
+                    self.name
+                )))
+            }
+            _ => {
+                return Err(Error::Internal(format!(
+                    "table {} has multiple primary keys",
+                    self.name
+                )))
+            }
+        }
+
+        for col in &self.columns {
+            if col.primary_key && col.nullable {
+                return Err(Error::Internal(format!(
+                    "primary key {} can not be nullable",
+                    col.name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    //获取主键列的位置
+    pub fn get_primary_key_index(&self) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|it| it.primary_key)
+            .ok_or(Error::Internal(format!(
+                "table {} has no primary key",
+                self.name
+            )))
+    }
+
+    //获取行的主键
+    pub fn get_primary_key(&self, row: &Row) -> Result<Value> {
+        let index = self.get_primary_key_index()?;
+        match row.get(index) {
+            Some(Value::Null) | None => Err(Error::Internal(format!(
+                "primary key of table {} can not be null",
+                self.name
+            ))),
+            Some(value) => Ok(value.clone()),
+        }
+    }
+
+    //获取列上的索引
+    pub fn get_index_by_column(&self, column_name: &str) -> Option<&Index> {
+        self.indexes.iter().find(|it| it.column_name == column_name)
+    }
+
+    //获取列在表中的位置
+    pub fn get_column_index(&self, column_name: &str) -> Result<usize> {
+        self.columns
+            .iter()
+            .position(|it| it.name == column_name)
+            .ok_or(Error::Internal(format!(
+                "column {} does not exists in table {}",
+                column_name, self.name
+            )))
+    }
+}
@@ -1,6 +1,15 @@
+use std::{
+    cmp::Ordering,
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
+
 use serde::{Deserialize, Serialize};
 
-use crate::sql::parser::ast::{Consts, Expression};
+use crate::{
+    error::{Error, Result},
+    sql::parser::ast::Expression,
+};
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum DataType {
@@ -20,14 +29,9 @@ pub enum Value {
 }
 
 impl Value {
-    pub fn from_expression(expr: Expression) -> Self {
-        match expr {
-            Expression::Consts(Consts::Null) => Self::Null,
-            Expression::Consts(Consts::Boolean(bool)) => Self::Boolean(bool),
-            Expression::Consts(Consts::Float(f)) => Self::Float(f),
-            Expression::Consts(Consts::Integer(i)) => Self::Integer(i),
-            Expression::Consts(Consts::String(s)) => Self::String(s),
-        }
+    //计算常量表达式的值, 表达式中不能引用列
+    pub fn from_expression(expr: Expression) -> Result<Self> {
+        expr.evaluate(&[], &Vec::new())
     }
 
     pub fn datatype(&self) -> Option<DataType> {
@@ -39,6 +43,230 @@ impl Value {
             Self::String(_) => Some(DataType::String),
         }
     }
+
+    /**
+     * 比较两个值的大小, 整数和浮点数之间可以互相比较
+     * 任意一个值为 NULL 时返回 None, 类型不兼容时返回错误
+     */
+    pub fn compare(&self, other: &Value) -> Result<Option<Ordering>> {
+        Ok(match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Boolean(l), Value::Boolean(r)) => Some(l.cmp(r)),
+            (Value::Integer(l), Value::Integer(r)) => Some(l.cmp(r)),
+            (Value::Integer(l), Value::Float(r)) => (*l as f64).partial_cmp(r),
+            (Value::Float(l), Value::Integer(r)) => l.partial_cmp(&(*r as f64)),
+            (Value::Float(l), Value::Float(r)) => l.partial_cmp(r),
+            (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+            (l, r) => return Err(Error::Internal(format!("can not compare {} and {}", l, r))),
+        })
+    }
+
+    /**
+     * 所有值之间的全序, 用于排序
+     * NULL < 布尔值 < 数值 < 字符串, 整数和浮点数按照数值大小比较, NaN 大于其他所有数值
+     */
+    pub fn total_cmp(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Boolean(l), Value::Boolean(r)) => l.cmp(r),
+            (Value::Integer(l), Value::Integer(r)) => l.cmp(r),
+            (Value::Integer(l), Value::Float(r)) => Self::float_cmp(*l as f64, *r),
+            (Value::Float(l), Value::Integer(r)) => Self::float_cmp(*l, *r as f64),
+            (Value::Float(l), Value::Float(r)) => Self::float_cmp(*l, *r),
+            (Value::String(l), Value::String(r)) => l.cmp(r),
+            (l, r) => l.type_rank().cmp(&r.type_rank()),
+        }
+    }
+
+    //NaN 排在最后, 0.0 和 -0.0 相等
+    fn float_cmp(l: f64, r: f64) -> Ordering {
+        match (l.is_nan(), r.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => l.partial_cmp(&r).unwrap_or(Ordering::Equal),
+        }
+    }
+
+    fn type_rank(&self) -> u8 {
+        match self {
+            Value::Null => 0,
+            Value::Boolean(_) => 1,
+            Value::Integer(_) | Value::Float(_) => 2,
+            Value::String(_) => 3,
+        }
+    }
+
+    pub fn checked_add(&self, other: &Value) -> Result<Value> {
+        self.arithmetic(other, "add", i64::checked_add, |l, r| l + r)
+    }
+
+    pub fn checked_sub(&self, other: &Value) -> Result<Value> {
+        self.arithmetic(other, "subtract", i64::checked_sub, |l, r| l - r)
+    }
+
+    pub fn checked_mul(&self, other: &Value) -> Result<Value> {
+        self.arithmetic(other, "multiply", i64::checked_mul, |l, r| l * r)
+    }
+
+    //整数除以 0 报错, 有浮点数参与时按照浮点数的语义得到无穷大或者 NaN
+    pub fn checked_div(&self, other: &Value) -> Result<Value> {
+        if let (Value::Integer(_), Value::Integer(0)) = (self, other) {
+            return Err(Error::Internal("can not divide by zero".into()));
+        }
+        self.arithmetic(other, "divide", i64::checked_div, |l, r| l / r)
+    }
+
+    pub fn checked_rem(&self, other: &Value) -> Result<Value> {
+        if let (Value::Integer(_), Value::Integer(0)) = (self, other) {
+            return Err(Error::Internal("can not divide by zero".into()));
+        }
+        self.arithmetic(other, "modulo", i64::checked_rem, |l, r| l % r)
+    }
+
+    //算术运算, 整数运算溢出时报错, 整数和浮点数运算结果为浮点数
+    fn arithmetic<I, F>(&self, other: &Value, name: &str, int_op: I, float_op: F) -> Result<Value>
+    where
+        I: Fn(i64, i64) -> Option<i64>,
+        F: Fn(f64, f64) -> f64,
+    {
+        Ok(match (self, other) {
+            (Value::Integer(l), Value::Integer(r)) => Value::Integer(
+                int_op(*l, *r).ok_or(Error::Internal("integer overflow".into()))?,
+            ),
+            (Value::Integer(l), Value::Float(r)) => Value::Float(float_op(*l as f64, *r)),
+            (Value::Float(l), Value::Integer(r)) => Value::Float(float_op(*l, *r as f64)),
+            (Value::Float(l), Value::Float(r)) => Value::Float(float_op(*l, *r)),
+            (Value::Null, Value::Null | Value::Integer(_) | Value::Float(_))
+            | (Value::Integer(_) | Value::Float(_), Value::Null) => Value::Null,
+            (l, r) => return Err(Error::Internal(format!("can not {} {} and {}", name, l, r))),
+        })
+    }
+}
+
+/**
+ * 作为哈希表键的值, 用于分组和哈希连接
+ * 整数值的浮点数转换成整数, 0.0 和 -0.0 都转换成 0, 所有的 NaN 相等
+ * 相等的键哈希值一定相同
+ */
+#[derive(Debug, Clone)]
+pub struct HashKey(Value);
+
+impl HashKey {
+    pub fn new(value: &Value) -> Self {
+        match value {
+            Value::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+                HashKey(Value::Integer(*f as i64))
+            }
+            v => HashKey(v.clone()),
+        }
+    }
+}
+
+impl PartialEq for HashKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Value::Float(l), Value::Float(r)) => l == r || (l.is_nan() && r.is_nan()),
+            (l, r) => l == r,
+        }
+    }
+}
+
+impl Eq for HashKey {}
+
+impl Hash for HashKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(&self.0).hash(state);
+        match &self.0 {
+            Value::Null => {}
+            Value::Boolean(b) => b.hash(state),
+            Value::Integer(i) => i.hash(state),
+            Value::Float(f) if f.is_nan() => f64::NAN.to_bits().hash(state),
+            Value::Float(f) => f.to_bits().hash(state),
+            Value::String(s) => s.hash(state),
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(b) if *b => write!(f, "TRUE"),
+            Value::Boolean(_) => write!(f, "FALSE"),
+            Value::Integer(i) => write!(f, "{}", i),
+            //整数值的浮点数也带上小数点, 和整数区分
+            Value::Float(v) if v.fract() == 0.0 => write!(f, "{:.1}", v),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::String(s) => write!(f, "{}", s),
+        }
+    }
 }
 
 pub type Row = Vec<Value>;
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cmp::Ordering,
+        collections::hash_map::DefaultHasher,
+        hash::{Hash, Hasher},
+    };
+
+    use super::{HashKey, Value};
+
+    #[test]
+    fn test_total_cmp() {
+        let mut values = vec![
+            Value::String("b".to_string()),
+            Value::Float(f64::NAN),
+            Value::Integer(2),
+            Value::Null,
+            Value::Float(1.5),
+            Value::Boolean(true),
+            Value::String("a".to_string()),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Boolean(false),
+            Value::Integer(-3),
+        ];
+        values.sort_by(|a, b| a.total_cmp(b));
+        assert_eq!(values[0], Value::Null);
+        assert_eq!(values[1], Value::Boolean(false));
+        assert_eq!(values[2], Value::Boolean(true));
+        assert_eq!(values[3], Value::Float(f64::NEG_INFINITY));
+        assert_eq!(values[4..6], [Value::Integer(-3), Value::Float(1.5)]);
+        assert_eq!(values[6], Value::Integer(2));
+        assert!(matches!(values[7], Value::Float(f) if f.is_nan()));
+        assert_eq!(
+            values[8..],
+            [Value::String("a".to_string()), Value::String("b".to_string())]
+        );
+
+        assert_eq!(Value::Integer(1).total_cmp(&Value::Float(1.0)), Ordering::Equal);
+        assert_eq!(Value::Float(0.0).total_cmp(&Value::Float(-0.0)), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_hash_key() {
+        let hash = |key: &HashKey| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        //和 total_cmp 相等的值对应相同的键, 哈希值也相同
+        let equal = [
+            (Value::Float(0.0), Value::Float(-0.0)),
+            (Value::Float(f64::NAN), Value::Float(-f64::NAN)),
+            (Value::Integer(1), Value::Float(1.0)),
+            (Value::Null, Value::Null),
+        ];
+        for (l, r) in equal {
+            assert_eq!(l.total_cmp(&r), Ordering::Equal);
+            let (l, r) = (HashKey::new(&l), HashKey::new(&r));
+            assert_eq!(l, r);
+            assert_eq!(hash(&l), hash(&r));
+        }
+        assert_ne!(HashKey::new(&Value::Float(1.5)), HashKey::new(&Value::Integer(1)));
+        assert_ne!(HashKey::new(&Value::Float(f64::NAN)), HashKey::new(&Value::Null));
+    }
+}
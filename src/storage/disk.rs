@@ -1,75 +1,543 @@
 use std::{
     collections::{btree_map, BTreeMap},
-    fs::{self, rename, File, OpenOptions},
-    io::{BufReader, BufWriter, Read, Seek, Write},
-    path::PathBuf,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use fs4::fs_std::FileExt;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
-use super::engine::{Engine, EngineIterator};
+use super::engine::{Engine, EngineIterator, Status, WriteBatch};
 
 /**
  * 定义磁盘存储引擎
- * 使用bitcast存储模型
+ * 使用bitcask存储模型
+ * 数据目录中包含多个日志段 00000001.log, 00000002.log ..., 编号最大的段是当前写入的段
+ * 写满之后切换到新的段, 旧的段不再修改
+ * 压缩时生成的段带有 hint 文件, 记录 key 和 value 的位置, 启动时不需要读取整个日志
+ * 每个段以文件头开始: 魔数和格式版本号, 不认识的版本拒绝打开
+ * 没有文件头的旧格式需要先用 DiskEngine::upgrade 离线转换
 **/
 
-pub type KeyDir = BTreeMap<Vec<u8>, (u64, u32)>;
+//value 在日志中的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValuePosition {
+    pub segment_id: u64,
+    pub offset: u64,
+    pub size: u32,
+}
+
+pub type KeyDir = BTreeMap<Vec<u8>, ValuePosition>;
+
+//hint 文件中的条目: key, value 的偏移量和大小
+type HintEntry = (Vec<u8>, u64, u32);
+
+//日志记录头: crc, key_size, value_size
+const LOG_HEADER_SIZE: u32 = 12;
+
+//段的文件头: 魔数, 格式版本号, 之后才是日志记录
+const FILE_MAGIC: &[u8; 8] = b"SQLDBLOG";
+const FORMAT_VERSION: u32 = 1;
+const FILE_HEADER_SIZE: u64 = 12;
+
+//旧的单文件日志的记录头: key_size, value_size, 没有 crc
+const LEGACY_LOG_HEADER_SIZE: u64 = 8;
+
+//批量写入记录的标记, 写在记录头 key_size 的位置
+const BATCH_MARKER: u32 = u32::MAX;
+
+//数据目录的文件锁, 保证同时只能有一个服务使用
+const LOCK_FILE: &str = "LOCK";
+
+/**
+ * 日志刷盘策略
+ * 写入只保证进入操作系统的页缓存, 刷盘之后才能在断电时不丢失
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncMode {
+    //每次写入之后刷盘
+    Always,
+    //只在调用 Engine::sync 时刷盘, MVCC 事务提交时会调用
+    OnCommit,
+    //后台线程按照固定间隔刷盘, 断电时最多丢失一个间隔内的写入
+    Interval(Duration),
+    //从不主动刷盘, 由操作系统决定
+    Never,
+}
 
-const LOG_HEADER_SIZE: u32 = 8;
+#[derive(Debug, Clone)]
+pub struct DiskEngineOptions {
+    pub sync_mode: SyncMode,
+    //单个日志段的最大字节数, 超过之后切换到新的段, 单条记录超过时独占一个段
+    pub max_segment_size: u64,
+    //写满的段中无效数据的比例超过阈值时, 在写入之后压缩最旧的段, 为 None 时不自动压缩
+    pub compaction_threshold: Option<f64>,
+}
+
+impl Default for DiskEngineOptions {
+    fn default() -> Self {
+        Self {
+            sync_mode: SyncMode::OnCommit,
+            max_segment_size: 32 * 1024 * 1024,
+            compaction_threshold: Some(0.5),
+        }
+    }
+}
 
 pub struct DiskEngine {
+    dir: PathBuf,
     keydir: KeyDir,
-    log: Log,
+    //所有的日志段, 按编号排列, 最后一个是当前写入的段
+    segments: BTreeMap<u64, Log>,
+    options: DiskEngineOptions,
+    //SyncMode::Interval 的后台刷盘线程
+    syncer: Option<Syncer>,
+    _lock: File,
 }
 
 impl DiskEngine {
-    pub fn new(file_path: PathBuf) -> Result<Self> {
-        let mut log = Log::new(file_path)?;
-        //从log恢复keydir
-        let keydir = log.build_keydir()?;
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        Self::with_options(dir, DiskEngineOptions::default())
+    }
+
+    pub fn with_options(dir: PathBuf, options: DiskEngineOptions) -> Result<Self> {
+        if dir.is_file() {
+            return Err(Error::Internal(format!(
+                "{} is not a directory, a single file log of the old format must be converted with DiskEngine::upgrade",
+                dir.display()
+            )));
+        }
+        fs::create_dir_all(&dir)?;
+
+        //加文件锁,保证只能同时只能有一个服务使用
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))?;
+        lock.try_lock_exclusive()?;
+
+        //按编号顺序加载所有的段, 后写入的数据覆盖之前的数据
+        let mut keydir = KeyDir::new();
+        let mut segments = BTreeMap::new();
+        for id in Self::segment_ids(&dir)? {
+            let mut log = Log::open(&dir, id)?;
+            log.build_keydir(&mut keydir)?;
+            segments.insert(id, log);
+        }
+        if segments.is_empty() {
+            segments.insert(1, Log::open(&dir, 1)?);
+        }
 
-        Ok(Self { keydir, log })
+        let mut eng = Self {
+            dir,
+            keydir,
+            segments,
+            options,
+            syncer: None,
+            _lock: lock,
+        };
+        eng.count_live();
+        eng.start_syncer()?;
+        Ok(eng)
     }
 
-    pub fn new_compact(file_path: PathBuf) -> Result<Self> {
-        let mut eng = Self::new(file_path)?;
+    pub fn new_compact(dir: PathBuf) -> Result<Self> {
+        let mut eng = Self::new(dir)?;
         eng.compact()?;
         Ok(eng)
     }
 
-    fn compact(&mut self) -> Result<()> {
-        //新打开一个临时的日志文件
-        let mut new_file_path = self.log.file_path.clone();
-        new_file_path.set_extension("compact");
-        let mut new_log = Log::new(new_file_path)?;
+    /**
+     * 离线升级旧格式的日志, 返回是否做了转换, 已经是当前格式时不做修改
+     * 支持两种旧格式: 没有 crc 的单文件日志, 以及没有文件头的日志段目录
+     * 数据先写入临时目录 <path>.upgrade, 完成之后原来的数据改名为 <path>.old 保留, 临时目录改名为 path
+     * 改名的过程中崩溃时, 重新执行会完成剩下的步骤
+     */
+    pub fn upgrade(path: PathBuf) -> Result<bool> {
+        let tmp_path = sibling_path(&path, "upgrade");
+        let backup_path = sibling_path(&path, "old");
+        //原来的数据已经改名, 临时目录是完整的, 只差最后一步
+        if !path.exists() && tmp_path.is_dir() && backup_path.exists() {
+            fs::rename(&tmp_path, &path)?;
+            return Ok(true);
+        }
+        if !path.exists() {
+            return Err(Error::Internal(format!("{} does not exist", path.display())));
+        }
+        if path.is_dir() && !Self::has_legacy_segments(&path)? {
+            return Ok(false);
+        }
+        if backup_path.exists() {
+            return Err(Error::Internal(format!(
+                "upgrade backup {} already exists",
+                backup_path.display()
+            )));
+        }
+        //上次升级中途失败留下的临时目录
+        if tmp_path.exists() {
+            fs::remove_dir_all(&tmp_path)?;
+        }
+
+        //旧版本的服务同样对日志文件或者数据目录的 LOCK 文件加锁, 升级的过程中不能有服务在使用
+        let lock_path = match path.is_file() {
+            true => path.clone(),
+            false => path.join(LOCK_FILE),
+        };
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(lock_path)?;
+        lock.try_lock_exclusive()?;
+
+        let mut eng = Self::new(tmp_path.clone())?;
+        if path.is_file() {
+            Self::copy_single_file(&path, &lock, &mut eng)?;
+        } else {
+            Self::copy_segments(&path, &mut eng)?;
+        }
+        eng.active().sync()?;
+        drop(eng);
+        drop(lock);
+
+        fs::rename(&path, &backup_path)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(true)
+    }
+
+    //数据目录中是否有没有文件头的日志段, 比文件头还短的段是创建时崩溃留下的, 打开时会重新写入文件头
+    fn has_legacy_segments(dir: &Path) -> Result<bool> {
+        for id in Self::segment_ids(dir)? {
+            let log = Log::open_raw(dir, id)?;
+            if log.len >= FILE_HEADER_SIZE && !log.check_version()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /**
+     * 复制旧的单文件日志中有效的数据
+     * 条目: key_size, value_size, key, value, 删除标记的 value_size 为 -1
+     * 末尾不完整的条目是写入过程中崩溃留下的, 直接忽略
+     */
+    fn copy_single_file(path: &Path, file: &File, eng: &mut DiskEngine) -> Result<()> {
+        let file_len = file.metadata()?.len();
+        let mut keydir = BTreeMap::new();
+        let mut header = [0; LEGACY_LOG_HEADER_SIZE as usize];
+        let mut offset = 0;
+        while offset + LEGACY_LOG_HEADER_SIZE <= file_len {
+            read_exact_at(file, &mut header, offset)?;
+            let key_size = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+            let value_size = i32::from_be_bytes(header[4..].try_into().unwrap());
+            if value_size < -1 {
+                return Err(Error::Corruption(path.display().to_string(), offset));
+            }
+            let value_offset = offset + LEGACY_LOG_HEADER_SIZE + key_size;
+            let end = value_offset + value_size.max(0) as u64;
+            if end > file_len {
+                break;
+            }
+
+            let mut key = vec![0; key_size as usize];
+            read_exact_at(file, &mut key, offset + LEGACY_LOG_HEADER_SIZE)?;
+            if value_size == -1 {
+                keydir.remove(&key);
+            } else {
+                keydir.insert(key, (value_offset, value_size as u32));
+            }
+            offset = end;
+        }
+
+        for (key, (offset, size)) in keydir {
+            let mut value = vec![0; size as usize];
+            read_exact_at(file, &mut value, offset)?;
+            eng.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    //复制没有文件头的日志段中有效的数据, 记录的格式和当前一致, 从文件开始读取, 不使用 hint 文件
+    fn copy_segments(dir: &Path, eng: &mut DiskEngine) -> Result<()> {
+        let mut keydir = KeyDir::new();
+        let mut segments = BTreeMap::new();
+        for id in Self::segment_ids(dir)? {
+            let log = Log::open_raw(dir, id)?;
+            let start = match log.check_version()? {
+                true => FILE_HEADER_SIZE,
+                false => 0,
+            };
+            log.read_entries(start, |key, offset, value_size| {
+                log.apply_entry(&mut keydir, offset, key, value_size)
+            })?;
+            segments.insert(id, log);
+        }
+
+        for (key, position) in &keydir {
+            eng.set(key.clone(), Self::read_value(&segments, key, position)?)?;
+        }
+        Ok(())
+    }
+
+    //数据目录中所有日志段的编号, 从小到大排列
+    fn segment_ids(dir: &Path) -> Result<Vec<u64>> {
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|it| it.to_str()) != Some("log") {
+                continue;
+            }
+            if let Some(id) = path
+                .file_stem()
+                .and_then(|it| it.to_str())
+                .and_then(|it| it.parse().ok())
+            {
+                ids.push(id);
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn active(&self) -> &Log {
+        self.segments.values().next_back().expect("no active segment")
+    }
+
+    fn start_syncer(&mut self) -> Result<()> {
+        //先停止旧的线程, 释放它持有的文件
+        self.syncer = None;
+        if let SyncMode::Interval(interval) = self.options.sync_mode {
+            let file = self.active().file.try_clone()?;
+            self.syncer = Some(Syncer::start(file, interval));
+        }
+        Ok(())
+    }
+
+    //写入记录, 当前的段写满时先切换到新的段, 返回记录所在的段和偏移量
+    fn append(&mut self, record: &[u8]) -> Result<(u64, u64)> {
+        let active = self.active();
+        if active.len > FILE_HEADER_SIZE
+            && active.len + record.len() as u64 > self.options.max_segment_size
+        {
+            //写满的段不再修改, 切换之前刷盘
+            if self.options.sync_mode != SyncMode::Never {
+                active.sync()?;
+            }
+            let id = active.id + 1;
+            self.segments.insert(id, Log::open(&self.dir, id)?);
+            self.start_syncer()?;
+        }
 
+        let active = self.segments.values_mut().next_back().expect("no active segment");
+        let offset = active.append(record)?;
+        Ok((active.id, offset))
+    }
+
+    //重新统计每个段中有效数据的大小, 文件头不能回收, 算作有效数据
+    fn count_live(&mut self) {
+        for log in self.segments.values_mut() {
+            log.live = FILE_HEADER_SIZE;
+        }
+        for (key, position) in &self.keydir {
+            if let Some(log) = self.segments.get_mut(&position.segment_id) {
+                log.live += entry_size(key.len(), position.size);
+            }
+        }
+    }
+
+    //更新 keydir, position 为 None 时删除, 同时维护每个段中有效数据的大小
+    fn update_keydir(&mut self, key: Vec<u8>, position: Option<ValuePosition>) {
+        let key_len = key.len();
+        let old = match position {
+            Some(position) => {
+                if let Some(log) = self.segments.get_mut(&position.segment_id) {
+                    log.live += entry_size(key_len, position.size);
+                }
+                self.keydir.insert(key, position)
+            }
+            None => self.keydir.remove(&key),
+        };
+        if let Some(old) = old {
+            if let Some(log) = self.segments.get_mut(&old.segment_id) {
+                log.live -= entry_size(key_len, old.size);
+            }
+        }
+    }
+
+    //写入批量记录并更新 keydir
+    fn apply_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        //整批写入日志之后再更新内存索引
+        let (segment_id, offset) = self.append(&Log::encode_batch(&batch)?)?;
+        let mut offset = offset + LOG_HEADER_SIZE as u64;
+        for (key, value) in batch.ops {
+            offset += LOG_HEADER_SIZE as u64 + key.len() as u64;
+            match value {
+                Some(value) => {
+                    let size = value.len() as u32;
+                    let position = ValuePosition {
+                        segment_id,
+                        offset,
+                        size,
+                    };
+                    self.update_keydir(key, Some(position));
+                    offset += size as u64;
+                }
+                None => self.update_keydir(key, None),
+            }
+        }
+        Ok(())
+    }
+
+    //写入日志之后按照刷盘策略处理, 并检查是否需要压缩
+    fn after_write(&mut self) -> Result<()> {
+        self.sync_after_write()?;
+        if self.need_compaction() {
+            self.compact_oldest()?;
+        }
+        Ok(())
+    }
+
+    fn sync_after_write(&mut self) -> Result<()> {
+        match self.options.sync_mode {
+            SyncMode::Always => self.active().sync(),
+            SyncMode::Interval(_) => {
+                if let Some(syncer) = &self.syncer {
+                    syncer.dirty.store(true, Ordering::Release);
+                }
+                Ok(())
+            }
+            SyncMode::OnCommit | SyncMode::Never => Ok(()),
+        }
+    }
+
+    //写满的段中无效数据的比例超过阈值, 当前写入的段还会变化, 不参与计算
+    fn need_compaction(&self) -> bool {
+        let threshold = match self.options.compaction_threshold {
+            Some(threshold) => threshold,
+            None => return false,
+        };
+        let (total, live) = self
+            .segments
+            .values()
+            .rev()
+            .skip(1)
+            .fold((0, 0), |(total, live), log| (total + log.len, live + log.live));
+        total > 0 && (total - live) as f64 / total as f64 > threshold
+    }
+
+    /**
+     * 增量压缩, 每次只处理最旧的一个段, 持有写锁的时间不超过重写一个段的时间
+     * 段中有效的数据作为一条批量记录重新写入当前的段, 刷盘之后删除旧的段
+     * 只压缩最旧的段, 其中的删除标记之前不会再有对应的数据, 可以直接丢弃
+     */
+    fn compact_oldest(&mut self) -> Result<()> {
+        if self.segments.len() < 2 {
+            return Ok(());
+        }
+        let log = self.segments.values().next().expect("no segment");
+
+        //找到 keydir 仍然指向这个段的数据
+        let mut batch = WriteBatch::new();
+        let mut live = Vec::new();
+        log.read_entries(FILE_HEADER_SIZE, |key, offset, value_size| {
+            if value_size >= 0 {
+                let position = ValuePosition {
+                    segment_id: log.id,
+                    offset: offset + LOG_HEADER_SIZE as u64 + key.len() as u64,
+                    size: value_size as u32,
+                };
+                if self.keydir.get(&key) == Some(&position) {
+                    live.push((key, position));
+                }
+            }
+        })?;
+        for (key, position) in live {
+            let value = log.read_value(&key, position.offset, position.size)?;
+            batch.set(key, value);
+        }
+
+        let id = log.id;
+        self.apply_batch(batch)?;
+        self.active().sync()?;
+        if let Some(log) = self.segments.remove(&id) {
+            log.remove()?;
+        }
+        Ok(())
+    }
+
+    fn read_value(
+        segments: &BTreeMap<u64, Log>,
+        key: &[u8],
+        position: &ValuePosition,
+    ) -> Result<Vec<u8>> {
+        segments
+            .get(&position.segment_id)
+            .ok_or(Error::Internal(format!(
+                "segment {} does not exist",
+                position.segment_id
+            )))?
+            .read_value(key, position.offset, position.size)
+    }
+
+    /**
+     * 压缩, 将所有有效的数据重写到新的段中, 并为新的段写入 hint 文件
+     * 新的段编号比所有旧的段都大, 完整写入之后才按照编号从小到大删除旧的段
+     * 中途崩溃时, 剩下的旧数据会被新的段覆盖, 删除标记仍然在对应的数据之后, 恢复的结果不变
+     */
+    fn compact(&mut self) -> Result<()> {
+        let max_segment_size = self.options.max_segment_size;
+        let mut new_segments = BTreeMap::new();
         let mut new_keydir = KeyDir::new();
+        let mut log = Log::open(&self.dir, self.active().id + 1)?;
+        let mut hints = Vec::new();
 
-        //重读数据到临时文件中
-        for (key, (offset, value_size)) in self.keydir.iter() {
+        //重读数据到新的段中
+        for (key, position) in self.keydir.iter() {
             //读取value
-            let value = self.log.read_value(*offset, *value_size)?;
-            let (new_offset, new_size) = new_log.write_entry(key, Some(&value))?;
+            let value = Self::read_value(&self.segments, key, position)?;
+            let record = Log::encode_entry(key, Some(&value));
+            if log.len > FILE_HEADER_SIZE && log.len + record.len() as u64 > max_segment_size {
+                log.finish(&hints)?;
+                let id = log.id + 1;
+                new_segments.insert(log.id, log);
+                log = Log::open(&self.dir, id)?;
+                hints.clear();
+            }
 
-            new_keydir.insert(
-                key.clone(),
-                (
-                    new_offset + new_size as u64 - *value_size as u64,
-                    *value_size,
-                ),
-            );
+            let offset = log.append(&record)?;
+            let new_position = ValuePosition {
+                segment_id: log.id,
+                offset: offset + LOG_HEADER_SIZE as u64 + key.len() as u64,
+                size: position.size,
+            };
+            hints.push((key.clone(), new_position));
+            new_keydir.insert(key.clone(), new_position);
         }
+        log.finish(&hints)?;
+        new_segments.insert(log.id, log);
 
-        //将临时文件更改为正式文件
-        rename(&new_log.file_path, &self.log.file_path)?;
-        new_log.file_path = self.log.file_path.clone();
+        //删除旧的段
+        for (_, old) in std::mem::replace(&mut self.segments, new_segments) {
+            old.remove()?;
+        }
         self.keydir = new_keydir;
-        self.log = new_log;
+        self.count_live();
 
-        Ok(())
+        self.start_syncer()
     }
 }
 
@@ -78,21 +546,25 @@ impl Engine for DiskEngine {
 
     fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
         //先写日志
-        let (offset, size) = self.log.write_entry(&key, Some(&value))?;
+        let record = Log::encode_entry(&key, Some(&value));
+        let (segment_id, offset) = self.append(&record)?;
         //更新内存索引
         //100--------|----150
         //           130
         //val_size = 20
-        let val_size = value.len() as u32;
-        self.keydir
-            .insert(key, (offset + size as u64 - val_size as u64, val_size));
-        Ok(())
+        let position = ValuePosition {
+            segment_id,
+            offset: offset + record.len() as u64 - value.len() as u64,
+            size: value.len() as u32,
+        };
+        self.update_keydir(key, Some(position));
+        self.after_write()
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         match self.keydir.get(&key) {
-            Some((offset, size)) => {
-                let val = self.log.read_value(*offset, *size)?;
+            Some(position) => {
+                let val = Self::read_value(&self.segments, &key, position)?;
                 Ok(Some(val))
             }
             None => Ok(None),
@@ -101,136 +573,555 @@ impl Engine for DiskEngine {
 
     fn delete(&mut self, key: Vec<u8>) -> Result<()> {
         //写日志
-        self.log.write_entry(&key, None)?;
+        self.append(&Log::encode_entry(&key, None))?;
         //删除内存数据
-        self.keydir.remove(&key);
-        Ok(())
+        self.update_keydir(key, None);
+        self.after_write()
     }
 
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.apply_batch(batch)?;
+        self.after_write()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        match self.options.sync_mode {
+            SyncMode::OnCommit => self.active().sync(),
+            //Always 每次写入时已经刷盘, Interval 由后台线程刷盘
+            SyncMode::Always | SyncMode::Interval(_) | SyncMode::Never => Ok(()),
+        }
+    }
+
+    fn status(&self) -> Result<Status> {
+        let keys = self.keydir.len() as u64;
+        let size = self
+            .keydir
+            .iter()
+            .map(|(key, position)| key.len() as u64 + position.size as u64)
+            .sum();
+        let total_disk_size = self.segments.values().map(|log| log.len).sum();
+        let live_disk_size = self.segments.values().map(|log| log.live).sum();
+        Ok(Status {
+            name: "disk".into(),
+            keys,
+            size,
+            total_disk_size,
+            live_disk_size,
+            garbage_disk_size: total_disk_size - live_disk_size,
+        })
+    }
+
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
         DiskEngineIterator {
             inner: self.keydir.range(range),
-            log: &mut self.log,
+            segments: &self.segments,
         }
     }
 }
 
+//日志段
 struct Log {
+    id: u64,
     file_path: PathBuf,
     file: File,
+    //文件长度, 新的记录写在末尾
+    len: u64,
+    //keydir 中指向这个段的条目的大小, 其余的都是无效数据
+    live: u64,
 }
 
 impl Log {
-    fn new(file_path: PathBuf) -> Result<Self> {
-        //如果目录不存在则创建
-        if let Some(dir) = file_path.parent() {
-            if !dir.exists() {
-                fs::create_dir_all(&dir)?;
+    /**
+     * 打开日志段, 新建的段写入文件头, 已有的段检查文件头
+     * 文件头只写入了一部分说明创建段的时候崩溃, 其中还没有数据, 重新写入
+     */
+    fn open(dir: &Path, id: u64) -> Result<Self> {
+        let mut log = Self::open_raw(dir, id)?;
+        let mut header = FILE_MAGIC.to_vec();
+        header.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+        if log.len < FILE_HEADER_SIZE {
+            let mut data = vec![0; log.len as usize];
+            read_exact_at(&log.file, &mut data, 0)?;
+            if header.starts_with(&data) {
+                log.file.set_len(0)?;
+                log.append(&header)?;
             }
         }
 
+        if !log.check_version()? {
+            return Err(Error::Internal(format!(
+                "{} has no log file header, logs of the old format must be converted with DiskEngine::upgrade",
+                log.file_path.display()
+            )));
+        }
+        Ok(log)
+    }
+
+    //打开日志段, 不检查文件头
+    fn open_raw(dir: &Path, id: u64) -> Result<Self> {
+        let file_path = dir.join(format!("{:08}.log", id));
         //打开文件
         let file = OpenOptions::new()
             .create(true)
+            .truncate(false)
             .read(true)
             .write(true)
             .open(&file_path)?;
+        let len = file.metadata()?.len();
 
-        //加文件锁,保证只能同时只能有一个服务使用
-        file.try_lock_exclusive()?;
+        Ok(Self {
+            id,
+            file_path,
+            file,
+            len,
+            live: FILE_HEADER_SIZE,
+        })
+    }
+
+    //检查文件头, 没有文件头时返回 false, 版本号不是当前版本时返回错误
+    fn check_version(&self) -> Result<bool> {
+        if self.len < FILE_HEADER_SIZE {
+            return Ok(false);
+        }
+        let mut header = [0; FILE_HEADER_SIZE as usize];
+        read_exact_at(&self.file, &mut header, 0)?;
+        if &header[..8] != FILE_MAGIC {
+            return Ok(false);
+        }
+        let version = u32::from_be_bytes(header[8..].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(Error::Internal(format!(
+                "unsupported log format version {} in {}, expected version {}",
+                version,
+                self.file_path.display(),
+                FORMAT_VERSION
+            )));
+        }
+        Ok(true)
+    }
 
-        Ok(Self { file_path, file })
+    fn hint_path(&self) -> PathBuf {
+        self.file_path.with_extension("hint")
     }
 
-    fn build_keydir(&mut self) -> Result<KeyDir> {
-        let mut keydir = KeyDir::new();
-        let file_len = self.file.metadata()?.len();
+    fn corruption(&self, offset: u64) -> Error {
+        Error::Corruption(self.file_path.display().to_string(), offset)
+    }
+
+    /**
+     * 从日志恢复 keydir
+     * 有 hint 文件时先从 hint 加载, 再读取 hint 没有覆盖的部分
+     * 末尾不完整或者校验失败的记录是写入过程中崩溃留下的, 直接从文件中截断
+     * 其他位置的记录校验失败说明文件已经损坏, 返回 Error::Corruption
+     */
+    fn build_keydir(&mut self, keydir: &mut KeyDir) -> Result<()> {
+        let start = self.load_hint(keydir)?;
+        let end = self.read_entries(start, |key, offset, value_size| {
+            self.apply_entry(keydir, offset, key, value_size)
+        })?;
+        if end < self.len {
+            self.file.set_len(end)?;
+            self.len = end;
+        }
+        Ok(())
+    }
+
+    //从 start 开始按顺序读取所有的条目, 返回有效数据的结束位置, 之后是末尾不完整的记录
+    fn read_entries(&self, start: u64, mut f: impl FnMut(Vec<u8>, u64, i32)) -> Result<u64> {
+        let file_len = self.len;
         let mut buf_reader = BufReader::new(&self.file);
 
-        let mut offset = 0;
-        loop {
-            if offset >= file_len {
-                break;
+        let mut offset = start;
+        while offset < file_len {
+            let record = match self.read_record(&mut buf_reader, offset, file_len)? {
+                Some(record) => record,
+                None => break,
+            };
+
+            let (key_size, _) = Self::decode_header(&record);
+            if key_size == BATCH_MARKER {
+                //批量写入的记录校验通过之后, 其中的条目才会生效
+                let mut position = LOG_HEADER_SIZE as usize;
+                while position < record.len() {
+                    let (key, value_size, len) = Self::decode_entry(&record[position..])
+                        .ok_or(self.corruption(offset))?;
+                    f(key, offset + position as u64, value_size);
+                    position += len;
+                }
+            } else {
+                let (key, value_size, _) =
+                    Self::decode_entry(&record).ok_or(self.corruption(offset))?;
+                f(key, offset, value_size);
             }
+            offset += record.len() as u64;
+        }
 
-            let (key, value_size) = Self::read_entry(&mut buf_reader, offset)?;
+        Ok(offset)
+    }
 
-            let key_size = key.len() as u64;
-            if value_size == -1 {
-                keydir.remove(&key);
-                offset += LOG_HEADER_SIZE as u64 + key_size;
-            } else {
-                keydir.insert(
-                    key,
-                    (
-                        offset + LOG_HEADER_SIZE as u64 + key_size,
-                        value_size as u32,
-                    ),
-                );
-                offset += LOG_HEADER_SIZE as u64 + key_size + value_size as u64;
+    //将一条日志应用到 keydir
+    fn apply_entry(&self, keydir: &mut KeyDir, offset: u64, key: Vec<u8>, value_size: i32) {
+        if value_size == -1 {
+            keydir.remove(&key);
+        } else {
+            let position = ValuePosition {
+                segment_id: self.id,
+                offset: offset + LOG_HEADER_SIZE as u64 + key.len() as u64,
+                size: value_size as u32,
+            };
+            keydir.insert(key, position);
+        }
+    }
+
+    /**
+     * 从 hint 文件加载 keydir, 返回 hint 覆盖的日志长度
+     * hint 文件: 多个条目 key_size, offset, size, key, 之后是覆盖的日志长度和 crc
+     * hint 文件不存在或者校验失败时忽略, 从文件头之后读取日志
+     */
+    fn load_hint(&self, keydir: &mut KeyDir) -> Result<u64> {
+        let data = match fs::read(self.hint_path()) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(FILE_HEADER_SIZE),
+            Err(err) => return Err(err.into()),
+        };
+        match self.decode_hint(&data) {
+            Some((entries, log_len)) => {
+                for (key, offset, size) in entries {
+                    let position = ValuePosition {
+                        segment_id: self.id,
+                        offset,
+                        size,
+                    };
+                    keydir.insert(key, position);
+                }
+                Ok(log_len)
             }
+            None => Ok(FILE_HEADER_SIZE),
+        }
+    }
+
+    fn decode_hint(&self, data: &[u8]) -> Option<(Vec<HintEntry>, u64)> {
+        if data.len() < 12 {
+            return None;
+        }
+        let (body, crc) = data.split_at(data.len() - 4);
+        if u32::from_be_bytes(crc.try_into().ok()?) != crc32fast::hash(body) {
+            return None;
+        }
+        let (mut entries_data, log_len) = body.split_at(body.len() - 8);
+        let log_len = u64::from_be_bytes(log_len.try_into().ok()?);
+        if log_len < FILE_HEADER_SIZE || log_len > self.len {
+            return None;
+        }
+
+        let mut entries = Vec::new();
+        while !entries_data.is_empty() {
+            if entries_data.len() < 16 {
+                return None;
+            }
+            let key_size = u32::from_be_bytes(entries_data[0..4].try_into().ok()?) as usize;
+            let offset = u64::from_be_bytes(entries_data[4..12].try_into().ok()?);
+            let size = u32::from_be_bytes(entries_data[12..16].try_into().ok()?);
+            let key = entries_data.get(16..16 + key_size)?.to_vec();
+            entries.push((key, offset, size));
+            entries_data = &entries_data[16 + key_size..];
+        }
+        Some((entries, log_len))
+    }
+
+    //段写入完成, 刷盘并写入 hint 文件
+    fn finish(&self, hints: &[(Vec<u8>, ValuePosition)]) -> Result<()> {
+        self.sync()?;
+        let mut data = Vec::new();
+        for (key, position) in hints {
+            data.extend_from_slice(&(key.len() as u32).to_be_bytes());
+            data.extend_from_slice(&position.offset.to_be_bytes());
+            data.extend_from_slice(&position.size.to_be_bytes());
+            data.extend_from_slice(key);
         }
+        data.extend_from_slice(&self.len.to_be_bytes());
+        let crc = crc32fast::hash(&data);
+        data.extend_from_slice(&crc.to_be_bytes());
 
-        Ok(keydir)
+        let mut file = File::create(self.hint_path())?;
+        file.write_all(&data)?;
+        Ok(file.sync_data()?)
     }
 
-    fn write_entry(&mut self, key: &Vec<u8>, value: Option<&Vec<u8>>) -> Result<(u64, u32)> {
+    //删除日志段以及 hint 文件
+    fn remove(self) -> Result<()> {
+        let hint_path = self.hint_path();
+        drop(self.file);
+        fs::remove_file(&self.file_path)?;
+        if hint_path.exists() {
+            fs::remove_file(hint_path)?;
+        }
+        Ok(())
+    }
+
+    //在文件末尾写入记录, 返回记录的偏移量
+    fn append(&mut self, record: &[u8]) -> Result<u64> {
         //将文件偏移量移动到文件末尾
         let offset = self.file.seek(std::io::SeekFrom::End(0))?;
-        let key_size = key.len() as u32;
-        let val_size = value.map_or(0, |it| it.len() as u32);
-        let total_size = key_size + val_size + LOG_HEADER_SIZE;
-        //数据写入磁盘
-        //写入 key_size,val_size,key,value
-        let mut writer = BufWriter::with_capacity(total_size as usize, &self.file);
-        writer.write_all(&key_size.to_be_bytes())?;
-        writer.write_all(&value.map_or(-1, |it| it.len() as i32).to_be_bytes())?;
-        writer.write_all(&key)?;
-        if let Some(v) = value {
-            writer.write_all(v)?;
+        (&self.file).write_all(record)?;
+        self.len = offset + record.len() as u64;
+        Ok(offset)
+    }
+
+    /**
+     * 编码批量写入的记录, 整批数据作为一条记录一次写入
+     * 记录头的 key_size 为 BATCH_MARKER, value_size 为记录内容的长度
+     * 记录内容是普通的日志条目, 恢复时只有完整的记录才会生效
+     */
+    fn encode_batch(batch: &WriteBatch) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        for (key, value) in &batch.ops {
+            body.extend_from_slice(&Self::encode_entry(key, value.as_deref()));
+        }
+        if body.len() > i32::MAX as usize {
+            return Err(Error::Internal("write batch is too large".into()));
         }
-        writer.flush()?;
-        Ok((offset, total_size as u32))
+        Ok(Self::encode_record(BATCH_MARKER, body.len() as i32, &body))
     }
 
-    fn read_value(&mut self, offset: u64, size: u32) -> Result<Vec<u8>> {
-        //跳转到偏移量位置
-        self.file.seek(std::io::SeekFrom::Start(offset))?;
-        let mut buffer = vec![0; size as usize];
-        //读取数据到buffer
-        self.file.read_exact(&mut buffer)?;
-        Ok(buffer)
+    fn sync(&self) -> Result<()> {
+        Ok(self.file.sync_data()?)
+    }
+
+    //读取 value, 同时校验整个条目, 按偏移量读取不修改文件的读写位置, 多个线程可以同时读取
+    fn read_value(&self, key: &[u8], offset: u64, size: u32) -> Result<Vec<u8>> {
+        let entry_offset = offset - key.len() as u64 - LOG_HEADER_SIZE as u64;
+        let mut entry = vec![0; LOG_HEADER_SIZE as usize + key.len() + size as usize];
+        read_exact_at(&self.file, &mut entry, entry_offset)?;
+        if !Self::verify(&entry) || Self::decode_header(&entry) != (key.len() as u32, size as i32)
+        {
+            return Err(self.corruption(entry_offset));
+        }
+        Ok(entry.split_off(LOG_HEADER_SIZE as usize + key.len()))
     }
 
-    fn read_entry(buf_reader: &mut BufReader<&File>, offset: u64) -> Result<(Vec<u8>, i32)> {
+    /**
+     * 读取一条完整的记录并校验
+     * 记录超出文件末尾, 或者最后一条记录校验失败时返回 None
+     * 记录超出文件末尾时, 如果之后还有完整的记录, 说明是中间记录的长度字段损坏, 返回 Error::Corruption
+     */
+    fn read_record(
+        &self,
+        buf_reader: &mut BufReader<&File>,
+        offset: u64,
+        file_len: u64,
+    ) -> Result<Option<Vec<u8>>> {
+        if offset + LOG_HEADER_SIZE as u64 > file_len {
+            return Ok(None);
+        }
         buf_reader.seek(std::io::SeekFrom::Start(offset))?;
-        let mut len_buf = [0; 4];
+        let mut record = vec![0; LOG_HEADER_SIZE as usize];
+        buf_reader.read_exact(&mut record)?;
+
+        let (key_size, value_size) = Self::decode_header(&record);
+        let body_size = Self::body_size(key_size, value_size);
+        let end = offset + LOG_HEADER_SIZE as u64 + body_size;
+        if end > file_len {
+            if self.has_record_after(offset, file_len)? {
+                return Err(self.corruption(offset));
+            }
+            return Ok(None);
+        }
+
+        record.resize(LOG_HEADER_SIZE as usize + body_size as usize, 0);
+        buf_reader.read_exact(&mut record[LOG_HEADER_SIZE as usize..])?;
+        if !Self::verify(&record) {
+            if end == file_len {
+                return Ok(None);
+            }
+            return Err(self.corruption(offset));
+        }
+        Ok(Some(record))
+    }
+
+    /**
+     * 从 offset 之后的每个位置尝试解析一条完整并且校验通过的记录
+     * 写入过程中崩溃时, 不完整的记录之后不会再有数据
+     * 批量记录中的条目本身也是完整的记录, 跳过记录开头连续的完整条目
+     */
+    fn has_record_after(&self, offset: u64, file_len: u64) -> Result<bool> {
+        let mut data = vec![0; (file_len - offset) as usize];
+        read_exact_at(&self.file, &mut data, offset)?;
+        let mut start = 1;
+        if Self::decode_header(&data).0 == BATCH_MARKER {
+            start = LOG_HEADER_SIZE as usize;
+            while let Some(len) = Self::valid_record_len(&data[start..]) {
+                if Self::decode_header(&data[start..]).0 == BATCH_MARKER {
+                    break;
+                }
+                start += len;
+            }
+        }
+        Ok((start..data.len()).any(|start| Self::valid_record_len(&data[start..]).is_some()))
+    }
+
+    //data 以一条完整并且校验通过的记录开始时, 返回记录的长度
+    fn valid_record_len(data: &[u8]) -> Option<usize> {
+        if data.len() < LOG_HEADER_SIZE as usize {
+            return None;
+        }
+        let (key_size, value_size) = Self::decode_header(data);
+        let len = LOG_HEADER_SIZE as u64 + Self::body_size(key_size, value_size);
+        if len > data.len() as u64 || !Self::verify(&data[..len as usize]) {
+            return None;
+        }
+        Some(len as usize)
+    }
+
+    //记录头之后的数据长度, 批量记录的 value_size 为记录内容的长度
+    fn body_size(key_size: u32, value_size: i32) -> u64 {
+        match key_size {
+            BATCH_MARKER => value_size as u32 as u64,
+            _ => key_size as u64 + value_size.max(0) as u64,
+        }
+    }
+
+    //编码一个条目: crc, key_size, value_size, key, value, 删除标记的 value_size 为 -1
+    fn encode_entry(key: &[u8], value: Option<&[u8]>) -> Vec<u8> {
+        let value_size = value.map_or(-1, |it| it.len() as i32);
+        let mut body = key.to_vec();
+        if let Some(v) = value {
+            body.extend_from_slice(v);
+        }
+        Self::encode_record(key.len() as u32, value_size, &body)
+    }
 
-        //读取key_size
-        buf_reader.read_exact(&mut len_buf)?;
-        let key_size = u32::from_be_bytes(len_buf);
+    //记录头之后的所有数据参与 crc 计算
+    fn encode_record(key_size: u32, value_size: i32, body: &[u8]) -> Vec<u8> {
+        let mut record = vec![0; 4];
+        record.extend_from_slice(&key_size.to_be_bytes());
+        record.extend_from_slice(&value_size.to_be_bytes());
+        record.extend_from_slice(body);
+        let crc = crc32fast::hash(&record[4..]);
+        record[..4].copy_from_slice(&crc.to_be_bytes());
+        record
+    }
 
-        //读取value_size
-        buf_reader.read_exact(&mut len_buf)?;
-        let value_size = i32::from_be_bytes(len_buf);
+    //解析记录头中的 key_size, value_size
+    fn decode_header(record: &[u8]) -> (u32, i32) {
+        let key_size = u32::from_be_bytes(record[4..8].try_into().unwrap());
+        let value_size = i32::from_be_bytes(record[8..12].try_into().unwrap());
+        (key_size, value_size)
+    }
 
-        //读取key
-        let mut key = vec![0; key_size as usize];
-        buf_reader.read_exact(&mut key)?;
+    //解析一个条目, 返回 key, value_size 以及条目的长度, 数据不完整时返回 None
+    fn decode_entry(data: &[u8]) -> Option<(Vec<u8>, i32, usize)> {
+        if data.len() < LOG_HEADER_SIZE as usize {
+            return None;
+        }
+        let (key_size, value_size) = Self::decode_header(data);
+        let key_end = LOG_HEADER_SIZE as usize + key_size as usize;
+        let len = key_end + value_size.max(0) as usize;
+        if key_size == BATCH_MARKER || value_size < -1 || data.len() < len {
+            return None;
+        }
+        Some((data[LOG_HEADER_SIZE as usize..key_end].to_vec(), value_size, len))
+    }
 
-        Ok((key, value_size))
+    fn verify(record: &[u8]) -> bool {
+        let crc = u32::from_be_bytes(record[..4].try_into().unwrap());
+        crc == crc32fast::hash(&record[4..])
     }
 }
 
+/**
+ * 后台刷盘线程, 每个间隔检查一次是否有新的写入
+ * 释放时通知线程退出并等待, 线程持有的文件句柄随之关闭
+ */
+struct Syncer {
+    dirty: Arc<AtomicBool>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Syncer {
+    fn start(file: File, interval: Duration) -> Self {
+        let dirty = Arc::new(AtomicBool::new(false));
+        let (stop, receiver) = mpsc::channel::<()>();
+        let flag = dirty.clone();
+        let handle = thread::spawn(move || loop {
+            match receiver.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {
+                    //刷盘失败时保留标记, 下一个间隔重试
+                    if flag.swap(false, Ordering::AcqRel) && file.sync_data().is_err() {
+                        flag.store(true, Ordering::Release);
+                    }
+                }
+                _ => {
+                    if flag.load(Ordering::Acquire) {
+                        file.sync_data().ok();
+                    }
+                    return;
+                }
+            }
+        });
+        Self {
+            dirty,
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for Syncer {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            handle.join().ok();
+        }
+    }
+}
+
+//和 path 在同一个目录下, 名字加上后缀
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+//一个条目在日志中占用的大小
+fn entry_size(key_len: usize, value_size: u32) -> u64 {
+    LOG_HEADER_SIZE as u64 + key_len as u64 + value_size as u64
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> std::io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+//windows 上的 seek_read 会移动文件的读写位置, 写入之前都会重新定位到文件末尾, 不受影响
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset)? {
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
 pub struct DiskEngineIterator<'a> {
-    inner: btree_map::Range<'a, Vec<u8>, (u64, u32)>,
-    log: &'a mut Log,
+    inner: btree_map::Range<'a, Vec<u8>, ValuePosition>,
+    segments: &'a BTreeMap<u64, Log>,
 }
 
 impl<'a> DiskEngineIterator<'a> {
-    fn map(&mut self, item: (&Vec<u8>, &(u64, u32))) -> <Self as Iterator>::Item {
-        let (k, (offset, val_size)) = item;
-        let value = self.log.read_value(*offset, *val_size)?;
+    fn map(&mut self, item: (&Vec<u8>, &ValuePosition)) -> <Self as Iterator>::Item {
+        let (k, position) = item;
+        let value = DiskEngine::read_value(self.segments, k, position)?;
         Ok((k.clone(), value))
     }
 }
@@ -282,7 +1173,7 @@ fn test_disk_engine_compact() -> Result<()> {
 
     drop(eng);
 
-    let mut eng2 = DiskEngine::new_compact(PathBuf::from("/tmp/sqldb/sqldb-log"))?;
+    let eng2 = DiskEngine::new_compact(PathBuf::from("/tmp/sqldb/sqldb-log"))?;
     let iter2 = eng2.scan(..);
     let v2 = iter2.collect::<Result<Vec<_>>>()?;
     assert_eq!(
@@ -304,3 +1195,431 @@ fn test_disk_engine_compact() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_disk_engine_write_batch() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("sqldb");
+    let path = dir.join("00000001.log");
+    let mut eng = DiskEngine::new(dir.clone())?;
+    eng.set(b"aa".to_vec(), b"value1".to_vec())?;
+    let mut batch = WriteBatch::new();
+    batch.set(b"bb".to_vec(), b"value2".to_vec());
+    batch.delete(b"aa".to_vec());
+    batch.set(b"cc".to_vec(), b"value3".to_vec());
+    eng.write_batch(batch)?;
+    drop(eng);
+
+    //批量写入的记录可以从日志中恢复
+    let eng = DiskEngine::new(dir.clone())?;
+    let expected = vec![
+        (b"bb".to_vec(), b"value2".to_vec()),
+        (b"cc".to_vec(), b"value3".to_vec()),
+    ];
+    assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+    drop(eng);
+
+    //模拟写入批量记录时崩溃, 只写入了一部分
+    let len = fs::metadata(&path)?.len();
+    let mut eng = DiskEngine::new(dir.clone())?;
+    let mut batch = WriteBatch::new();
+    batch.set(b"dd".to_vec(), b"value4".to_vec());
+    batch.delete(b"bb".to_vec());
+    eng.write_batch(batch)?;
+    drop(eng);
+    let file = OpenOptions::new().write(true).open(&path)?;
+    file.set_len(fs::metadata(&path)?.len() - 3)?;
+    drop(file);
+
+    //不完整的记录被整体丢弃, 并从日志中截断
+    let mut eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+    assert_eq!(fs::metadata(&path)?.len(), len);
+
+    //截断之后可以继续写入
+    eng.set(b"ee".to_vec(), b"value5".to_vec())?;
+    drop(eng);
+    let eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(eng.get(b"ee".to_vec())?, Some(b"value5".to_vec()));
+    drop(eng);
+    Ok(())
+}
+
+#[test]
+fn test_disk_engine_recovery() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("sqldb");
+    let path = dir.join("00000001.log");
+    let mut eng = DiskEngine::new(dir.clone())?;
+    eng.set(b"aa".to_vec(), b"value1".to_vec())?;
+    eng.set(b"bb".to_vec(), b"value2".to_vec())?;
+    let len = fs::metadata(&path)?.len();
+    eng.set(b"cc".to_vec(), b"value3".to_vec())?;
+    drop(eng);
+
+    //最后一条记录只写入了一部分
+    let file = OpenOptions::new().write(true).open(&path)?;
+    file.set_len(fs::metadata(&path)?.len() - 2)?;
+    drop(file);
+
+    let mut eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(eng.get(b"bb".to_vec())?, Some(b"value2".to_vec()));
+    assert_eq!(eng.get(b"cc".to_vec())?, None);
+    assert_eq!(fs::metadata(&path)?.len(), len);
+    eng.set(b"cc".to_vec(), b"value3".to_vec())?;
+    drop(eng);
+
+    //最后一条记录的内容写入错误, 校验失败
+    let mut data = fs::read(&path)?;
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    fs::write(&path, &data)?;
+    let eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(eng.get(b"cc".to_vec())?, None);
+    assert_eq!(fs::metadata(&path)?.len(), len);
+    drop(eng);
+
+    //中间的记录损坏, 返回损坏的位置
+    let mut data = fs::read(&path)?;
+    let first = (FILE_HEADER_SIZE + LOG_HEADER_SIZE as u64) as usize;
+    data[first] ^= 0xff;
+    fs::write(&path, &data)?;
+    assert_eq!(
+        DiskEngine::new(dir.clone()).err(),
+        Some(Error::Corruption(path.display().to_string(), FILE_HEADER_SIZE))
+    );
+
+    //打开之后数据被损坏, 读取时校验失败
+    data[first] ^= 0xff;
+    fs::write(&path, &data)?;
+    let eng = DiskEngine::new(dir.clone())?;
+    let mut data = fs::read(&path)?;
+    data[len as usize - 1] ^= 0xff;
+    fs::write(&path, &data)?;
+    let offset = len - (LOG_HEADER_SIZE as u64 + 2 + 6);
+    assert_eq!(
+        eng.get(b"bb".to_vec()),
+        Err(Error::Corruption(path.display().to_string(), offset))
+    );
+    assert_eq!(eng.get(b"aa".to_vec())?, Some(b"value1".to_vec()));
+    drop(eng);
+
+    //中间记录的长度字段损坏, 之后的记录是完整的, 不能当作末尾不完整的记录截断
+    let dir = dir.parent().unwrap().join("sqldb2");
+    let path = dir.join("00000001.log");
+    let mut eng = DiskEngine::new(dir.clone())?;
+    eng.set(b"aa".to_vec(), b"value1".to_vec())?;
+    eng.set(b"bb".to_vec(), b"value2".to_vec())?;
+    eng.set(b"cc".to_vec(), b"value3".to_vec())?;
+    drop(eng);
+    let mut data = fs::read(&path)?;
+    let offset = FILE_HEADER_SIZE + entry_size(2, 6);
+    data[offset as usize + 4] = 0x7f;
+    fs::write(&path, &data)?;
+    assert_eq!(
+        DiskEngine::new(dir.clone()).err(),
+        Some(Error::Corruption(path.display().to_string(), offset))
+    );
+    assert_eq!(fs::read(&path)?, data);
+    Ok(())
+}
+
+#[test]
+fn test_disk_engine_sync_mode() -> Result<()> {
+    let modes = [
+        SyncMode::Always,
+        SyncMode::OnCommit,
+        SyncMode::Interval(Duration::from_millis(10)),
+        SyncMode::Never,
+    ];
+    for sync_mode in modes {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("sqldb-log");
+        let options = DiskEngineOptions {
+            sync_mode,
+            ..Default::default()
+        };
+        let mut eng = DiskEngine::with_options(path.clone(), options.clone())?;
+        eng.set(b"aa".to_vec(), b"value1".to_vec())?;
+        let mut batch = WriteBatch::new();
+        batch.set(b"bb".to_vec(), b"value2".to_vec());
+        eng.write_batch(batch)?;
+        eng.sync()?;
+
+        //后台线程刷盘之后清除标记
+        if let Some(syncer) = &eng.syncer {
+            thread::sleep(Duration::from_millis(50));
+            assert!(!syncer.dirty.load(Ordering::Acquire));
+        }
+        drop(eng);
+
+        //释放之后文件锁被释放, 可以重新打开
+        let eng = DiskEngine::with_options(path.clone(), options)?;
+        assert_eq!(eng.get(b"aa".to_vec())?, Some(b"value1".to_vec()));
+        assert_eq!(eng.get(b"bb".to_vec())?, Some(b"value2".to_vec()));
+        drop(eng);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_disk_engine_segments() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("sqldb");
+    let options = DiskEngineOptions {
+        max_segment_size: 100,
+        ..Default::default()
+    };
+    let read_all = |eng: &DiskEngine| eng.scan(..).collect::<Result<Vec<_>>>();
+
+    //写满之后切换到新的段
+    let mut eng = DiskEngine::with_options(dir.clone(), options.clone())?;
+    for i in 0..20 {
+        eng.set(format!("key{:02}", i).into_bytes(), format!("value{}", i).into_bytes())?;
+    }
+    for i in 0..5 {
+        eng.delete(format!("key{:02}", i).into_bytes())?;
+        eng.set(format!("key{:02}", i + 10).into_bytes(), b"new".to_vec())?;
+    }
+    assert!(eng.segments.len() > 1);
+    let expected = read_all(&eng)?;
+    assert_eq!(expected.len(), 15);
+    drop(eng);
+
+    let mut eng = DiskEngine::with_options(dir.clone(), options.clone())?;
+    assert_eq!(read_all(&eng)?, expected);
+
+    //压缩之后旧的段被删除, 新的段都有 hint 文件
+    let old_ids = eng.segments.keys().copied().collect::<Vec<_>>();
+    let old_files = old_ids
+        .iter()
+        .map(|id| {
+            let path = dir.join(format!("{:08}.log", id));
+            fs::read(&path).map(|data| (path, data))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    eng.compact()?;
+    assert_eq!(read_all(&eng)?, expected);
+    let new_ids = eng.segments.keys().copied().collect::<Vec<_>>();
+    assert!(new_ids[0] > *old_ids.last().unwrap());
+    assert_eq!(DiskEngine::segment_ids(&dir)?, new_ids);
+    for log in eng.segments.values() {
+        assert!(log.hint_path().exists());
+    }
+
+    //hint 没有覆盖的写入从日志中读取
+    eng.set(b"key99".to_vec(), b"value99".to_vec())?;
+    drop(eng);
+    let eng = DiskEngine::with_options(dir.clone(), options.clone())?;
+    let mut all = expected.clone();
+    all.push((b"key99".to_vec(), b"value99".to_vec()));
+    assert_eq!(read_all(&eng)?, all);
+    drop(eng);
+
+    //压缩时删除旧的段之前崩溃, 旧的数据被新的段覆盖
+    for (path, data) in &old_files {
+        fs::write(path, data)?;
+    }
+    let eng = DiskEngine::with_options(dir.clone(), options.clone())?;
+    assert_eq!(read_all(&eng)?, all);
+    drop(eng);
+    for (path, _) in &old_files {
+        fs::remove_file(path)?;
+    }
+
+    //启动时使用 hint, 不读取 hint 覆盖的日志
+    let first = dir.join(format!("{:08}.log", new_ids[0]));
+    let mut data = fs::read(&first)?;
+    data[(FILE_HEADER_SIZE + LOG_HEADER_SIZE as u64) as usize] ^= 0xff;
+    fs::write(&first, &data)?;
+    let eng = DiskEngine::with_options(dir.clone(), options.clone())?;
+    assert!(matches!(eng.get(b"key05".to_vec()), Err(Error::Corruption(..))));
+    drop(eng);
+
+    //hint 校验失败时从日志读取
+    let hint = first.with_extension("hint");
+    let mut data = fs::read(&hint)?;
+    data[0] ^= 0xff;
+    fs::write(&hint, &data)?;
+    assert!(matches!(
+        DiskEngine::with_options(dir.clone(), options),
+        Err(Error::Corruption(..))
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_disk_engine_online_compaction() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("sqldb");
+    let options = DiskEngineOptions {
+        max_segment_size: 200,
+        ..Default::default()
+    };
+
+    //反复覆盖同一批 key, 无效数据在写入的过程中被回收
+    let mut eng = DiskEngine::with_options(dir.clone(), options.clone())?;
+    for round in 0..50 {
+        for i in 0..3 {
+            eng.set(format!("key{}", i).into_bytes(), format!("value{}", round).into_bytes())?;
+        }
+        eng.delete(b"tmp".to_vec())?;
+        let status = eng.status()?;
+        assert_eq!(status.keys, 3);
+        assert_eq!(status.total_disk_size, status.live_disk_size + status.garbage_disk_size);
+    }
+    assert!(eng.segments.len() <= 4);
+    let status = eng.status()?;
+    assert_eq!(status.size, 3 * (4 + 7));
+    let headers = eng.segments.len() as u64 * FILE_HEADER_SIZE;
+    assert_eq!(status.live_disk_size, 3 * entry_size(4, 7) + headers);
+    drop(eng);
+
+    //重新打开之后统计信息不变
+    let eng = DiskEngine::with_options(dir.clone(), options.clone())?;
+    assert_eq!(eng.status()?, status);
+    for i in 0..3 {
+        assert_eq!(eng.get(format!("key{}", i).into_bytes())?, Some(b"value49".to_vec()));
+    }
+    assert_eq!(eng.get(b"tmp".to_vec())?, None);
+    drop(eng);
+
+    //关闭自动压缩时段的个数持续增长
+    let options = DiskEngineOptions {
+        compaction_threshold: None,
+        ..options
+    };
+    let mut eng = DiskEngine::with_options(dir.clone(), options)?;
+    for round in 0..50 {
+        eng.set(b"key0".to_vec(), format!("value{}", round).into_bytes())?;
+    }
+    assert!(eng.segments.len() > 4);
+    assert!(eng.status()?.garbage_disk_size > eng.status()?.live_disk_size);
+    drop(eng);
+    Ok(())
+}
+
+#[test]
+fn test_disk_engine_file_header() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("sqldb");
+    let path = dir.join("00000001.log");
+    let mut header = FILE_MAGIC.to_vec();
+    header.extend_from_slice(&FORMAT_VERSION.to_be_bytes());
+
+    //新建的段只有文件头
+    let mut eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(fs::read(&path)?, header);
+    assert_eq!(eng.status()?.garbage_disk_size, 0);
+    eng.set(b"aa".to_vec(), b"value1".to_vec())?;
+    drop(eng);
+    let data = fs::read(&path)?;
+    assert!(data.starts_with(&header));
+
+    //不认识的版本号拒绝打开
+    let mut bad = data.clone();
+    bad[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_be_bytes());
+    fs::write(&path, &bad)?;
+    assert_eq!(
+        DiskEngine::new(dir.clone()).err(),
+        Some(Error::Internal(format!(
+            "unsupported log format version {} in {}, expected version {}",
+            FORMAT_VERSION + 1,
+            path.display(),
+            FORMAT_VERSION
+        )))
+    );
+
+    //没有文件头的旧格式需要先升级
+    fs::write(&path, &data[FILE_HEADER_SIZE as usize..])?;
+    assert_eq!(
+        DiskEngine::new(dir.clone()).err(),
+        Some(Error::Internal(format!(
+            "{} has no log file header, logs of the old format must be converted with DiskEngine::upgrade",
+            path.display()
+        )))
+    );
+
+    //创建段的时候崩溃, 文件头只写入了一部分
+    fs::write(&path, &data)?;
+    let next = dir.join("00000002.log");
+    fs::write(&next, &header[..5])?;
+    let mut eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(fs::read(&next)?, header);
+    assert_eq!(eng.get(b"aa".to_vec())?, Some(b"value1".to_vec()));
+    eng.set(b"bb".to_vec(), b"value2".to_vec())?;
+    drop(eng);
+    let eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(eng.get(b"bb".to_vec())?, Some(b"value2".to_vec()));
+    drop(eng);
+    Ok(())
+}
+
+#[test]
+fn test_disk_engine_upgrade() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let root = tmp.path();
+    let expected = vec![
+        (b"aa".to_vec(), b"value3".to_vec()),
+        (b"cc".to_vec(), b"value4".to_vec()),
+    ];
+
+    //旧的单文件日志: key_size, value_size, key, value, 末尾有一条不完整的条目
+    let path = root.join("sqldb-log");
+    let mut data = Vec::new();
+    let entries: [(&[u8], Option<&[u8]>); 5] = [
+        (b"aa", Some(b"value1")),
+        (b"bb", Some(b"value2")),
+        (b"aa", Some(b"value3")),
+        (b"bb", None),
+        (b"cc", Some(b"value4")),
+    ];
+    for (key, value) in entries {
+        data.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        data.extend_from_slice(&value.map_or(-1, |it| it.len() as i32).to_be_bytes());
+        data.extend_from_slice(key);
+        data.extend_from_slice(value.unwrap_or_default());
+    }
+    data.extend_from_slice(&[0, 0, 0, 2, 0, 0]);
+    fs::write(&path, &data)?;
+    assert!(DiskEngine::new(path.clone()).is_err());
+
+    assert!(DiskEngine::upgrade(path.clone())?);
+    assert_eq!(fs::read(root.join("sqldb-log.old"))?, data);
+    assert!(!root.join("sqldb-log.upgrade").exists());
+    let eng = DiskEngine::new(path.clone())?;
+    assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+    drop(eng);
+
+    //已经是当前格式, 不做修改
+    assert!(!DiskEngine::upgrade(path.clone())?);
+
+    //改名的过程中崩溃, 重新执行完成剩下的步骤
+    fs::rename(&path, root.join("sqldb-log.upgrade"))?;
+    assert!(DiskEngine::upgrade(path.clone())?);
+    let eng = DiskEngine::new(path.clone())?;
+    assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+    drop(eng);
+
+    //没有文件头的日志段目录
+    let dir = root.join("sqldb");
+    fs::create_dir_all(&dir)?;
+    let mut first = Vec::new();
+    for (key, value) in &entries[..3] {
+        first.extend_from_slice(&Log::encode_entry(key, *value));
+    }
+    let mut batch = WriteBatch::new();
+    batch.delete(b"bb".to_vec());
+    batch.set(b"cc".to_vec(), b"value4".to_vec());
+    fs::write(dir.join("00000001.log"), &first)?;
+    fs::write(dir.join("00000002.log"), Log::encode_batch(&batch)?)?;
+    assert!(DiskEngine::new(dir.clone()).is_err());
+
+    assert!(DiskEngine::upgrade(dir.clone())?);
+    assert_eq!(fs::read(root.join("sqldb.old").join("00000001.log"))?, first);
+    let eng = DiskEngine::new(dir.clone())?;
+    assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+    drop(eng);
+    assert!(!DiskEngine::upgrade(dir.clone())?);
+    Ok(())
+}
@@ -16,40 +16,122 @@ pub trait Engine {
 
     /**
      * 获取key对应的数值
+     * 读操作只需要共享引用, 多个线程可以同时读取
      */
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>>;
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>>;
 
     /**
      * 删除key对应的数值, 如果key不存在, 则忽略
      */
     fn delete(&mut self, key: Vec<u8>) -> Result<()>;
 
+    /**
+     * 原子地写入一批修改, 要么全部生效, 要么全部不生效
+     */
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()>;
+
+    /**
+     * 将已经写入的数据持久化, 是否真正刷盘由存储引擎的配置决定
+     */
+    fn sync(&mut self) -> Result<()>;
+
+    /**
+     * 存储引擎的统计信息
+     */
+    fn status(&self) -> Result<Status>;
+
     /**
      * 扫描
      */
-    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_>;
+    fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_>;
 
-    fn scan_prefix(&mut self, prefix: Vec<u8>) -> Self::EngineIterator<'_> {
-        let start = Bound::Included(prefix.clone());
-        let mut bound_prefix = prefix.clone();
-        if let Some(it) = bound_prefix.iter_mut().last() {
+    fn scan_prefix(&self, prefix: Vec<u8>) -> Self::EngineIterator<'_> {
+        let last = prefix_end(&prefix);
+        self.scan((Bound::Included(prefix), last))
+    }
+}
+
+/**
+ * 前缀扫描的上界
+ * 去掉末尾的 255, 再将最后一个字节加 1 作为上界
+ * 例如 [1, 2, 255] 的上界为 [1, 3], 全部是 255 时没有上界
+ */
+pub fn prefix_end(prefix: &[u8]) -> Bound<Vec<u8>> {
+    let mut bound_prefix = prefix.to_vec();
+    while bound_prefix.last() == Some(&u8::MAX) {
+        bound_prefix.pop();
+    }
+    match bound_prefix.last_mut() {
+        Some(it) => {
             *it += 1;
+            Bound::Excluded(bound_prefix)
         }
-        let last = Bound::Excluded(bound_prefix);
-
-        self.scan((start, last))
+        None => Bound::Unbounded,
     }
 }
 
 pub trait EngineIterator: DoubleEndedIterator<Item = Result<(Vec<u8>, Vec<u8>)>> {}
 
+/**
+ * 存储引擎的统计信息
+ */
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Status {
+    //存储引擎的名称
+    pub name: String,
+    //key 的个数
+    pub keys: u64,
+    //所有 key 和 value 的大小
+    pub size: u64,
+    //数据文件的总大小
+    pub total_disk_size: u64,
+    //数据文件中有效数据的大小
+    pub live_disk_size: u64,
+    //数据文件中已经被覆盖或者删除的数据的大小, 压缩之后回收
+    pub garbage_disk_size: u64,
+}
+
+/**
+ * 批量写入, 按照加入的顺序执行, value 为 None 表示删除
+ */
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WriteBatch {
+    pub ops: Vec<(Vec<u8>, Option<Vec<u8>>)>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.ops.push((key, Some(value)));
+    }
+
+    pub fn delete(&mut self, key: Vec<u8>) {
+        self.ops.push((key, None));
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{fs, ops::Bound, path::PathBuf, vec};
 
     use crate::{
         error::Result,
-        storage::{disk::DiskEngine, engine::Engine, memory::MemoryEngine},
+        storage::{
+            disk::DiskEngine,
+            engine::{Engine, WriteBatch},
+            memory::MemoryEngine,
+        },
     };
 
     //测试点读操作
@@ -129,15 +211,58 @@ mod tests {
         assert_eq!(key1, b"camhue".to_vec());
         let (key2, _) = iter.next().transpose()?.unwrap();
         assert_eq!(key2, b"canehe".to_vec());
+        drop(iter);
+
+        //前缀以 255 结尾
+        eng.set(vec![1, 255, 3], b"value7".to_vec())?;
+        eng.set(vec![1, 255], b"value8".to_vec())?;
+        eng.set(vec![2], b"value9".to_vec())?;
+        let keys = eng
+            .scan_prefix(vec![1, 255])
+            .map(|it| it.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, vec![vec![1, 255], vec![1, 255, 3]]);
 
         Ok(())
     }
 
+    //批量写入, 同一批中的操作按顺序执行
+    fn test_write_batch(mut eng: impl Engine) -> Result<()> {
+        eng.set(b"aa".to_vec(), b"value1".to_vec())?;
+        eng.set(b"bb".to_vec(), b"value2".to_vec())?;
+
+        let mut batch = WriteBatch::new();
+        batch.delete(b"aa".to_vec());
+        batch.set(b"cc".to_vec(), b"value3".to_vec());
+        batch.set(b"bb".to_vec(), b"value4".to_vec());
+        batch.set(b"dd".to_vec(), vec![]);
+        batch.delete(b"dd".to_vec());
+        batch.set(b"ee".to_vec(), vec![]);
+        eng.write_batch(batch)?;
+        eng.write_batch(WriteBatch::new())?;
+
+        let items = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            items,
+            vec![
+                (b"bb".to_vec(), b"value4".to_vec()),
+                (b"cc".to_vec(), b"value3".to_vec()),
+                (b"ee".to_vec(), vec![]),
+            ]
+        );
+
+        let status = eng.status()?;
+        assert_eq!(status.keys, 3);
+        assert_eq!(status.size, 2 + 6 + 2 + 6 + 2);
+        Ok(())
+    }
+
     #[test]
     fn test_memory() -> Result<()> {
         test_point_opt(MemoryEngine::new())?;
         test_scan(MemoryEngine::new())?;
         test_scan_prefix(MemoryEngine::new())?;
+        test_write_batch(MemoryEngine::new())?;
         Ok(())
     }
 
@@ -151,6 +276,9 @@ mod tests {
 
         test_scan_prefix(DiskEngine::new(PathBuf::from("/tmp/sqldb/db.log"))?)?;
         fs::remove_dir_all(PathBuf::from("/tmp/sqldb"))?;
+
+        test_write_batch(DiskEngine::new(PathBuf::from("/tmp/sqldb/db.log"))?)?;
+        fs::remove_dir_all(PathBuf::from("/tmp/sqldb"))?;
         Ok(())
     }
 }
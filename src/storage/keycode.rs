@@ -6,6 +6,16 @@ use serde::{
 
 use crate::error::{Error, Result};
 
+/**
+ * 保序编码, 编码后的字节序和原始值的大小顺序保持一致
+ * - bool: 1 个字节, false 为 0, true 为 1
+ * - 有符号整数: 大端字节序, 并翻转符号位, 使负数排在正数之前
+ * - 无符号整数: 大端字节序
+ * - 浮点数: 大端字节序, 正数翻转符号位, 负数翻转所有位
+ * - 字符串、字节数组: 0 转义为 0,255, 以 0,0 结尾
+ * - Option: None 为 0, Some 为 1 加上实际的值
+ * - 枚举: 1 个字节的 variant 下标加上内部的值
+ */
 pub fn serialize_key<T: Serialize>(key: &T) -> Result<Vec<u8>> {
     let mut serializer = Serializer { output: Vec::new() };
     key.serialize(&mut serializer)?;
@@ -21,6 +31,24 @@ pub struct Serializer {
     output: Vec<u8>,
 }
 
+impl Serializer {
+    //翻转最高位的符号位, 使负数排在正数之前
+    fn serialize_signed(&mut self, mut bytes: Vec<u8>) {
+        bytes[0] ^= 1 << 7;
+        self.output.extend(bytes);
+    }
+
+    //正数翻转符号位, 负数翻转所有位, 保证浮点数的全序
+    fn serialize_float(&mut self, mut bytes: Vec<u8>, negative: bool) {
+        if negative {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+        } else {
+            bytes[0] ^= 1 << 7;
+        }
+        self.output.extend(bytes);
+    }
+}
+
 impl<'a> ser::Serializer for &'a mut Serializer {
     type Ok = ();
 
@@ -32,44 +60,55 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 
     type SerializeTupleVariant = Self;
 
-    type SerializeTupleStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeTupleStruct = Self;
 
     type SerializeMap = Impossible<Self::Ok, Self::Error>;
 
-    type SerializeStruct = Impossible<Self::Ok, Self::Error>;
+    type SerializeStruct = Self;
 
-    type SerializeStructVariant = Impossible<Self::Ok, Self::Error>;
+    type SerializeStructVariant = Self;
 
     fn serialize_bool(self, v: bool) -> Result<()> {
-        todo!()
+        self.output.push(v as u8);
+        Ok(())
     }
 
     fn serialize_i8(self, v: i8) -> Result<()> {
-        todo!()
+        self.serialize_signed(v.to_be_bytes().to_vec());
+        Ok(())
     }
 
     fn serialize_i16(self, v: i16) -> Result<()> {
-        todo!()
+        self.serialize_signed(v.to_be_bytes().to_vec());
+        Ok(())
     }
 
     fn serialize_i32(self, v: i32) -> Result<()> {
-        todo!()
+        self.serialize_signed(v.to_be_bytes().to_vec());
+        Ok(())
     }
 
+    // -1 -> 0x7f ff ff ff ff ff ff ff
+    //  0 -> 0x80 00 00 00 00 00 00 00
+    //  1 -> 0x80 00 00 00 00 00 00 01
     fn serialize_i64(self, v: i64) -> Result<()> {
-        todo!()
+        self.serialize_signed(v.to_be_bytes().to_vec());
+        Ok(())
     }
 
     fn serialize_u8(self, v: u8) -> Result<()> {
-        todo!()
+        self.output.push(v);
+        Ok(())
     }
 
     fn serialize_u16(self, v: u16) -> Result<()> {
-        todo!()
+        self.output.extend(v.to_be_bytes());
+        Ok(())
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
-        todo!()
+        self.output.extend(v.to_be_bytes());
+        Ok(())
     }
 
     fn serialize_u64(self, v: u64) -> Result<()> {
@@ -78,19 +117,21 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_f32(self, v: f32) -> Result<()> {
-        todo!()
+        self.serialize_float(v.to_be_bytes().to_vec(), v.is_sign_negative());
+        Ok(())
     }
 
     fn serialize_f64(self, v: f64) -> Result<()> {
-        todo!()
+        self.serialize_float(v.to_be_bytes().to_vec(), v.is_sign_negative());
+        Ok(())
     }
 
     fn serialize_char(self, v: char) -> Result<()> {
-        todo!()
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        todo!()
+        self.serialize_bytes(v.as_bytes())
     }
 
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
@@ -109,40 +150,43 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     }
 
     fn serialize_none(self) -> Result<()> {
-        todo!()
+        self.output.push(0);
+        Ok(())
     }
 
     fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        todo!()
+        self.output.push(1);
+        value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<()> {
-        todo!()
+        Ok(())
     }
 
-    fn serialize_unit_struct(self, name: &'static str) -> Result<()> {
-        todo!()
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
     }
 
     //类似Mvcc:NextVersion
     fn serialize_unit_variant(
         self,
-        name: &'static str,
+        _name: &'static str,
         variant_index: u32,
-        variant: &'static str,
+        _variant: &'static str,
     ) -> Result<()> {
-        self.output.extend(u8::try_from(variant_index));
+        let index = u8::try_from(variant_index).map_err(|e| Error::Internal(e.to_string()))?;
+        self.output.push(index);
         Ok(())
     }
 
-    fn serialize_newtype_struct<T>(self, name: &'static str, value: &T) -> Result<()>
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + ser::Serialize,
     {
-        todo!()
+        value.serialize(self)
     }
 
     //类似  Mvcc::TxnActive(Version)
@@ -161,20 +205,20 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         Ok(())
     }
 
-    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
         Ok(self)
     }
 
-    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple> {
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
         Ok(self)
     }
 
     fn serialize_tuple_struct(
         self,
-        name: &'static str,
-        len: usize,
+        _name: &'static str,
+        _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
-        todo!()
+        Ok(self)
     }
 
     //Mvcc::TxnWrite(Version, Vec<u8>)
@@ -183,18 +227,19 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         name: &'static str,
         variant_index: u32,
         variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeTupleVariant> {
         self.serialize_unit_variant(name, variant_index, variant)?;
         Ok(self)
     }
 
-    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap> {
-        todo!()
+    //map 中的 key 没有固定顺序, 无法保序编码
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(Error::Internal("map is not supported in key".into()))
     }
 
-    fn serialize_struct(self, name: &'static str, len: usize) -> Result<Self::SerializeStruct> {
-        todo!()
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Ok(self)
     }
 
     fn serialize_struct_variant(
@@ -202,9 +247,10 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         name: &'static str,
         variant_index: u32,
         variant: &'static str,
-        len: usize,
+        _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        todo!()
+        self.serialize_unit_variant(name, variant_index, variant)?;
+        Ok(self)
     }
 }
 
@@ -242,6 +288,22 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
     }
 }
 
+impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
 impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     type Ok = ();
     type Error = Error;
@@ -258,6 +320,39 @@ impl<'a> ser::SerializeTupleVariant for &'a mut Serializer {
     }
 }
 
+//结构体按照字段的声明顺序编码
+impl<'a> ser::SerializeStruct for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for &'a mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
 pub struct Deserializer<'de> {
     // This string starts with the input data and characters are truncated off
     // the beginning as data is parsed.
@@ -265,10 +360,17 @@ pub struct Deserializer<'de> {
 }
 
 impl<'de> Deserializer<'de> {
-    fn take_bytes(&mut self, len: usize) -> &[u8] {
+    fn take_bytes(&mut self, len: usize) -> Result<&[u8]> {
+        if self.input.len() < len {
+            return Err(Error::Internal(format!(
+                "insufficient bytes, expected {}, got {}",
+                len,
+                self.input.len()
+            )));
+        }
         let bytes = &self.input[..len];
         self.input = &self.input[len..];
-        bytes
+        Ok(bytes)
     }
 
     //如果0之后是255, 说明是原始字符串0, 继续解析
@@ -292,79 +394,106 @@ impl<'de> Deserializer<'de> {
 
         Ok(res)
     }
+
+    fn next_string(&mut self) -> Result<String> {
+        String::from_utf8(self.next_bytes()?).map_err(|e| Error::Internal(e.to_string()))
+    }
+
+    //还原有符号整数的符号位
+    fn next_signed<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes: [u8; N] = self.take_bytes(N)?.try_into()?;
+        bytes[0] ^= 1 << 7;
+        Ok(bytes)
+    }
+
+    //还原浮点数, 符号位为 1 的是正数, 否则是负数
+    fn next_float<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes: [u8; N] = self.take_bytes(N)?.try_into()?;
+        if bytes[0] >> 7 == 1 {
+            bytes[0] ^= 1 << 7;
+        } else {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+        }
+        Ok(bytes)
+    }
 }
 
 impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     type Error = Error;
 
-    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    //编码中不包含类型信息, 无法推断类型
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        Err(Error::Internal("deserialize_any is not supported".into()))
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_bool(match self.take_bytes(1)?[0] {
+            0 => false,
+            1 => true,
+            b => return Err(Error::Internal(format!("invalid boolean value {}", b))),
+        })
     }
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_i8(i8::from_be_bytes(self.next_signed()?))
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_i16(i16::from_be_bytes(self.next_signed()?))
     }
 
     fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_i32(i32::from_be_bytes(self.next_signed()?))
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_i64(i64::from_be_bytes(self.next_signed()?))
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_u8(self.take_bytes(1)?[0])
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_u16(u16::from_be_bytes(self.take_bytes(2)?.try_into()?))
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_u32(u32::from_be_bytes(self.take_bytes(4)?.try_into()?))
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        let bytes = self.take_bytes(8);
+        let bytes = self.take_bytes(8)?;
         let v = u64::from_be_bytes(bytes.try_into()?);
         visitor.visit_u64(v)
     }
@@ -373,35 +502,40 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_f32(f32::from_be_bytes(self.next_float()?))
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_f64(f64::from_be_bytes(self.next_float()?))
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        let s = self.next_string()?;
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(Error::Internal(format!("invalid char value {}", s))),
+        }
     }
 
     fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_str(&self.next_string()?)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_string(self.next_string()?)
     }
 
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
@@ -422,28 +556,32 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        match self.take_bytes(1)?[0] {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            b => Err(Error::Internal(format!("invalid option value {}", b))),
+        }
     }
 
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_unit()
     }
 
-    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_unit()
     }
 
-    fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
@@ -453,7 +591,7 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_seq(self)
     }
 
-    fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
@@ -462,39 +600,39 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
 
     fn deserialize_tuple_struct<V>(
         self,
-        name: &'static str,
-        len: usize,
+        _name: &'static str,
+        _len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_seq(self)
     }
 
-    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        Err(Error::Internal("map is not supported in key".into()))
     }
 
     fn deserialize_struct<V>(
         self,
-        name: &'static str,
-        fields: &'static [&'static str],
+        _name: &'static str,
+        _fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_seq(self)
     }
 
     fn deserialize_enum<V>(
         self,
-        name: &'static str,
-        variants: &'static [&'static str],
+        _name: &'static str,
+        _variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
@@ -503,22 +641,22 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_enum(self)
     }
 
-    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        Err(Error::Internal("deserialize_identifier is not supported".into()))
     }
 
-    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        Err(Error::Internal("deserialize_ignored_any is not supported".into()))
     }
 }
 
-impl<'de, 'a> SeqAccess<'de> for Deserializer<'de> {
+impl<'de> SeqAccess<'de> for Deserializer<'de> {
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -530,7 +668,7 @@ impl<'de, 'a> SeqAccess<'de> for Deserializer<'de> {
     }
 }
 
-impl<'de, 'a> EnumAccess<'de> for &mut Deserializer<'de> {
+impl<'de> EnumAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     type Variant = Self;
@@ -539,13 +677,13 @@ impl<'de, 'a> EnumAccess<'de> for &mut Deserializer<'de> {
     where
         V: de::DeserializeSeed<'de>,
     {
-        let index = self.take_bytes(1)[0] as u32;
+        let index = self.take_bytes(1)?[0] as u32;
         let variant_index: Result<_> = seed.deserialize(index.into_deserializer());
         Ok((variant_index?, self))
     }
 }
 
-impl<'de, 'a> VariantAccess<'de> for &mut Deserializer<'de> {
+impl<'de> VariantAccess<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
@@ -559,7 +697,7 @@ impl<'de, 'a> VariantAccess<'de> for &mut Deserializer<'de> {
         seed.deserialize(&mut *self)
     }
 
-    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value>
     where
         V: de::Visitor<'de>,
     {
@@ -568,13 +706,13 @@ impl<'de, 'a> VariantAccess<'de> for &mut Deserializer<'de> {
 
     fn struct_variant<V>(
         self,
-        fields: &'static [&'static str],
+        _fields: &'static [&'static str],
         visitor: V,
     ) -> std::result::Result<V::Value, Self::Error>
     where
         V: de::Visitor<'de>,
     {
-        todo!()
+        visitor.visit_seq(self)
     }
 }
 
@@ -648,4 +786,68 @@ mod tests {
             vec![3, 97, 98, 99, 0, 0, 0, 0, 0, 0, 0, 0, 0, 11],
         );
     }
+
+    #[test]
+    fn test_encode_order() {
+        use crate::sql::types::Value;
+
+        //编码后的字节顺序和值的顺序一致
+        let assert_order = |values: Vec<Value>| {
+            let encoded = values
+                .iter()
+                .map(|it| keycode::serialize_key(it).unwrap())
+                .collect::<Vec<_>>();
+            let mut sorted = encoded.clone();
+            sorted.sort();
+            assert_eq!(encoded, sorted);
+
+            for (v, bytes) in values.iter().zip(encoded.iter()) {
+                let decoded: Value = super::deserialize_key(bytes).unwrap();
+                assert_eq!(&decoded, v);
+            }
+        };
+
+        assert_order(
+            vec![i64::MIN, -256, -1, 0, 1, 255, 256, i64::MAX]
+                .into_iter()
+                .map(Value::Integer)
+                .collect(),
+        );
+        assert_order(
+            vec![f64::NEG_INFINITY, -1.5e10, -1.0, -0.0, 0.0, 1e-10, 1.0, 2.5, f64::INFINITY]
+                .into_iter()
+                .map(Value::Float)
+                .collect(),
+        );
+        assert_order(
+            vec!["", "\0", "a", "a\0", "a\0b", "ab", "b"]
+                .into_iter()
+                .map(|it| Value::String(it.to_string()))
+                .collect(),
+        );
+        assert_order(vec![
+            Value::Null,
+            Value::Boolean(false),
+            Value::Boolean(true),
+            Value::Integer(-1),
+        ]);
+    }
+
+    #[test]
+    fn test_decode_types() {
+        let v: (bool, i8, i16, i32, u16, u32) = super::deserialize_key(
+            &keycode::serialize_key(&(true, -8i8, -16i16, 32i32, 16u16, 32u32)).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(v, (true, -8, -16, 32, 16, 32));
+
+        let v: (f32, char, Option<u8>, Option<String>) = super::deserialize_key(
+            &keycode::serialize_key(&(-1.5f32, 'x', None::<u8>, Some("s".to_string()))).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(v, (-1.5, 'x', None, Some("s".to_string())));
+
+        //数据不完整时返回错误
+        assert!(super::deserialize_key::<u64>(&[0, 1]).is_err());
+    }
 }
@@ -2,7 +2,7 @@ use std::collections::{btree_map, BTreeMap};
 
 use crate::error::Result;
 
-use super::engine::EngineIterator;
+use super::engine::{EngineIterator, Status, WriteBatch};
 
 pub struct MemoryEngine {
     data: BTreeMap<Vec<u8>, Vec<u8>>,
@@ -24,7 +24,7 @@ impl super::engine::Engine for MemoryEngine {
         Ok(())
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
+    fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
         Ok(self.data.get(&key).cloned())
     }
 
@@ -33,7 +33,32 @@ impl super::engine::Engine for MemoryEngine {
         Ok(())
     }
 
-    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+    //内存引擎的修改都在持有 &mut self 时完成, 不会被其他操作打断
+    fn write_batch(&mut self, batch: WriteBatch) -> Result<()> {
+        for (key, value) in batch.ops {
+            match value {
+                Some(value) => self.data.insert(key, value),
+                None => self.data.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    //内存引擎没有需要持久化的数据
+    fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn status(&self) -> Result<Status> {
+        Ok(Status {
+            name: "memory".into(),
+            keys: self.data.len() as u64,
+            size: self.data.iter().map(|(k, v)| (k.len() + v.len()) as u64).sum(),
+            ..Default::default()
+        })
+    }
+
+    fn scan(&self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
         MemoryEnginIterator {
             inner: self.data.range(range),
         }
@@ -1,25 +1,31 @@
 use crate::error::{Error, Result};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashSet},
-    sync::{Arc, Mutex, MutexGuard},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    ops::{Bound, RangeBounds},
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard},
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use super::{
-    engine::Engine,
+    engine::{prefix_end, Engine, Status, WriteBatch},
     keycode::{deserialize_key, serialize_key},
 };
 
 pub type Version = u64;
 
 pub struct Mvcc<E: Engine> {
-    engine: Arc<Mutex<E>>,
+    engine: Arc<RwLock<E>>,
+    //只读事务不写入 TxnActive, 在内存中记录它们能看到的最小版本号以及事务个数, 用于计算垃圾回收的水位线
+    snapshots: Arc<Mutex<BTreeMap<Version, usize>>>,
 }
 
 impl<E: Engine> Clone for Mvcc<E> {
     fn clone(&self) -> Self {
         Self {
             engine: self.engine.clone(),
+            snapshots: self.snapshots.clone(),
         }
     }
 }
@@ -27,32 +33,237 @@ impl<E: Engine> Clone for Mvcc<E> {
 impl<E: Engine> Mvcc<E> {
     pub fn new(eng: E) -> Self {
         Self {
-            engine: Arc::new(Mutex::new(eng)),
+            engine: Arc::new(RwLock::new(eng)),
+            snapshots: Arc::new(Mutex::new(BTreeMap::new())),
         }
     }
 
     pub fn begin(&self) -> Result<MvccTransaction<E>> {
         MvccTransaction::begin(self.engine.clone())
     }
+
+    pub fn begin_read_only(&self) -> Result<MvccTransaction<E>> {
+        MvccTransaction::begin_read_only(self.engine.clone(), self.snapshots.clone())
+    }
+
+    pub fn begin_serializable(&self) -> Result<MvccTransaction<E>> {
+        MvccTransaction::begin_serializable(self.engine.clone())
+    }
+
+    //开启历史版本的只读事务, 看到的是版本号为 version 的事务开启时的数据
+    pub fn begin_as_of(&self, version: Version) -> Result<MvccTransaction<E>> {
+        MvccTransaction::begin_as_of(self.engine.clone(), self.snapshots.clone(), version)
+    }
+
+    //存储引擎的统计信息
+    pub fn status(&self) -> Result<Status> {
+        self.engine.read()?.status()
+    }
+
+    //当前版本号, 即下一个事务将要分配的版本号
+    pub fn current_version(&self) -> Result<Version> {
+        let engine = self.engine.read()?;
+        MvccTransaction::<E>::next_version(&engine)
+    }
+
+    /**
+     * 垃圾回收, 删除所有事务都不会再读取的旧版本, 返回删除的版本个数
+     * 水位线为所有活跃事务能看到的最小版本号, 低于水位线的版本都已经提交, 对所有事务可见
+     * 同一个 key 低于水位线的版本只需要保留最新的一个, 如果最新的是删除标记, 也一并删除
+     */
+    pub fn vacuum(&self) -> Result<usize> {
+        let mut engine = self.engine.write()?;
+        let watermark = self.watermark(&engine)?;
+
+        let mut delete_keys = Vec::new();
+        //当前 key 低于水位线的版本, 按照版本号从小到大排列
+        let mut versions: Vec<(Vec<u8>, bool)> = Vec::new();
+        let mut current_key: Option<Vec<u8>> = None;
+        let mut iter = engine.scan_prefix(MvccTransaction::<E>::version_prefix(Vec::new())?);
+        while let Some((key, value)) = iter.next().transpose()? {
+            let (raw_key, version) = match MvccKey::decode(key.clone())? {
+                MvccKey::Version(raw_key, version) => (raw_key, version),
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            };
+            if current_key.as_ref() != Some(&raw_key) {
+                Self::collect_garbage(&mut versions, &mut delete_keys);
+                current_key = Some(raw_key);
+            }
+            if version < watermark {
+                let deleted = bincode::deserialize::<Option<Vec<u8>>>(&value)?.is_none();
+                versions.push((key, deleted));
+            }
+        }
+        Self::collect_garbage(&mut versions, &mut delete_keys);
+        drop(iter);
+
+        //低于水位线的事务快照不会再被使用
+        let from = MvccKey::TxnActiveSnapshot(0).encode()?;
+        let to = MvccKey::TxnActiveSnapshot(watermark).encode()?;
+        let snapshot_keys = engine
+            .scan(from..to)
+            .map(|item| item.map(|(key, _)| key))
+            .collect::<Result<Vec<_>>>()?;
+
+        let count = delete_keys.len();
+        let mut batch = WriteBatch::new();
+        for key in delete_keys.into_iter().chain(snapshot_keys) {
+            batch.delete(key);
+        }
+
+        //记录水位线, 历史版本查询不能早于这个版本
+        if MvccTransaction::<E>::vacuum_watermark(&engine)? < watermark {
+            batch.set(
+                MvccKey::VacuumWatermark.encode()?,
+                bincode::serialize(&watermark)?,
+            );
+        }
+        engine.write_batch(batch)?;
+        Ok(count)
+    }
+
+    /**
+     * 在后台线程中定期执行垃圾回收
+     * 线程只持有弱引用, 所有的 Mvcc 被释放之后线程退出
+     */
+    pub fn start_vacuum(&self, interval: Duration) -> JoinHandle<()>
+    where
+        E: Send + Sync + 'static,
+    {
+        let engine = Arc::downgrade(&self.engine);
+        let snapshots = Arc::downgrade(&self.snapshots);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            let mvcc = match (engine.upgrade(), snapshots.upgrade()) {
+                (Some(engine), Some(snapshots)) => Mvcc { engine, snapshots },
+                _ => return,
+            };
+            //后台回收失败时等待下一次执行
+            mvcc.vacuum().ok();
+        })
+    }
+
+    //计算水位线, 没有活跃事务时为下一个将要分配的版本号
+    fn watermark(&self, engine: &E) -> Result<Version> {
+        let mut watermark = MvccTransaction::next_version(engine)?;
+        let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnActive.encode()?);
+        while let Some((key, value)) = iter.next().transpose()? {
+            match MvccKey::decode(key.clone())? {
+                MvccKey::TxnActive(version) => {
+                    //TxnActive 中保存了事务能看到的最小版本号, 旧格式的数据为空, 保守地从 0 开始
+                    let snapshot = match value.is_empty() {
+                        true => 0,
+                        false => bincode::deserialize(&value)?,
+                    };
+                    watermark = watermark.min(snapshot).min(version);
+                }
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            }
+        }
+        drop(iter);
+
+        if let Some(snapshot) = self.snapshots.lock()?.keys().next() {
+            watermark = watermark.min(*snapshot);
+        }
+        Ok(watermark)
+    }
+
+    fn collect_garbage(versions: &mut Vec<(Vec<u8>, bool)>, delete_keys: &mut Vec<Vec<u8>>) {
+        if let Some((key, deleted)) = versions.pop() {
+            delete_keys.extend(versions.drain(..).map(|(key, _)| key));
+            if deleted {
+                delete_keys.push(key);
+            }
+        }
+    }
 }
 
 pub struct MvccTransaction<E: Engine> {
-    engine: Arc<Mutex<E>>,
+    engine: Arc<RwLock<E>>,
     state: TransactionState,
+    //事务中的保存点, 按照创建的顺序排列, 写入时在最新的保存点中记录旧值
+    savepoints: Mutex<Vec<Savepoint>>,
+    //可串行化事务的读集合, 快照隔离的事务不记录
+    reads: Option<Mutex<ReadSet>>,
+    //只读事务注册的快照, 事务释放时注销
+    _snapshot: Option<SnapshotGuard>,
+}
+
+struct SnapshotGuard {
+    snapshots: Arc<Mutex<BTreeMap<Version, usize>>>,
+    version: Version,
+}
+
+impl Drop for SnapshotGuard {
+    fn drop(&mut self) {
+        if let Ok(mut snapshots) = self.snapshots.lock() {
+            if let Some(count) = snapshots.get_mut(&self.version) {
+                *count -= 1;
+                if *count == 0 {
+                    snapshots.remove(&self.version);
+                }
+            }
+        }
+    }
+}
+
+//可串行化事务读取过的 key 和扫描过的范围, 提交时检查是否被并发的事务修改
+//范围为编码之后的 Version key
+#[derive(Default)]
+struct ReadSet {
+    keys: HashSet<Vec<u8>>,
+    ranges: Vec<KeyRange>,
+}
+
+type KeyRange = (Bound<Vec<u8>>, Bound<Vec<u8>>);
+
+/**
+ * 保存点, 记录创建之后第一次写入的 key 在写入之前的 Version 值
+ * 保存点之前事务没有写入过的 key 记录为 None, 回滚时直接删除
+ */
+struct Savepoint {
+    name: String,
+    writes: HashMap<Vec<u8>, Option<Vec<u8>>>,
 }
 
+#[derive(Clone)]
 pub struct TransactionState {
     //当前版本号
     pub version: Version,
 
     //当前事务下活跃事务列表
     pub active_versions: HashSet<Version>,
+
+    //只读事务不分配新的版本号, version 为下一个将要分配的版本号
+    pub read_only: bool,
 }
 
 impl TransactionState {
+    //事务能看到的最小版本号, 低于这个版本号的数据都已经提交
+    fn snapshot_version(&self) -> Version {
+        self.active_versions
+            .iter()
+            .min()
+            .copied()
+            .unwrap_or(self.version)
+            .min(self.version)
+    }
+
     fn is_visible(&self, version: Version) -> bool {
         if self.active_versions.contains(&version) {
             false
+        } else if self.read_only {
+            version < self.version
         } else {
             version <= self.version
         }
@@ -65,6 +276,10 @@ pub enum MvccKey {
     TxnActive(Version),
     TxnWrite(Version, #[serde(with = "serde_bytes")] Vec<u8>),
     Version(#[serde(with = "serde_bytes")] Vec<u8>, Version),
+    //事务开启时的活跃事务列表, 用于历史版本查询, 列表为空时不写入
+    TxnActiveSnapshot(Version),
+    //已经执行过的垃圾回收的最大水位线, 更早的历史版本不能再查询
+    VacuumWatermark,
 }
 
 //Version key1-101, key2-102
@@ -97,60 +312,186 @@ impl MvccKeyPrefix {
 }
 
 impl<E: Engine> MvccTransaction<E> {
-    pub fn begin(eng: Arc<Mutex<E>>) -> Result<Self> {
+    pub fn begin(eng: Arc<RwLock<E>>) -> Result<Self> {
         //获取存储引擎
-        let mut engine = eng.lock()?;
+        let mut engine = eng.write()?;
         //获取版本号
         let next_version = match engine.get(MvccKey::NextVersion.encode()?)? {
             Some(val) => bincode::deserialize(&val)?,
             None => 1,
         };
 
-        engine.set(
+        //版本号和活跃事务列表在同一批中写入
+        let mut batch = WriteBatch::new();
+        batch.set(
             MvccKey::NextVersion.encode()?,
             bincode::serialize(&(next_version + 1))?,
-        )?;
+        );
 
         //获取当前活跃的事务列表
-        let active_versions = Self::scan_active(&mut engine)?;
+        let active_versions = Self::scan_active(&engine)?;
 
-        //当前事务加入到活跃事务列表
-        engine.set(MvccKey::TxnActive(next_version).encode()?, vec![])?;
+        let state = TransactionState {
+            version: next_version,
+            active_versions,
+            read_only: false,
+        };
+
+        //当前事务加入到活跃事务列表, 同时记录能看到的最小版本号, 用于垃圾回收
+        batch.set(
+            MvccKey::TxnActive(next_version).encode()?,
+            bincode::serialize(&state.snapshot_version())?,
+        );
+        if !state.active_versions.is_empty() {
+            batch.set(
+                MvccKey::TxnActiveSnapshot(next_version).encode()?,
+                bincode::serialize(&state.active_versions)?,
+            );
+        }
+        engine.write_batch(batch)?;
 
         Ok(Self {
             engine: eng.clone(),
-            state: TransactionState {
-                version: next_version,
-                active_versions,
+            state,
+            savepoints: Mutex::new(Vec::new()),
+            reads: None,
+            _snapshot: None,
+        })
+    }
+
+    //开启只读事务, 不会写入任何数据, 可以看到开启之前已经提交的所有数据
+    pub fn begin_read_only(
+        eng: Arc<RwLock<E>>,
+        snapshots: Arc<Mutex<BTreeMap<Version, usize>>>,
+    ) -> Result<Self> {
+        let engine = eng.read()?;
+        let version = Self::next_version(&engine)?;
+        let active_versions = Self::scan_active(&engine)?;
+        Self::begin_snapshot(eng.clone(), engine, snapshots, version, active_versions)
+    }
+
+    /**
+     * 开启历史版本的只读事务, 和版本号为 version 的事务开启时看到的数据一致, 不包括它自己的写入
+     * 如果需要看到某个事务提交的数据, 应该使用它的下一个版本号
+     * 已经被垃圾回收的历史版本不能再查询
+     */
+    pub fn begin_as_of(
+        eng: Arc<RwLock<E>>,
+        snapshots: Arc<Mutex<BTreeMap<Version, usize>>>,
+        version: Version,
+    ) -> Result<Self> {
+        let engine = eng.read()?;
+        let next_version = Self::next_version(&engine)?;
+        if version == 0 || version > next_version {
+            return Err(Error::Internal(format!("version {} does not exist", version)));
+        }
+
+        //当前版本号还没有分配, 使用当前的活跃事务列表
+        let active_versions = match version == next_version {
+            true => Self::scan_active(&engine)?,
+            false => match engine.get(MvccKey::TxnActiveSnapshot(version).encode()?)? {
+                Some(value) => bincode::deserialize(&value)?,
+                None => HashSet::new(),
             },
+        };
+
+        let snapshot_version = active_versions.iter().min().copied().unwrap_or(version).min(version);
+        if snapshot_version < Self::vacuum_watermark(&engine)? {
+            return Err(Error::Internal(format!(
+                "version {} has been vacuumed",
+                version
+            )));
+        }
+        Self::begin_snapshot(eng.clone(), engine, snapshots, version, active_versions)
+    }
+
+    //创建只读事务, 持有存储引擎的锁时注册快照, 避免和垃圾回收交错
+    fn begin_snapshot(
+        eng: Arc<RwLock<E>>,
+        engine: RwLockReadGuard<E>,
+        snapshots: Arc<Mutex<BTreeMap<Version, usize>>>,
+        version: Version,
+        active_versions: HashSet<Version>,
+    ) -> Result<Self> {
+        let state = TransactionState {
+            version,
+            active_versions,
+            read_only: true,
+        };
+
+        let snapshot_version = state.snapshot_version();
+        *snapshots.lock()?.entry(snapshot_version).or_insert(0) += 1;
+        drop(engine);
+
+        Ok(Self {
+            engine: eng,
+            state,
+            savepoints: Mutex::new(Vec::new()),
+            reads: None,
+            _snapshot: Some(SnapshotGuard {
+                snapshots,
+                version: snapshot_version,
+            }),
         })
     }
 
+    /**
+     * 开启可串行化事务, 在快照隔离的基础上记录读集合
+     * 提交时如果读取过的数据被并发的事务修改并且已经提交, 事务回滚并返回 Error::Serialization
+     */
+    pub fn begin_serializable(eng: Arc<RwLock<E>>) -> Result<Self> {
+        let mut txn = Self::begin(eng)?;
+        txn.reads = Some(Mutex::new(ReadSet::default()));
+        Ok(txn)
+    }
+
+    pub fn version(&self) -> Version {
+        self.state.version
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.state.read_only
+    }
+
     pub fn commit(&self) -> Result<()> {
-        let mut engine = self.engine.lock()?;
+        //只读事务没有需要清理的数据
+        if self.state.read_only {
+            return Ok(());
+        }
+        let mut engine = self.engine.write()?;
 
-        let mut delete_keys = Vec::new();
+        //可串行化检查失败时回滚事务
+        if let Err(err) = self.check_serializable(&engine) {
+            drop(engine);
+            self.rollback()?;
+            return Err(err);
+        }
+
+        //TxnWrite 信息和活跃事务列表在同一批中删除, 不会只删除一部分
+        let mut batch = WriteBatch::new();
 
         //找到这个事务的TxnWrite信息,并删除
         let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
         while let Some((key, _)) = iter.next().transpose()? {
-            delete_keys.push(key);
+            batch.delete(key);
         }
 
         drop(iter);
 
-        for key in delete_keys {
-            engine.delete(key)?;
-        }
-
         //删除活跃事务列表
-        engine.delete(MvccKey::TxnActive(self.state.version).encode()?)
+        batch.delete(MvccKey::TxnActive(self.state.version).encode()?);
+        engine.write_batch(batch)?;
+        //提交之后按照存储引擎的刷盘策略持久化
+        engine.sync()
     }
 
     pub fn rollback(&self) -> Result<()> {
-        let mut engine = self.engine.lock()?;
+        if self.state.read_only {
+            return Ok(());
+        }
+        let mut engine = self.engine.write()?;
 
-        let mut delete_keys = Vec::new();
+        let mut batch = WriteBatch::new();
 
         //找到这个事务的TxnWrite信息,并删除
         let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
@@ -158,7 +499,7 @@ impl<E: Engine> MvccTransaction<E> {
             match MvccKey::decode(key.clone())? {
                 //MvccKey:Version信息也需要一并删掉
                 MvccKey::TxnWrite(_, raw_key) => {
-                    delete_keys.push(MvccKey::Version(raw_key, self.state.version).encode()?);
+                    batch.delete(MvccKey::Version(raw_key, self.state.version).encode()?);
                 }
                 _ => {
                     return Err(Error::Internal(format!(
@@ -167,17 +508,81 @@ impl<E: Engine> MvccTransaction<E> {
                     )))
                 }
             }
-            delete_keys.push(key);
+            batch.delete(key);
         }
 
         drop(iter);
 
-        for key in delete_keys {
-            engine.delete(key)?;
+        //删除活跃事务列表
+        batch.delete(MvccKey::TxnActive(self.state.version).encode()?);
+        engine.write_batch(batch)
+    }
+
+    //创建保存点, 同名的保存点已经存在时, 回滚和释放操作的是最新的一个
+    pub fn savepoint(&mut self, name: String) -> Result<()> {
+        self.savepoints.get_mut()?.push(Savepoint {
+            name,
+            writes: HashMap::new(),
+        });
+        Ok(())
+    }
+
+    /**
+     * 回滚到保存点, 撤销保存点之后的写入, 保存点本身仍然保留
+     * 从最新的保存点开始依次恢复记录的旧值, 同一个 key 以最早的保存点中记录的为准
+     */
+    pub fn rollback_to_savepoint(&mut self, name: &str) -> Result<()> {
+        let savepoints = self.savepoints.get_mut()?;
+        let position = Self::savepoint_position(savepoints, name)?;
+        let mut batch = WriteBatch::new();
+        for savepoint in savepoints.drain(position + 1..).rev() {
+            Self::undo_writes(&mut batch, self.state.version, savepoint.writes)?;
         }
+        let writes = std::mem::take(&mut savepoints[position].writes);
+        Self::undo_writes(&mut batch, self.state.version, writes)?;
 
-        //删除活跃事务列表
-        engine.delete(MvccKey::TxnActive(self.state.version).encode()?)
+        self.engine.write()?.write_batch(batch)
+    }
+
+    //释放保存点, 之后创建的保存点一并释放, 记录的旧值合并到之前的保存点中
+    pub fn release_savepoint(&mut self, name: &str) -> Result<()> {
+        let savepoints = self.savepoints.get_mut()?;
+        let position = Self::savepoint_position(savepoints, name)?;
+        let released = savepoints.split_off(position);
+        if let Some(previous) = savepoints.last_mut() {
+            for savepoint in released {
+                for (key, value) in savepoint.writes {
+                    previous.writes.entry(key).or_insert(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn savepoint_position(savepoints: &[Savepoint], name: &str) -> Result<usize> {
+        savepoints
+            .iter()
+            .rposition(|it| it.name == name)
+            .ok_or(Error::Internal(format!("savepoint {} does not exist", name)))
+    }
+
+    //将 key 恢复成保存点中记录的旧值, 之前没有写入过的 key 直接删除
+    fn undo_writes(
+        batch: &mut WriteBatch,
+        version: Version,
+        writes: HashMap<Vec<u8>, Option<Vec<u8>>>,
+    ) -> Result<()> {
+        for (raw_key, value) in writes {
+            let key = MvccKey::Version(raw_key.clone(), version).encode()?;
+            match value {
+                Some(value) => batch.set(key, value),
+                None => {
+                    batch.delete(key);
+                    batch.delete(MvccKey::TxnWrite(version, raw_key).encode()?);
+                }
+            }
+        }
+        Ok(())
     }
 
     pub fn set(&self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
@@ -189,7 +594,10 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     pub fn get(&self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
-        let mut engine = self.engine.lock()?;
+        if let Some(reads) = &self.reads {
+            reads.lock()?.keys.insert(key.clone());
+        }
+        let engine = self.engine.read()?;
         //如果version : 9
         //扫描version的范围 0..=9
         let from = MvccKey::Version(key.clone(), 0).encode()?;
@@ -216,7 +624,53 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     pub fn scan_prefix(&self, prefix: Vec<u8>) -> Result<Vec<ScanResult>> {
-        let mut eng = self.engine.lock()?;
+        self.scan_prefix_iter(prefix)?.collect()
+    }
+
+    //流式扫描前缀下的数据, 按 key 的顺序返回
+    pub fn scan_prefix_iter(&self, prefix: Vec<u8>) -> Result<MvccScanIterator<E>> {
+        let start = Self::version_prefix(prefix)?;
+        let end = prefix_end(&start);
+        self.scan_versions(Bound::Included(start), end)
+    }
+
+    //流式扫描 key 范围内的数据, 支持正向和反向迭代
+    pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<MvccScanIterator<E>> {
+        //同一个 key 的版本号从 0 到 u64::MAX 排列
+        let start = match range.start_bound() {
+            Bound::Included(key) => Bound::Included(MvccKey::Version(key.clone(), 0).encode()?),
+            Bound::Excluded(key) => {
+                Bound::Excluded(MvccKey::Version(key.clone(), u64::MAX).encode()?)
+            }
+            Bound::Unbounded => Bound::Included(Self::version_prefix(Vec::new())?),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(key) => {
+                Bound::Included(MvccKey::Version(key.clone(), u64::MAX).encode()?)
+            }
+            Bound::Excluded(key) => Bound::Excluded(MvccKey::Version(key.clone(), 0).encode()?),
+            Bound::Unbounded => prefix_end(&Self::version_prefix(Vec::new())?),
+        };
+        self.scan_versions(start, end)
+    }
+
+    fn scan_versions(
+        &self,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> Result<MvccScanIterator<E>> {
+        if let Some(reads) = &self.reads {
+            reads.lock()?.ranges.push((start.clone(), end.clone()));
+        }
+        Ok(MvccScanIterator::new(
+            self.engine.clone(),
+            self.state.clone(),
+            start,
+            end,
+        ))
+    }
+
+    fn version_prefix(prefix: Vec<u8>) -> Result<Vec<u8>> {
         let mut enc_prefix = MvccKeyPrefix::Version(prefix).encode()?;
 
         //原始值       编码后
@@ -225,34 +679,70 @@ impl<E: Engine> MvccTransaction<E> {
         //97,98  ->     97,98,0,0
         //去掉最后的[0,0] 后缀
         enc_prefix.truncate(enc_prefix.len() - 2);
-        let mut iter = eng.scan_prefix(enc_prefix);
-        let mut btree_map = BTreeMap::new();
-        while let Some((key, value)) = iter.next().transpose()? {
+        Ok(enc_prefix)
+    }
+
+    /**
+     * 检查读集合中是否有并发事务提交的写入
+     * 对当前事务不可见, 并且已经不在活跃列表中的版本, 就是并发事务已经提交的写入
+     * 回滚的事务会删除写入的数据, 不会被误判
+     */
+    fn check_serializable(&self, engine: &E) -> Result<()> {
+        let reads = match &self.reads {
+            Some(reads) => reads.lock()?,
+            None => return Ok(()),
+        };
+        let active_versions = Self::scan_active(engine)?;
+        let conflict = |key: Vec<u8>| -> Result<bool> {
             match MvccKey::decode(key.clone())? {
-                MvccKey::Version(raw_key, version) => {
-                    if self.state.is_visible(version) {
-                        match bincode::deserialize(&value)? {
-                            Some(raw_value) => btree_map.insert(raw_key, raw_value),
-                            None => btree_map.remove(&raw_key),
-                        };
-                    }
+                MvccKey::Version(_, version) => Ok(!self.state.is_visible(version)
+                    && !active_versions.contains(&version)),
+                _ => Err(Error::Internal(format!(
+                    "unexpected key: {:?}",
+                    String::from_utf8(key)
+                ))),
+            }
+        };
+
+        for key in &reads.keys {
+            let from = MvccKey::Version(key.clone(), 0).encode()?;
+            let to = MvccKey::Version(key.clone(), u64::MAX).encode()?;
+            let mut iter = engine.scan(from..=to);
+            while let Some((key, _)) = iter.next().transpose()? {
+                if conflict(key)? {
+                    return Err(Error::Serialization);
                 }
-                _ => {
-                    return Err(Error::Internal(format!(
-                        "Unexpected key {:?}",
-                        String::from_utf8(key)
-                    )));
+            }
+        }
+        for (start, end) in &reads.ranges {
+            if is_empty_range(start, end) {
+                continue;
+            }
+            let mut iter = engine.scan((start.clone(), end.clone()));
+            while let Some((key, _)) = iter.next().transpose()? {
+                if conflict(key)? {
+                    return Err(Error::Serialization);
                 }
             }
         }
-        let result = btree_map
-            .into_iter()
-            .map(|(key, value)| ScanResult { key, value })
-            .collect();
-        Ok(result)
+        Ok(())
     }
 
-    fn scan_active(engine: &mut MutexGuard<E>) -> Result<HashSet<Version>> {
+    fn next_version(engine: &E) -> Result<Version> {
+        Ok(match engine.get(MvccKey::NextVersion.encode()?)? {
+            Some(val) => bincode::deserialize(&val)?,
+            None => 1,
+        })
+    }
+
+    fn vacuum_watermark(engine: &E) -> Result<Version> {
+        Ok(match engine.get(MvccKey::VacuumWatermark.encode()?)? {
+            Some(val) => bincode::deserialize(&val)?,
+            None => 0,
+        })
+    }
+
+    fn scan_active(engine: &E) -> Result<HashSet<Version>> {
         let mut active_versions = HashSet::new();
         let mut iter = engine.scan_prefix(MvccKeyPrefix::TxnActive.encode()?);
         while let Some((key, _)) = iter.next().transpose()? {
@@ -273,8 +763,11 @@ impl<E: Engine> MvccTransaction<E> {
     }
 
     fn write_inner(&self, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<()> {
+        if self.state.read_only {
+            return Err(Error::ReadOnly);
+        }
         //获取存储引擎
-        let mut engine = self.engine.lock()?;
+        let mut engine = self.engine.write()?;
 
         //检测冲突
         //当前活跃列表 3  4  5
@@ -312,38 +805,232 @@ impl<E: Engine> MvccTransaction<E> {
             }
         }
 
+        //保存点之后第一次写入这个 key, 记录写入之前的值
+        let version_key = MvccKey::Version(key.clone(), self.state.version).encode()?;
+        if let Some(savepoint) = self.savepoints.lock()?.last_mut() {
+            if !savepoint.writes.contains_key(&key) {
+                savepoint.writes.insert(key.clone(), engine.get(version_key.clone())?);
+            }
+        }
+
         //记录这个version,写入哪些key, 用于回滚事务
-        engine.set(
+        let mut batch = WriteBatch::new();
+        batch.set(
             MvccKey::TxnWrite(self.state.version, key.clone()).encode()?,
             vec![],
-        )?;
+        );
 
         //写入实际的key,value数据
-        engine.set(
-            MvccKey::Version(key.clone(), self.state.version).encode()?,
-            bincode::serialize(&value)?,
-        )?;
+        batch.set(version_key, bincode::serialize(&value)?);
 
-        Ok(())
+        engine.write_batch(batch)
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ScanResult {
     pub key: Vec<u8>,
     pub value: Vec<u8>,
 }
 
+//流式扫描时每次加锁处理的 key 的个数
+const SCAN_BATCH_SIZE: usize = 64;
+
+/**
+ * MVCC 流式扫描迭代器
+ * 每次只在加锁期间读取一批 key, 解析出可见的版本后放入缓冲区, 不会长时间持有存储引擎的锁
+ * 正向和反向迭代分别从范围的两端读取, 每读取一批就缩小剩余的范围, 两端不会重复读取
+ * 迭代过程中其他事务提交的数据对当前事务不可见, 不影响扫描结果
+ */
+pub struct MvccScanIterator<E: Engine> {
+    engine: Arc<RwLock<E>>,
+    state: TransactionState,
+    //还没有读取的范围, 为编码之后的 Version key
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    //范围内的数据都已经读取
+    exhausted: bool,
+    //正向读取的结果, 按 key 从小到大排列
+    front: VecDeque<ScanResult>,
+    //反向读取的结果, 按 key 从大到小排列
+    back: VecDeque<ScanResult>,
+}
+
+impl<E: Engine> MvccScanIterator<E> {
+    fn new(
+        engine: Arc<RwLock<E>>,
+        state: TransactionState,
+        start: Bound<Vec<u8>>,
+        end: Bound<Vec<u8>>,
+    ) -> Self {
+        let exhausted = is_empty_range(&start, &end);
+        Self {
+            engine,
+            state,
+            start,
+            end,
+            exhausted,
+            front: VecDeque::new(),
+            back: VecDeque::new(),
+        }
+    }
+
+    //从范围的起点读取一批数据, 同一个 key 取最新的可见版本
+    fn fill_front(&mut self) -> Result<()> {
+        let engine = self.engine.read()?;
+        let mut iter = engine.scan((self.start.clone(), self.end.clone()));
+        //当前 key 以及最新的可见值, 删除标记和不可见时为 None
+        let mut current: Option<(Vec<u8>, Option<Vec<u8>>)> = None;
+        let mut count = 0;
+        loop {
+            let (raw_key, version, value) = match iter.next().transpose()? {
+                Some((key, value)) => {
+                    let (raw_key, version) = decode_version(key)?;
+                    (raw_key, version, value)
+                }
+                None => {
+                    if let Some((key, Some(value))) = current {
+                        self.front.push_back(ScanResult { key, value });
+                    }
+                    self.exhausted = true;
+                    return Ok(());
+                }
+            };
+
+            match &mut current {
+                Some((key, visible)) if *key == raw_key => {
+                    if self.state.is_visible(version) {
+                        *visible = bincode::deserialize(&value)?;
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            //上一个 key 的所有版本都已经读取
+            if let Some((key, visible)) = current.take() {
+                self.start = Bound::Excluded(MvccKey::Version(key.clone(), u64::MAX).encode()?);
+                if let Some(value) = visible {
+                    self.front.push_back(ScanResult { key, value });
+                }
+                count += 1;
+                if count >= SCAN_BATCH_SIZE {
+                    return Ok(());
+                }
+            }
+            let visible = match self.state.is_visible(version) {
+                true => bincode::deserialize(&value)?,
+                false => None,
+            };
+            current = Some((raw_key, visible));
+        }
+    }
+
+    //从范围的终点读取一批数据, 同一个 key 第一个可见的版本就是最新的可见版本
+    fn fill_back(&mut self) -> Result<()> {
+        let engine = self.engine.read()?;
+        let mut iter = engine.scan((self.start.clone(), self.end.clone())).rev();
+        //当前 key 以及是否已经找到可见版本
+        let mut current: Option<(Vec<u8>, bool)> = None;
+        let mut count = 0;
+        loop {
+            let (raw_key, version, value) = match iter.next().transpose()? {
+                Some((key, value)) => {
+                    let (raw_key, version) = decode_version(key)?;
+                    (raw_key, version, value)
+                }
+                None => {
+                    self.exhausted = true;
+                    return Ok(());
+                }
+            };
+
+            match &current {
+                Some((key, _)) if *key == raw_key => {}
+                _ => {
+                    if let Some((key, _)) = current.take() {
+                        self.end = Bound::Excluded(MvccKey::Version(key, 0).encode()?);
+                        count += 1;
+                        if count >= SCAN_BATCH_SIZE {
+                            return Ok(());
+                        }
+                    }
+                    current = Some((raw_key.clone(), false));
+                }
+            }
+
+            if let Some((_, found)) = &mut current {
+                if !*found && self.state.is_visible(version) {
+                    *found = true;
+                    if let Some(value) = bincode::deserialize(&value)? {
+                        self.back.push_back(ScanResult {
+                            key: raw_key,
+                            value,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<E: Engine> Iterator for MvccScanIterator<E> {
+    type Item = Result<ScanResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        //一批 key 可能全部被删除或者不可见, 继续读取直到有数据或者范围读取完
+        while self.front.is_empty() && !self.exhausted {
+            if let Err(err) = self.fill_front() {
+                return Some(Err(err));
+            }
+        }
+        //范围读取完之后, 剩下的数据在反向读取的缓冲区中
+        self.front.pop_front().or_else(|| self.back.pop_back()).map(Ok)
+    }
+}
+
+impl<E: Engine> DoubleEndedIterator for MvccScanIterator<E> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.back.is_empty() && !self.exhausted {
+            if let Err(err) = self.fill_back() {
+                return Some(Err(err));
+            }
+        }
+        self.back.pop_front().or_else(|| self.front.pop_back()).map(Ok)
+    }
+}
+
+fn decode_version(key: Vec<u8>) -> Result<(Vec<u8>, Version)> {
+    match MvccKey::decode(key.clone())? {
+        MvccKey::Version(raw_key, version) => Ok((raw_key, version)),
+        _ => Err(Error::Internal(format!(
+            "unexpected key: {:?}",
+            String::from_utf8(key)
+        ))),
+    }
+}
+
+//范围中没有任何 key, 存储引擎不能扫描这样的范围
+fn is_empty_range(start: &Bound<Vec<u8>>, end: &Bound<Vec<u8>>) -> bool {
+    match (start, end) {
+        (Bound::Included(s), Bound::Included(e)) => s > e,
+        (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e))
+        | (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::{fs::remove_dir_all, result};
+    use std::{ops::Bound, result};
 
     use crate::{
         error::{self, Error, Result},
         storage::{disk::DiskEngine, engine::Engine, memory::MemoryEngine, mvcc::ScanResult},
     };
 
-    use super::Mvcc;
+    use super::{Mvcc, MvccTransaction};
 
     fn get(eng: impl Engine) -> Result<()> {
         let mvcc = Mvcc::new(eng);
@@ -367,9 +1054,10 @@ mod tests {
     fn test_get() -> Result<()> {
         get(MemoryEngine::new())?;
 
-        let p: std::path::PathBuf = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+
+        let p = dir.path().join("sqldb-log");
         get(DiskEngine::new(p.clone())?)?;
-        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -404,9 +1092,10 @@ mod tests {
     fn test_get_isalation() -> Result<()> {
         get_isolation(MemoryEngine::new())?;
 
-        let p: std::path::PathBuf = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+
+        let p = dir.path().join("sqldb-log");
         get_isolation(DiskEngine::new(p.clone())?)?;
-        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -476,9 +1165,10 @@ mod tests {
     fn test_scan_prefix() -> Result<()> {
         scan_prefix(MemoryEngine::new())?;
 
-        let p: std::path::PathBuf = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+
+        let p = dir.path().join("sqldb-log");
         scan_prefix(DiskEngine::new(p.clone())?)?;
-        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -557,9 +1247,10 @@ mod tests {
     fn test_scan_isolation() -> Result<()> {
         scan_isolation(MemoryEngine::new())?;
 
-        let p: std::path::PathBuf = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+
+        let p = dir.path().join("sqldb-log");
         scan_isolation(DiskEngine::new(p.clone())?)?;
-        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -596,9 +1287,9 @@ mod tests {
     #[test]
     fn test_set() -> Result<()> {
         set(MemoryEngine::new())?;
-        let p: std::path::PathBuf = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         set(DiskEngine::new(p.clone())?)?;
-        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -639,9 +1330,9 @@ mod tests {
     #[test]
     fn test_set_conflict() -> Result<()> {
         set_conflict(MemoryEngine::new())?;
-        let p: std::path::PathBuf = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         set_conflict(DiskEngine::new(p.clone())?)?;
-        std::fs::remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -680,9 +1371,9 @@ mod tests {
     #[test]
     fn test_delete() -> Result<()>{
         delete(MemoryEngine::new())?;
-        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         delete(DiskEngine::new(p.clone())?)?;
-        remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -714,9 +1405,9 @@ mod tests {
     #[test]
     fn test_delete_conflict() -> Result<()>{
         delete_conflict(MemoryEngine::new())?;
-        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         delete_conflict(DiskEngine::new(p.clone())?)?;
-        remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
     
@@ -742,9 +1433,9 @@ mod tests {
     #[test]
     fn test_dirty_read() -> Result<()>{
         dirty_read(MemoryEngine::new())?;
-        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         dirty_read(DiskEngine::new(p.clone())?)?;
-        remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }   
 
@@ -769,9 +1460,9 @@ mod tests {
     #[test]
     fn test_unrepeatable_read() -> Result<()>{
         unrepeatable_read(MemoryEngine::new())?;
-        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         unrepeatable_read(DiskEngine::new(p.clone())?)?;
-        remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }   
 
@@ -834,9 +1525,9 @@ mod tests {
     #[test]
     fn test_phantom_read() -> Result<()>{
         phantom_read(MemoryEngine::new())?;
-        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         phantom_read(DiskEngine::new(p.clone())?)?;
-        remove_dir_all(p.parent().unwrap())?;
         Ok(())
     }
 
@@ -866,14 +1557,547 @@ mod tests {
     #[test]
     fn test_rollback() -> Result<()>{
         rollback(MemoryEngine::new())?;
-        let p = tempfile::tempdir()?.into_path().join("sqldb-log");
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
         rollback(DiskEngine::new(p.clone())?)?;
-        remove_dir_all(p.parent().unwrap())?;
         Ok(())
-    }  
+    }
+
+    fn read_only(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        //未提交的事务对只读事务不可见
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+        tx1.set(b"key2".to_vec(), b"val2".to_vec())?;
+
+        let ro = mvcc.begin_read_only()?;
+        assert!(ro.is_read_only());
+        tx1.commit()?;
+
+        //只读事务开启之后提交的事务也不可见
+        let tx2 = mvcc.begin()?;
+        tx2.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx2.commit()?;
+
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(ro.get(b"key2".to_vec())?, None);
+        assert_eq!(ro.scan_prefix(b"key".to_vec())?.len(), 1);
+        assert_eq!(ro.set(b"key1".to_vec(), b"val".to_vec()), Err(Error::ReadOnly));
+        assert_eq!(ro.delete(b"key1".to_vec()), Err(Error::ReadOnly));
+        ro.commit()?;
+
+        //只读事务不占用版本号
+        let ro1 = mvcc.begin_read_only()?;
+        let tx3 = mvcc.begin()?;
+        assert_eq!(ro1.version(), tx3.version());
+        assert_eq!(ro1.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only() -> Result<()> {
+        read_only(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        read_only(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
+
+    fn serializable(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"1".to_vec())?;
+        tx.commit()?;
+
+        //写偏斜: 两个事务读取对方要修改的 key, 后提交的事务失败
+        let tx1 = mvcc.begin_serializable()?;
+        let tx2 = mvcc.begin_serializable()?;
+        assert_eq!(tx1.get(b"key2".to_vec())?, Some(b"1".to_vec()));
+        tx1.set(b"key1".to_vec(), b"0".to_vec())?;
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"1".to_vec()));
+        tx2.set(b"key2".to_vec(), b"0".to_vec())?;
+        tx1.commit()?;
+        assert_eq!(tx2.commit(), Err(Error::Serialization));
+
+        //失败的事务已经回滚
+        let tx3 = mvcc.begin()?;
+        assert_eq!(tx3.get(b"key1".to_vec())?, Some(b"0".to_vec()));
+        assert_eq!(tx3.get(b"key2".to_vec())?, Some(b"1".to_vec()));
+        tx3.commit()?;
+
+        //快照隔离下允许写偏斜
+        let tx4 = mvcc.begin()?;
+        let tx5 = mvcc.begin()?;
+        tx4.get(b"key2".to_vec())?;
+        tx4.set(b"key1".to_vec(), b"1".to_vec())?;
+        tx5.get(b"key1".to_vec())?;
+        tx5.set(b"key2".to_vec(), b"0".to_vec())?;
+        tx4.commit()?;
+        tx5.commit()?;
+
+        //范围读取同样会被检查, 包括并发事务新插入的 key
+        let tx6 = mvcc.begin_serializable()?;
+        let tx7 = mvcc.begin()?;
+        assert_eq!(tx6.scan_prefix(b"key".to_vec())?.len(), 2);
+        tx6.set(b"sum".to_vec(), b"2".to_vec())?;
+        tx7.set(b"key3".to_vec(), b"1".to_vec())?;
+        tx7.commit()?;
+        assert_eq!(tx6.commit(), Err(Error::Serialization));
+
+        //并发事务回滚, 或者修改的数据没有被读取时可以提交
+        let tx8 = mvcc.begin_serializable()?;
+        let tx9 = mvcc.begin()?;
+        let tx10 = mvcc.begin()?;
+        assert_eq!(tx8.scan_prefix(b"key".to_vec())?.len(), 3);
+        tx8.set(b"sum".to_vec(), b"3".to_vec())?;
+        tx9.set(b"key4".to_vec(), b"1".to_vec())?;
+        tx9.rollback()?;
+        tx10.set(b"other".to_vec(), b"1".to_vec())?;
+        tx10.commit()?;
+        tx8.commit()?;
+
+        //并发事务在提交检查时仍然活跃, 由它自己提交时检查
+        let tx11 = mvcc.begin_serializable()?;
+        let tx12 = mvcc.begin_serializable()?;
+        tx11.get(b"sum".to_vec())?;
+        tx11.set(b"key1".to_vec(), b"2".to_vec())?;
+        tx12.get(b"key1".to_vec())?;
+        tx12.set(b"sum".to_vec(), b"4".to_vec())?;
+        tx12.commit()?;
+        assert_eq!(tx11.commit(), Err(Error::Serialization));
+        Ok(())
+    }
+
+    //存储引擎中 Version 数据的个数
+    fn count_versions<E: Engine>(mvcc: &Mvcc<E>) -> Result<usize> {
+        let engine = mvcc.engine.read()?;
+        let prefix = MvccTransaction::<E>::version_prefix(Vec::new())?;
+        Ok(engine.scan_prefix(prefix).count())
+    }
+
+    fn vacuum(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+        tx1.delete(b"key2".to_vec())?;
+
+        //tx2 开启时 tx1 仍然活跃, 即使 tx1 提交了, tx2 仍然需要读取旧版本
+        let tx2 = mvcc.begin()?;
+        tx1.commit()?;
+        let ro = mvcc.begin_read_only()?;
+        assert_eq!(count_versions(&mvcc)?, 5);
+        assert_eq!(mvcc.vacuum()?, 0);
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx2.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        tx2.commit()?;
+
+        //key1 的旧版本以及 key2 的所有版本被删除, 只读事务能看到的版本仍然保留
+        assert_eq!(mvcc.vacuum()?, 3);
+        assert_eq!(count_versions(&mvcc)?, 2);
+        assert_eq!(ro.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(ro.get(b"key2".to_vec())?, None);
+        drop(ro);
+        assert_eq!(mvcc.vacuum()?, 0);
+        let tx3 = mvcc.begin()?;
+        assert_eq!(
+            tx3.scan_prefix(b"key".to_vec())?,
+            vec![
+                ScanResult {
+                    key: b"key1".to_vec(),
+                    value: b"val1-1".to_vec()
+                },
+                ScanResult {
+                    key: b"key3".to_vec(),
+                    value: b"val3".to_vec()
+                },
+            ]
+        );
+
+        //活跃事务自己的写入不会被回收
+        tx3.set(b"key3".to_vec(), b"val3-1".to_vec())?;
+        assert_eq!(mvcc.vacuum()?, 0);
+        tx3.rollback()?;
+        assert_eq!(count_versions(&mvcc)?, 2);
+
+        //只读事务开启之后的修改, 在事务结束之前不会回收旧版本
+        let ro1 = mvcc.begin_read_only()?;
+        let tx4 = mvcc.begin()?;
+        tx4.set(b"key3".to_vec(), b"val3-2".to_vec())?;
+        tx4.commit()?;
+        assert_eq!(mvcc.vacuum()?, 0);
+        assert_eq!(ro1.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+        drop(ro1);
+        assert_eq!(mvcc.vacuum()?, 1);
+        Ok(())
+    }
 
+    #[test]
+    fn test_vacuum() -> Result<()> {
+        vacuum(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        vacuum(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
 
+    #[test]
+    fn test_start_vacuum() -> Result<()> {
+        let mvcc = Mvcc::new(MemoryEngine::new());
+        for i in 0..3 {
+            let tx = mvcc.begin()?;
+            tx.set(b"key".to_vec(), vec![i])?;
+            tx.commit()?;
+        }
+        let handle = mvcc.start_vacuum(std::time::Duration::from_millis(10));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(count_versions(&mvcc)?, 1);
 
+        //Mvcc 释放之后后台线程退出
+        drop(mvcc);
+        handle.join().unwrap();
+        Ok(())
+    }
 
+    fn as_of(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx1.commit()?;
 
+        let tx2 = mvcc.begin()?;
+        tx2.set(b"key1".to_vec(), b"val1-2".to_vec())?;
+        tx2.set(b"key2".to_vec(), b"val2".to_vec())?;
+        let tx3 = mvcc.begin()?;
+        tx2.commit()?;
+        tx3.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx3.commit()?;
+        assert_eq!(mvcc.current_version()?, 4);
+
+        let keys = |version| -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+            let txn = mvcc.begin_as_of(version)?;
+            Ok(txn
+                .scan_prefix(b"key".to_vec())?
+                .into_iter()
+                .map(|it| (it.key, it.value))
+                .collect())
+        };
+        assert_eq!(keys(1)?, vec![]);
+        assert_eq!(keys(2)?, vec![(b"key1".to_vec(), b"val1".to_vec())]);
+        //tx3 开启时 tx2 仍然活跃, 看不到 tx2 的写入
+        assert_eq!(keys(3)?, vec![(b"key1".to_vec(), b"val1".to_vec())]);
+        assert_eq!(keys(4)?.len(), 3);
+        assert!(mvcc.begin_as_of(0).is_err());
+        assert!(mvcc.begin_as_of(5).is_err());
+
+        let txn = mvcc.begin_as_of(2)?;
+        assert_eq!(txn.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(txn.set(b"key1".to_vec(), b"val".to_vec()), Err(Error::ReadOnly));
+
+        //历史版本的事务会阻止垃圾回收, 释放之后早于水位线的版本不能再查询
+        assert_eq!(mvcc.vacuum()?, 0);
+        assert_eq!(txn.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        drop(txn);
+        assert_eq!(mvcc.vacuum()?, 1);
+        assert!(mvcc.begin_as_of(2).is_err());
+        assert!(mvcc.begin_as_of(3).is_err());
+        assert_eq!(keys(4)?.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_of() -> Result<()> {
+        as_of(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        as_of(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
+
+    fn scan(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        //超过一批的 key, 每个 key 有多个版本, 偶数 key 最终被删除
+        let key = |i: u8| vec![b'k', i];
+        for round in 0..3 {
+            let tx = mvcc.begin()?;
+            for i in 0..150 {
+                match round == 2 && i % 2 == 0 {
+                    true => tx.delete(key(i))?,
+                    false => tx.set(key(i), vec![round, i])?,
+                }
+            }
+            tx.commit()?;
+        }
+        let expected: Vec<_> = (0..150)
+            .filter(|i| i % 2 == 1)
+            .map(|i| ScanResult {
+                key: key(i),
+                value: vec![2, i],
+            })
+            .collect();
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(key(200), vec![200])?;
+        let tx2 = mvcc.begin()?;
+        let forward = tx2.scan(..)?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(forward, expected);
+        let mut backward = tx2.scan(..)?.rev().collect::<Result<Vec<_>>>()?;
+        backward.reverse();
+        assert_eq!(backward, expected);
+
+        //正向和反向交替迭代, 两端不会重复
+        let mut iter = tx2.scan_prefix_iter(b"k".to_vec())?;
+        let mut front = Vec::new();
+        let mut back = Vec::new();
+        loop {
+            match (iter.next().transpose()?, iter.next_back().transpose()?) {
+                (Some(a), Some(b)) => {
+                    front.push(a);
+                    back.push(b);
+                }
+                (Some(a), None) => front.push(a),
+                (None, _) => break,
+            }
+        }
+        back.reverse();
+        front.extend(back);
+        assert_eq!(front, expected);
+
+        //范围扫描
+        let range = tx2.scan(key(10)..key(15))?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(range, expected[5..7]);
+        let range = tx2.scan((Bound::Excluded(key(11)), Bound::Included(key(15))))?;
+        assert_eq!(range.collect::<Result<Vec<_>>>()?, expected[6..8]);
+        assert_eq!(tx2.scan(key(15)..key(10))?.count(), 0);
+
+        //迭代过程中不持有锁, 其他事务可以写入, 之后提交的数据不可见
+        let mut iter = tx2.scan(..)?;
+        assert_eq!(iter.next().transpose()?, Some(expected[0].clone()));
+        tx1.commit()?;
+        let tx3 = mvcc.begin()?;
+        tx3.set(key(149), vec![3])?;
+        tx3.commit()?;
+        assert_eq!(iter.collect::<Result<Vec<_>>>()?, expected[1..]);
+
+        let tx4 = mvcc.begin()?;
+        let mut iter = tx4.scan(..)?.rev();
+        assert_eq!(iter.next().transpose()?.map(|it| it.key), Some(key(200)));
+        assert_eq!(iter.next().transpose()?.map(|it| it.value), Some(vec![3]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan() -> Result<()> {
+        scan(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        scan(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
+
+    fn scan_deleted(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        //范围的开头和结尾都有超过一批连续删除的 key
+        let key = |i: u8| vec![b'k', i];
+        let tx = mvcc.begin()?;
+        for i in 0..250 {
+            tx.set(key(i), vec![i])?;
+        }
+        tx.commit()?;
+        let tx = mvcc.begin()?;
+        for i in (0..100).chain(150..250) {
+            tx.delete(key(i))?;
+        }
+        tx.commit()?;
+        let expected: Vec<_> = (100..150)
+            .map(|i| ScanResult {
+                key: key(i),
+                value: vec![i],
+            })
+            .collect();
+
+        let tx = mvcc.begin()?;
+        assert_eq!(tx.scan(..)?.collect::<Result<Vec<_>>>()?, expected);
+        let mut backward = tx.scan(..)?.rev().collect::<Result<Vec<_>>>()?;
+        backward.reverse();
+        assert_eq!(backward, expected);
+
+        //全部被删除的范围
+        assert_eq!(tx.scan(key(0)..key(100))?.count(), 0);
+        assert_eq!(tx.scan(key(150)..key(250))?.rev().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_deleted() -> Result<()> {
+        scan_deleted(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        scan_deleted(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_serializable() -> Result<()> {
+        serializable(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        serializable(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
+
+    fn savepoint(eng: impl Engine) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let mut tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val1-1".to_vec())?;
+        tx1.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx1.savepoint("sp1".to_string())?;
+        tx1.set(b"key1".to_vec(), b"val1-2".to_vec())?;
+        tx1.delete(b"key2".to_vec())?;
+        tx1.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx1.savepoint("sp2".to_string())?;
+        tx1.set(b"key4".to_vec(), b"val4".to_vec())?;
+
+        //回滚到 sp1, sp2 一并失效
+        tx1.rollback_to_savepoint("sp1")?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1-1".to_vec()));
+        assert_eq!(tx1.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(tx1.get(b"key3".to_vec())?, None);
+        assert_eq!(tx1.get(b"key4".to_vec())?, None);
+        assert!(tx1.rollback_to_savepoint("sp2").is_err());
+
+        //保存点在回滚之后仍然可用
+        tx1.set(b"key3".to_vec(), b"val3-1".to_vec())?;
+        tx1.rollback_to_savepoint("sp1")?;
+        assert_eq!(tx1.get(b"key3".to_vec())?, None);
+
+        tx1.release_savepoint("sp1")?;
+        assert!(tx1.rollback_to_savepoint("sp1").is_err());
+        tx1.set(b"key5".to_vec(), b"val5".to_vec())?;
+        tx1.commit()?;
+
+        let tx2 = mvcc.begin()?;
+        assert_eq!(
+            tx2.scan_prefix(b"key".to_vec())?,
+            vec![
+                ScanResult {
+                    key: b"key1".to_vec(),
+                    value: b"val1-1".to_vec()
+                },
+                ScanResult {
+                    key: b"key2".to_vec(),
+                    value: b"val2".to_vec()
+                },
+                ScanResult {
+                    key: b"key5".to_vec(),
+                    value: b"val5".to_vec()
+                },
+            ]
+        );
+
+        //释放内层的保存点之后, 回滚到外层的保存点仍然撤销内层保存点之后的写入
+        let mut tx5 = mvcc.begin()?;
+        tx5.set(b"key1".to_vec(), b"val1-3".to_vec())?;
+        tx5.savepoint("outer".to_string())?;
+        tx5.set(b"key8".to_vec(), b"val8".to_vec())?;
+        tx5.savepoint("inner".to_string())?;
+        tx5.set(b"key1".to_vec(), b"val1-4".to_vec())?;
+        tx5.set(b"key8".to_vec(), b"val8-1".to_vec())?;
+        tx5.set(b"key9".to_vec(), b"val9".to_vec())?;
+        tx5.release_savepoint("inner")?;
+        tx5.delete(b"key2".to_vec())?;
+        tx5.rollback_to_savepoint("outer")?;
+        assert_eq!(tx5.get(b"key1".to_vec())?, Some(b"val1-3".to_vec()));
+        assert_eq!(tx5.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(tx5.get(b"key8".to_vec())?, None);
+        assert_eq!(tx5.get(b"key9".to_vec())?, None);
+        tx5.rollback()?;
+
+        //回滚整个事务时保存点之前写入的数据也会被撤销
+        let mut tx3 = mvcc.begin()?;
+        tx3.set(b"key6".to_vec(), b"val6".to_vec())?;
+        tx3.savepoint("sp".to_string())?;
+        tx3.set(b"key7".to_vec(), b"val7".to_vec())?;
+        tx3.rollback_to_savepoint("sp")?;
+        tx3.rollback()?;
+        let tx4 = mvcc.begin()?;
+        assert_eq!(tx4.get(b"key6".to_vec())?, None);
+        assert_eq!(tx4.get(b"key7".to_vec())?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_savepoint() -> Result<()> {
+        savepoint(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        savepoint(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
+
+    //多个线程同时读取, 并发的写入对已经开启的事务不可见
+    fn concurrent_read(eng: impl Engine + Send + Sync + 'static) -> Result<()> {
+        let mvcc = Mvcc::new(eng);
+        let tx = mvcc.begin()?;
+        for i in 0..100u32 {
+            tx.set(i.to_be_bytes().to_vec(), b"val1".to_vec())?;
+        }
+        tx.commit()?;
+
+        let handles = (0..4)
+            .map(|_| {
+                let mvcc = mvcc.clone();
+                std::thread::spawn(move || -> Result<()> {
+                    for _ in 0..10 {
+                        let tx = mvcc.begin_read_only()?;
+                        let values = tx
+                            .scan_prefix(Vec::new())?
+                            .into_iter()
+                            .map(|it| it.value)
+                            .collect::<Vec<_>>();
+                        assert_eq!(values.len(), 100);
+                        //同一个快照中的数据来自同一个事务
+                        assert!(values.iter().all(|it| *it == values[0]));
+                        assert_eq!(tx.get(50u32.to_be_bytes().to_vec())?, Some(values[0].clone()));
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..10 {
+            let tx = mvcc.begin()?;
+            for i in 0..100u32 {
+                tx.set(i.to_be_bytes().to_vec(), b"val2".to_vec())?;
+            }
+            tx.commit()?;
+        }
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_read() -> Result<()> {
+        concurrent_read(MemoryEngine::new())?;
+        let dir = tempfile::tempdir()?;
+        let p = dir.path().join("sqldb-log");
+        concurrent_read(DiskEngine::new(p.clone())?)?;
+        Ok(())
+    }
 }